@@ -83,6 +83,7 @@ pub async fn exec(enc_opts: EncOpts) {
     let skr = SigningKeyRequest {
         pub_sign_id,
         priv_sign_id,
+        extra_sign_ids: None,
     };
 
     let SigningKeyResponse {
@@ -3,6 +3,9 @@ use pg_core::client::Sealer;
 
 use futures::executor::block_on;
 use futures::io::AllowStdIo;
+use pg_core::ibs::gg::Signer;
+use pg_core::identity::{Attribute, Policy};
+use pg_core::kem::cgw_kv::CGWKV;
 use pg_core::test::TestSetup;
 use rand::{CryptoRng, RngCore};
 use std::io::Cursor;
@@ -48,5 +51,69 @@ fn bench(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench);
+// Compares the derivation of the 64-byte state to the derivation of a KEM identity, the difference
+// is the cost of mapping the state to an identity.
+fn bench_derive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("throughput-derive");
+
+    for n in [1, 4, 16] {
+        let policy = Policy {
+            timestamp: 1566722350,
+            con: (0..n)
+                .map(|i| {
+                    Attribute::new(
+                        &format!("pbdf.sidn-pbdf.email.email{i}"),
+                        Some("bob@example.com"),
+                    )
+                })
+                .collect(),
+        };
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(format!("derive {n} attributes"), |b| {
+            b.iter(|| policy.derive().unwrap())
+        });
+        group.bench_function(format!("derive_kem {n} attributes"), |b| {
+            b.iter(|| policy.derive_kem::<CGWKV>().unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+// Every segment is signed by a clone of the running signer, chained with the counter and a flag.
+// The signer only holds the state of a hash function, so its clone costs the same regardless of
+// how much payload it has processed. Compares this clone to the signature it precedes.
+fn bench_segment_signer(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let setup = TestSetup::new(&mut rng);
+    let signing_key = &setup.signing_keys[0];
+
+    let mut group = c.benchmark_group("segment-signer");
+
+    for blen in [10, 20] {
+        let signer = Signer::default().chain(&rand_vec(1 << blen));
+        let counter = 1u32;
+
+        group.bench_function(format!("clone after {} KiB", (1 << blen) / 1024), |b| {
+            b.iter(|| black_box(signer.clone().chain(&counter.to_be_bytes()).chain(&[0x00])))
+        });
+        group.bench_function(
+            format!("clone and sign after {} KiB", (1 << blen) / 1024),
+            |b| {
+                b.iter(|| {
+                    signer
+                        .clone()
+                        .chain(&counter.to_be_bytes())
+                        .chain(&[0x00])
+                        .sign(&signing_key.key.0, &mut rng)
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench, bench_derive, bench_segment_signer);
 criterion_main!(benches);
@@ -1,9 +1,18 @@
 //! Definitions of the PostGuard protocol REST API.
 
-use crate::{artifacts::SigningKeyExt, identity::Attribute};
+use crate::artifacts::{SigningKey, SigningKeyExt, VerifyingKey};
+use crate::error::Error;
+use crate::identity::Attribute;
+use alloc::string::String;
 use alloc::vec::Vec;
+use ibs::gg::{Identity, Signature, Signer, Verifier};
 use irma::{ProofStatus, SessionStatus};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
+use tiny_keccak::{Hasher, Sha3};
+
+/// Domain separator of the signature over the public parameters.
+const PARAMETERS_DOMAIN: &str = "postguard-parameters";
 
 /// The public parameters of the Private Key Generator (PKG).
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,6 +23,67 @@ pub struct Parameters<T> {
 
     /// The Master Public Key.
     pub public_key: T,
+
+    /// A signature over the parameters by a trust anchor, see [`Parameters::verify`].
+    ///
+    /// PKGs that are not configured with a parameters signing key do not include this field.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub signature: Option<Signature>,
+}
+
+/// The identity for which the signing key of the public parameters is issued.
+///
+/// The trust anchor is an IBS master key pair that is kept offline. The PKG only holds a signing
+/// key for this identity, extracted using the master secret key of the trust anchor.
+pub fn parameters_identity() -> Identity {
+    let mut h = Sha3::v512();
+    h.update(PARAMETERS_DOMAIN.as_bytes());
+
+    let mut id = [0u8; 64];
+    h.finalize(&mut id);
+
+    Identity::from(&id)
+}
+
+impl<T: Serialize> Parameters<T> {
+    /// The bytes covered by the signature, which excludes the signature itself.
+    fn signed_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(&(
+            PARAMETERS_DOMAIN,
+            self.format_version,
+            &self.public_key,
+        ))?)
+    }
+
+    /// Signs the parameters using a signing key for [`parameters_identity`].
+    pub fn sign<R: RngCore + CryptoRng>(
+        &mut self,
+        key: &SigningKey,
+        rng: &mut R,
+    ) -> Result<(), Error> {
+        let msg = self.signed_bytes()?;
+        self.signature = Some(Signer::new().chain(msg).sign(&key.0, rng));
+
+        Ok(())
+    }
+
+    /// Verifies that the parameters are signed by the trust anchor.
+    ///
+    /// Errors with [`Error::IncorrectSignature`] if the signature is missing or invalid, in
+    /// which case the Master Public Key must not be used.
+    pub fn verify(&self, trust_anchor: &VerifyingKey) -> Result<(), Error> {
+        let sig = self.signature.as_ref().ok_or(Error::IncorrectSignature)?;
+        let msg = self.signed_bytes()?;
+
+        if Verifier::default()
+            .chain(msg)
+            .verify(&trust_anchor.0, sig, &parameters_identity())
+        {
+            Ok(())
+        } else {
+            Err(Error::IncorrectSignature)
+        }
+    }
 }
 
 /// An authentication request for a IRMA identity.
@@ -40,6 +110,19 @@ pub struct KeyResponse<T> {
     /// The key will remain `None` until the status is `Done` and the proof is `Valid`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<T>,
+
+    /// The time (UNIX time) at which the key was issued.
+    ///
+    /// Older PKGs do not include this field.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub issued_at: Option<u64>,
+
+    /// The time (UNIX time) until which the key is considered valid.
+    ///
+    /// Clients can use this to cache keys and refresh them in time.
+    /// Older PKGs do not include this field.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub valid_until: Option<u64>,
 }
 
 /// The request Signing key request body.
@@ -52,6 +135,12 @@ pub struct SigningKeyRequest {
     /// The private signing identity.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priv_sign_id: Option<Vec<Attribute>>,
+
+    /// Additional public signing identities, e.g., for senders with multiple identities.
+    ///
+    /// A key is issued for each conjunction.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub extra_sign_ids: Option<Vec<Vec<Attribute>>>,
 }
 
 /// The signing key response from the Private Key Generator (PKG).
@@ -73,4 +162,82 @@ pub struct SigningKeyResponse {
     /// This private signing key.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priv_sign_key: Option<SigningKeyExt>,
+
+    /// The signing keys for the additional public signing identities, in the same order.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub extra_sign_keys: Option<Vec<SigningKeyExt>>,
+}
+
+/// The response of the Private Key Generator (PKG) to a policy validation request.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyValidationResponse {
+    /// The attribute types in the conjunction that the PKG recognizes.
+    pub recognized: Vec<String>,
+
+    /// The attribute types in the conjunction that the PKG does not recognize.
+    pub unrecognized: Vec<String>,
+
+    /// Whether the PKG would issue a key for the conjunction, once it has been disclosed.
+    pub acceptable: bool,
+}
+
+/// The attribute types that the Private Key Generator (PKG) offers for use in policies.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeTypesResponse {
+    /// The attribute type identifiers, sorted.
+    pub attribute_types: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::PublicKey;
+    use ibe::kem::cgw_kv::CGWKV;
+    use ibe::kem::IBKEM;
+
+    #[test]
+    fn test_parameters_signature() {
+        let mut rng = rand::thread_rng();
+        let (pk, _) = CGWKV::setup(&mut rng);
+        let (anchor_pk, anchor_sk) = ibs::gg::setup(&mut rng);
+        let key = SigningKey(ibs::gg::keygen(
+            &anchor_sk,
+            &parameters_identity(),
+            &mut rng,
+        ));
+
+        let mut params = Parameters {
+            format_version: 0x00,
+            public_key: PublicKey::<CGWKV>(pk),
+            signature: None,
+        };
+
+        // Unsigned parameters are rejected.
+        let anchor = VerifyingKey(anchor_pk);
+        assert!(matches!(
+            params.verify(&anchor),
+            Err(Error::IncorrectSignature)
+        ));
+
+        params.sign(&key, &mut rng).unwrap();
+        params.verify(&anchor).unwrap();
+
+        // The signature survives serialization, and older clients ignore it.
+        let json = serde_json::to_string(&params).unwrap();
+        let parsed: Parameters<PublicKey<CGWKV>> = serde_json::from_str(&json).unwrap();
+        parsed.verify(&anchor).unwrap();
+
+        // A substituted public key or another trust anchor is detected.
+        let (other_pk, _) = CGWKV::setup(&mut rng);
+        let substituted = Parameters {
+            public_key: PublicKey::<CGWKV>(other_pk),
+            ..parsed
+        };
+        assert!(substituted.verify(&anchor).is_err());
+
+        let (other_anchor, _) = ibs::gg::setup(&mut rng);
+        assert!(params.verify(&VerifyingKey(other_anchor)).is_err());
+    }
 }
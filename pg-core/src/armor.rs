@@ -0,0 +1,417 @@
+//! ASCII armor for sealed streams.
+//!
+//! Armored streams can be pasted into text, such as chat messages or email bodies. The sealed
+//! bytes are encoded using base64 in lines of 64 characters, enclosed by a header and footer line
+//! (analogous to PEM):
+//!
+//! ```text
+//! -----BEGIN POSTGUARD MESSAGE-----
+//! UElUR...
+//! -----END POSTGUARD MESSAGE-----
+//! ```
+//!
+//! Since the armor only encodes the bytes, the result of [`unwrap_reader`] can be passed to the
+//! [`Unsealer`][`crate::client::Unsealer`] directly. When dearmoring, line breaks and whitespace
+//! within the base64 are ignored, so text that has been reflowed can still be read.
+
+use alloc::vec::Vec;
+use base64ct::{Base64, Encoding};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures::io::{AsyncRead, Error, ErrorKind, Result};
+use futures::ready;
+
+/// The line preceding the armored stream.
+pub const ARMOR_BEGIN: &str = "-----BEGIN POSTGUARD MESSAGE-----";
+
+/// The line following the armored stream.
+pub const ARMOR_END: &str = "-----END POSTGUARD MESSAGE-----";
+
+/// The number of bytes encoded per line, which results in lines of 64 characters.
+const LINE_BYTES: usize = 48;
+
+/// The maximum length of a line of the armored stream that is accepted when dearmoring.
+///
+/// Longer lines before the header or after the footer are skipped, they are not the armor.
+const MAX_LINE_LEN: usize = 1024;
+
+/// Armors the bytes read from `r`, see [`ArmorReader`].
+pub fn wrap_reader<R: AsyncRead + Unpin>(r: R) -> ArmorReader<R> {
+    let mut out = Vec::with_capacity(ARMOR_BEGIN.len() + 1);
+    out.extend_from_slice(ARMOR_BEGIN.as_bytes());
+    out.push(b'\n');
+
+    ArmorReader {
+        r,
+        buf: [0u8; LINE_BYTES],
+        buf_len: 0,
+        out,
+        out_pos: 0,
+        done: false,
+    }
+}
+
+/// Dearmors the text read from `r`, see [`DearmorReader`].
+pub fn unwrap_reader<R: AsyncRead + Unpin>(r: R) -> DearmorReader<R> {
+    DearmorReader {
+        r,
+        state: DearmorState::Begin,
+        line: Vec::new(),
+        skip_line: false,
+        b64: Vec::new(),
+        out: Vec::new(),
+        out_pos: 0,
+    }
+}
+
+/// Reads the armored text of the bytes read from the inner reader.
+pub struct ArmorReader<R> {
+    r: R,
+    // The bytes of the current line that have not been encoded yet.
+    buf: [u8; LINE_BYTES],
+    buf_len: usize,
+    // Armored text that has not been read yet.
+    out: Vec<u8>,
+    out_pos: usize,
+    done: bool,
+}
+
+impl<R> ArmorReader<R> {
+    /// Encodes the pending bytes as one line.
+    fn encode_line(&mut self) {
+        let mut enc = [0u8; 64];
+        // Cannot fail, the buffer fits a full line.
+        let line = Base64::encode(&self.buf[..self.buf_len], &mut enc).unwrap();
+
+        self.out.extend_from_slice(line.as_bytes());
+        self.out.push(b'\n');
+        self.buf_len = 0;
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ArmorReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.out_pos < this.out.len() {
+                let n = dst.len().min(this.out.len() - this.out_pos);
+                dst[..n].copy_from_slice(&this.out[this.out_pos..this.out_pos + n]);
+                this.out_pos += n;
+
+                return Poll::Ready(Ok(n));
+            }
+
+            if this.done {
+                return Poll::Ready(Ok(0));
+            }
+
+            this.out.clear();
+            this.out_pos = 0;
+
+            let n = ready!(Pin::new(&mut this.r).poll_read(cx, &mut this.buf[this.buf_len..]))?;
+            this.buf_len += n;
+
+            if n == 0 {
+                if this.buf_len > 0 {
+                    this.encode_line();
+                }
+
+                this.out.extend_from_slice(ARMOR_END.as_bytes());
+                this.out.push(b'\n');
+                this.done = true;
+            } else if this.buf_len == LINE_BYTES {
+                this.encode_line();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DearmorState {
+    /// Looking for the header line.
+    Begin,
+    /// Decoding the base64 lines.
+    Body,
+    /// The footer line has been read.
+    Done,
+}
+
+/// Reads the bytes of the armored text read from the inner reader.
+///
+/// Reading fails with [`ErrorKind::InvalidData`] if the header or footer is missing, or if the
+/// text is not valid base64. Any text before the header is ignored, as is any text after the
+/// footer.
+pub struct DearmorReader<R> {
+    r: R,
+    state: DearmorState,
+    // The current line.
+    line: Vec<u8>,
+    // Whether the rest of the current line is skipped, since it is too long to be the header.
+    skip_line: bool,
+    // Base64 characters that have not been decoded yet.
+    b64: Vec<u8>,
+    // Decoded bytes that have not been read yet.
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn trim(mut line: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = line {
+        if !first.is_ascii_whitespace() {
+            break;
+        }
+        line = rest;
+    }
+
+    while let [rest @ .., last] = line {
+        if !last.is_ascii_whitespace() {
+            break;
+        }
+        line = rest;
+    }
+
+    line
+}
+
+impl<R> DearmorReader<R> {
+    /// Decodes the pending base64 characters, up to a multiple of four.
+    fn decode(&mut self, all: bool) -> Result<()> {
+        let len = if all {
+            self.b64.len()
+        } else {
+            self.b64.len() - self.b64.len() % 4
+        };
+
+        let mut dec = vec![0u8; len / 4 * 3];
+        let decoded = Base64::decode(&self.b64[..len], &mut dec)
+            .map_err(|_e| invalid_data("armor contains invalid base64"))?;
+
+        self.out.extend_from_slice(decoded);
+        self.b64.drain(..len);
+
+        Ok(())
+    }
+
+    /// Processes a complete line.
+    fn process_line(&mut self) -> Result<()> {
+        let line = core::mem::take(&mut self.line);
+        let line = trim(&line);
+
+        match self.state {
+            DearmorState::Begin if line == ARMOR_BEGIN.as_bytes() => {
+                self.state = DearmorState::Body;
+            }
+            DearmorState::Begin => (),
+            DearmorState::Body if line == ARMOR_END.as_bytes() => {
+                self.decode(true)?;
+                self.state = DearmorState::Done;
+            }
+            DearmorState::Body => {
+                self.b64
+                    .extend(line.iter().filter(|b| !b.is_ascii_whitespace()));
+                self.decode(false)?;
+            }
+            DearmorState::Done => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DearmorReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let mut tmp = [0u8; 1024];
+
+        loop {
+            if this.out_pos < this.out.len() {
+                let n = dst.len().min(this.out.len() - this.out_pos);
+                dst[..n].copy_from_slice(&this.out[this.out_pos..this.out_pos + n]);
+                this.out_pos += n;
+
+                return Poll::Ready(Ok(n));
+            }
+
+            if this.state == DearmorState::Done {
+                return Poll::Ready(Ok(0));
+            }
+
+            this.out.clear();
+            this.out_pos = 0;
+
+            let n = ready!(Pin::new(&mut this.r).poll_read(cx, &mut tmp))?;
+
+            if n == 0 {
+                // The final line might not be terminated.
+                this.process_line()?;
+
+                match this.state {
+                    DearmorState::Begin => {
+                        return Poll::Ready(Err(invalid_data("armor header is missing")))
+                    }
+                    DearmorState::Body => {
+                        return Poll::Ready(Err(invalid_data("armor footer is missing")))
+                    }
+                    DearmorState::Done => continue,
+                }
+            }
+
+            for &b in &tmp[..n] {
+                if b == b'\n' {
+                    if this.skip_line {
+                        this.skip_line = false;
+                    } else {
+                        this.process_line()?;
+                    }
+                } else if this.skip_line || this.state == DearmorState::Done {
+                    // Text after the footer is ignored.
+                } else if this.line.len() < MAX_LINE_LEN {
+                    this.line.push(b);
+                } else if this.state == DearmorState::Body {
+                    return Poll::Ready(Err(invalid_data("armor line is too long")));
+                } else {
+                    this.line.clear();
+                    this.skip_line = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::rust::stream::{SealerStreamConfig, UnsealerStreamConfig};
+    use crate::client::{Sealer, Unsealer};
+    use crate::test::TestSetup;
+    use crate::PRELUDE;
+    use alloc::string::String;
+    use futures::executor::block_on;
+    use futures::io::{AllowStdIo, AsyncReadExt};
+    use rand::RngCore;
+    use std::io::Cursor;
+
+    fn armor(bytes: &[u8]) -> String {
+        let mut text = String::new();
+        block_on(wrap_reader(AllowStdIo::new(Cursor::new(bytes))).read_to_string(&mut text))
+            .unwrap();
+
+        text
+    }
+
+    fn dearmor(text: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        block_on(unwrap_reader(AllowStdIo::new(Cursor::new(text))).read_to_end(&mut bytes))?;
+
+        Ok(bytes)
+    }
+
+    #[test]
+    fn test_armor_seal_unseal() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let mut plain = vec![0u8; 1000];
+        rng.fill_bytes(&mut plain);
+
+        let mut sealed = Vec::new();
+        block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut sealed),
+        )
+        .unwrap();
+
+        let text = armor(&sealed);
+        assert!(text.starts_with(ARMOR_BEGIN));
+        assert!(text.trim_end().ends_with(ARMOR_END));
+        assert!(text.lines().all(|line| line.len() <= 64));
+
+        // The framing survives a round trip.
+        let dearmored = dearmor(&text).unwrap();
+        assert_eq!(&dearmored[..PRELUDE.len()], &PRELUDE);
+        assert_eq!(dearmored, sealed);
+
+        // The unsealer reads the armored text transparently.
+        let mut output = Vec::new();
+        block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(
+                unwrap_reader(AllowStdIo::new(Cursor::new(&text))),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap()
+            .unseal("Bob", &setup.usks[2], &mut output)
+            .await
+            .unwrap();
+        });
+        assert_eq!(output, plain);
+    }
+
+    #[test]
+    fn test_armor_reflowed() {
+        for len in [0, 1, 2, 3, 47, 48, 49, 100] {
+            let bytes: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let text = armor(&bytes);
+
+            // Surrounding text, other line breaks and CRLF are accepted.
+            let body: String = text
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect();
+            let reflowed = format!(
+                "Hi Bob,\r\n\r\n{ARMOR_BEGIN}\r\n{}\r\n{ARMOR_END}",
+                body.as_bytes()
+                    .chunks(20)
+                    .map(|c| core::str::from_utf8(c).unwrap())
+                    .collect::<Vec<_>>()
+                    .join("\r\n  ")
+            );
+
+            assert_eq!(dearmor(&text).unwrap(), bytes);
+            assert_eq!(dearmor(&reflowed).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_armor_invalid() {
+        let text = armor(b"some sealed bytes");
+
+        assert!(dearmor("no armor here").is_err());
+        assert!(dearmor(&text.replace(ARMOR_END, "")).is_err());
+        assert!(dearmor(&text.replace("c29", "c2!")).is_err());
+
+        let long = "x".repeat(MAX_LINE_LEN + 1);
+        let body = format!("{ARMOR_BEGIN}\n{long}\n{ARMOR_END}");
+        assert!(dearmor(&body).is_err());
+    }
+
+    #[test]
+    fn test_armor_long_surrounding_lines() {
+        let bytes = b"some sealed bytes";
+        let text = armor(bytes);
+
+        // Unwrapped paragraphs around the armor are not part of it.
+        let long = "lorem ipsum ".repeat(MAX_LINE_LEN / 4);
+        let email = format!("{long}\n\n{text}\n{long}\n");
+        assert_eq!(dearmor(&email).unwrap(), bytes);
+    }
+}
@@ -17,6 +17,7 @@ use ibe::{
 };
 use serde::de::{Error, SeqAccess, Visitor};
 use serde::{ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
 
 // Computes the byte length of raw bytes encoded in (padded) b64.
 // We use this to preallocate a buffer to encode into.
@@ -116,6 +117,9 @@ pub struct PublicKey<K: IBKEM>(pub K::Pk);
 pub struct SecretKey<K: IBKEM>(pub K::Sk);
 
 /// User secret keys.
+///
+/// The compressed form of the key is wiped after (de)serialization. The key itself consists of
+/// group elements of the scheme, which do not implement [`zeroize::Zeroize`].
 #[derive(Debug, Clone)]
 pub struct UserSecretKey<K: IBKEM>(pub K::Usk);
 
@@ -134,7 +138,29 @@ pub struct MultiRecipientCiphertext<K: IBKEM>(pub MkemCt<K>);
 // each scheme specifically.
 
 /// Implements [`serde::ser::Serialize`] and [`serde::de::Deserialize`] for encapsulation wrapper types.
+///
+/// Secret types are (de)serialized via a buffer that is wiped afterwards.
 macro_rules! impl_serialize {
+    (secret $type: ty, $inner: ty) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_bin_or_b64(&*Zeroizing::new(self.0.to_bytes()), serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let mut buf = Zeroizing::new([0u8; <$inner as Compress>::OUTPUT_SIZE]);
+                deserialize_bin_or_b64(&mut buf[..], deserializer)?;
+
+                let artifact = open_ct(<$inner as Compress>::from_bytes(&buf)).ok_or(
+                    serde::de::Error::custom(format!("not a valid {}", stringify!($type))),
+                )?;
+
+                Ok(Self(artifact))
+            }
+        }
+    };
     ($type: ty, $inner: ty) => {
         impl Serialize for $type {
             fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -158,8 +184,8 @@ macro_rules! impl_serialize {
 }
 
 impl_serialize!(PublicKey<CGWKV>, <CGWKV as IBKEM>::Pk);
-impl_serialize!(SecretKey<CGWKV>, <CGWKV as IBKEM>::Sk);
-impl_serialize!(UserSecretKey<CGWKV>, <CGWKV as IBKEM>::Usk);
+impl_serialize!(secret SecretKey<CGWKV>, <CGWKV as IBKEM>::Sk);
+impl_serialize!(secret UserSecretKey<CGWKV>, <CGWKV as IBKEM>::Usk);
 impl_serialize!(Ciphertext<CGWKV>, <CGWKV as IBKEM>::Ct);
 impl_serialize!(MultiRecipientCiphertext<CGWKV>, MkemCt<CGWKV>);
 
@@ -177,6 +203,8 @@ pub struct SigningKeyExt {
 }
 
 /// Identity-based signing keys.
+///
+/// The serialized form of the key is wiped after (de)serialization.
 #[derive(Debug, Clone)]
 pub struct SigningKey(pub ibs::gg::UserSecretKey);
 
@@ -185,22 +213,22 @@ impl Serialize for SigningKey {
     where
         S: Serializer,
     {
-        let bytes = bincode::serialize(&self.0).map_err(|e| {
+        let bytes = Zeroizing::new(bincode::serialize(&self.0).map_err(|e| {
             serde::ser::Error::custom(format!("could not serialize signing key: {e}"))
-        })?;
+        })?);
 
         debug_assert_eq!(bytes.len(), ibs::gg::USK_BYTES);
 
-        serialize_bin_or_b64(&bytes, serializer)
+        serialize_bin_or_b64(&*bytes, serializer)
     }
 }
 
 impl<'de> Deserialize<'de> for SigningKey {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let mut buf = [0u8; ibs::gg::USK_BYTES];
-        deserialize_bin_or_b64(&mut buf, deserializer)?;
+        let mut buf = Zeroizing::new([0u8; ibs::gg::USK_BYTES]);
+        deserialize_bin_or_b64(&mut buf[..], deserializer)?;
 
-        let usk = bincode::deserialize(&buf).map_err(|e| {
+        let usk = bincode::deserialize(&buf[..]).map_err(|e| {
             serde::de::Error::custom(format!("could not deserialize signing key: {e}"))
         })?;
 
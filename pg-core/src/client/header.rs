@@ -1,16 +1,17 @@
 //! PostGuard header definitions.
 
 use crate::artifacts::{deserialize_bin_or_b64, serialize_bin_or_b64};
-use crate::artifacts::{MultiRecipientCiphertext, PublicKey, UserSecretKey};
+use crate::artifacts::{MultiRecipientCiphertext, PublicKey, UserSecretKey, VerifyingKey};
 use crate::consts::*;
 use crate::error::Error;
-use crate::identity::{EncryptionPolicy, HiddenPolicy, Policy};
+use crate::identity::{DeriveFromState, EncryptionPolicy, HiddenPolicy, Policy};
+use crate::util::preamble_checked;
 
 use ibe::kem::cgw_kv::CGWKV;
 use ibe::kem::mkem::MultiRecipient;
 use ibe::kem::{SharedSecret, IBKEM};
 
-use ibs::gg::Signature;
+use ibs::gg::{Signature, Verifier};
 
 use alloc::collections::BTreeMap;
 use alloc::fmt::Debug;
@@ -18,6 +19,7 @@ use alloc::string::String;
 use alloc::vec::Vec;
 
 use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 /// Possible encryption modes.
@@ -50,6 +52,49 @@ impl Default for Mode {
     }
 }
 
+/// Possible compression codecs, applied to the plaintext before sealing.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Compression {
+    /// The plaintext is not compressed.
+    None,
+
+    /// The plaintext is compressed using DEFLATE (RFC 1951).
+    Deflate,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+/// How the payload of a stream is signed.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SignatureMode {
+    /// Every segment is signed, so every segment is verified before it is output.
+    PerSegment,
+
+    /// Only the final segment is signed, which covers the whole payload.
+    ///
+    /// This saves a signature per segment, but the output can only be trusted once the final
+    /// segment has been verified, i.e., once unsealing has succeeded.
+    Detached,
+
+    /// Neither the header nor the segments are signed.
+    ///
+    /// The payload is only encrypted, the sender is not authenticated. The segments are still
+    /// bound to the header, see [`Header::bind_header`]. Their order is authenticated by their
+    /// counters, and a truncated stream is detected because only the final segment is shorter
+    /// than a full one.
+    Unsigned,
+}
+
+impl Default for SignatureMode {
+    fn default() -> Self {
+        SignatureMode::PerSegment
+    }
+}
+
 /// An initialization vector (IV).
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub struct Iv<const N: usize>(pub [u8; N]);
@@ -99,11 +144,42 @@ impl Algorithm {
     }
 }
 
+/// Identifiers of the supported identity-based KEMs.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum KemScheme {
+    /// The CGW-KV scheme.
+    CGWKV,
+}
+
+impl Default for KemScheme {
+    fn default() -> Self {
+        KemScheme::CGWKV
+    }
+}
+
+/// An identity-based KEM that can be used to seal and unseal.
+///
+/// This trait is sealed, you cannot implement it yourself.
+pub trait Scheme: IBKEM + MultiRecipient + DeriveFromState + crate::client::sealed::Scheme {
+    /// The identifier of this scheme, as recorded in the header.
+    const ID: KemScheme;
+}
+
+impl crate::client::sealed::Scheme for CGWKV {}
+
+impl Scheme for CGWKV {
+    const ID: KemScheme = KemScheme::CGWKV;
+}
+
 /// A header contains header data for _all_ recipients.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Header {
+#[serde(bound(
+    serialize = "RecipientHeader<K>: Serialize",
+    deserialize = "RecipientHeader<K>: Deserialize<'de>"
+))]
+pub struct Header<K: IBKEM = CGWKV> {
     /// Map of recipient identifiers to [`RecipientHeader`]s.
-    pub recipients: BTreeMap<String, RecipientHeader>,
+    pub recipients: BTreeMap<String, RecipientHeader<K>>,
 
     /// The symmetric-key encryption algorithm used.
     pub algo: Algorithm,
@@ -111,45 +187,126 @@ pub struct Header {
     /// The encryption mode.
     #[serde(default)]
     pub mode: Mode,
+
+    /// The compression codec applied to the plaintext before sealing.
+    ///
+    /// Since the header is signed, this cannot be altered to downgrade the compression.
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// The identity-based KEM used to encapsulate the shared secret.
+    #[serde(default)]
+    pub kem: KemScheme,
+
+    /// How the payload of a stream is signed.
+    #[serde(default)]
+    pub signature_mode: SignatureMode,
+
+    /// Whether the segments of a stream are bound to this header.
+    ///
+    /// If set, a hash of the serialized header is absorbed into the DEM as associated data, such
+    /// that segments cannot be spliced into a stream with another header.
+    #[serde(default)]
+    pub bind_header: bool,
+
+    /// The signature scheme that protects this header.
+    ///
+    /// An unsealer selects the verifier of the header signature accordingly.
+    #[serde(default)]
+    pub sig_scheme: SigScheme,
+
+    /// Public metadata, readable by anyone before decryption.
+    ///
+    /// The metadata is part of the header and therefore covered by the header signature.
+    #[serde(default)]
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// Identifiers of the signature schemes that protect the header.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(from = "u8", into = "u8")]
+pub enum SigScheme {
+    /// The Galindo-Garcia identity-based signature scheme.
+    GG,
+
+    /// A scheme that is not supported by this version.
+    Unknown(u8),
+}
+
+impl Default for SigScheme {
+    fn default() -> Self {
+        SigScheme::GG
+    }
+}
+
+impl From<u8> for SigScheme {
+    fn from(tag: u8) -> Self {
+        match tag {
+            0 => SigScheme::GG,
+            tag => SigScheme::Unknown(tag),
+        }
+    }
+}
+
+impl From<SigScheme> for u8 {
+    fn from(scheme: SigScheme) -> Self {
+        match scheme {
+            SigScheme::GG => 0,
+            SigScheme::Unknown(tag) => tag,
+        }
+    }
 }
 
 /// Contains header data specific to _one_ recipient.
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct RecipientHeader {
+#[serde(bound(
+    serialize = "MultiRecipientCiphertext<K>: Serialize",
+    deserialize = "MultiRecipientCiphertext<K>: Deserialize<'de>"
+))]
+pub struct RecipientHeader<K: IBKEM = CGWKV> {
     /// The [`HiddenPolicy`] associated with this identifier.
     pub policy: HiddenPolicy,
 
     /// Ciphertext for this specific recipient.
-    pub ct: MultiRecipientCiphertext<CGWKV>,
+    pub ct: MultiRecipientCiphertext<K>,
 }
 
-impl RecipientHeader {
+impl<K: Scheme> RecipientHeader<K> {
     /// Decapsulates a [`ibe::kem::SharedSecret`] from a [`RecipientHeader`].
     ///
     /// These bytes can either directly be used for an AEAD, or a key derivation function.
-    pub fn decaps(&self, usk: &UserSecretKey<CGWKV>) -> Result<SharedSecret, Error> {
-        CGWKV::multi_decaps(None, &usk.0, &self.ct.0).map_err(|_e| Error::KEM)
+    ///
+    /// Errors with [`Error::WrongUserSecretKey`] if the key was issued for another identity.
+    pub fn decaps(&self, usk: &UserSecretKey<K>) -> Result<SharedSecret, Error> {
+        K::multi_decaps(None, &usk.0, &self.ct.0).map_err(|_e| Error::WrongUserSecretKey)
     }
 }
 
-impl Header {
+impl<K: Scheme> Header<K> {
     /// Creates a new [`Header`] using the Master Public Key and the policies.
+    ///
+    /// Errors with [`Error::ConstraintViolation`] if there are no recipients, since nobody could
+    /// unseal the payload.
     pub fn new<R: RngCore + CryptoRng>(
-        pk: &PublicKey<CGWKV>,
+        pk: &PublicKey<K>,
         policies: &EncryptionPolicy,
         rng: &mut R,
     ) -> Result<(Self, SharedSecret), Error> {
+        if policies.is_empty() {
+            return Err(Error::ConstraintViolation);
+        }
+
         // Map each RecipientPolicy to an IBE identity.
         let ids = policies
             .values()
-            .map(Policy::derive_kem::<CGWKV>)
-            .collect::<Result<Vec<<CGWKV as IBKEM>::Id>, _>>()?;
+            .map(Policy::derive_kem::<K>)
+            .collect::<Result<Vec<<K as IBKEM>::Id>, _>>()?;
 
         // Generate the shared secret and ciphertexts.
-        let (cts, ss) = CGWKV::multi_encaps(&pk.0, &ids[..], rng);
+        let (cts, ss) = K::multi_encaps(&pk.0, &ids[..], rng);
 
         // Generate all RecipientHeaders.
-        let recipient_info: BTreeMap<String, RecipientHeader> = policies
+        let recipient_info: BTreeMap<String, RecipientHeader<K>> = policies
             .iter()
             .zip(cts)
             .map(|((rid, policy), ct)| {
@@ -168,11 +325,70 @@ impl Header {
                 recipients: recipient_info,
                 algo: Algorithm::new_aes128_gcm(rng),
                 mode: Mode::default(),
+                compression: Compression::default(),
+                kem: K::ID,
+                signature_mode: SignatureMode::default(),
+                bind_header: false,
+                sig_scheme: SigScheme::default(),
+                metadata: None,
             },
             ss,
         ))
     }
 
+    /// Parses a header from the start of a sealed bytestream.
+    ///
+    /// Checks the preamble, i.e., the prelude, the version and the length of the header, and
+    /// returns the header along with the number of bytes consumed. The signature that follows the
+    /// header is not verified, use an `Unsealer` to do so.
+    pub fn parse(bytes: &[u8]) -> Result<(Self, usize), Error>
+    where
+        Self: DeserializeOwned,
+        RecipientHeader<K>: DeserializeOwned,
+    {
+        if bytes.len() < PREAMBLE_SIZE {
+            return Err(Error::HeaderTooShort);
+        }
+
+        let (version, header_len) = preamble_checked(&bytes[..PREAMBLE_SIZE])?;
+        let header_bytes = bytes
+            .get(PREAMBLE_SIZE..PREAMBLE_SIZE + header_len)
+            .ok_or(Error::HeaderTooShort)?;
+
+        let header = header_decode::<K>(version, header_bytes)
+            .map_err(Error::HeaderDeserialize)?
+            .scheme_checked()?;
+
+        Ok((header, PREAMBLE_SIZE + header_len))
+    }
+
+    /// Checks that the header was created using the scheme `K`.
+    pub fn scheme_checked(self) -> Result<Self, Error> {
+        if self.kem != K::ID {
+            return Err(Error::IncorrectSchemeVersion);
+        }
+
+        Ok(self)
+    }
+
+    /// Decapsulates the shared secret of a single recipient.
+    ///
+    /// Every recipient must recover the same shared secret, from which the symmetric key is
+    /// derived. Only meant for testing and conformance checks.
+    #[cfg(feature = "test-utils")]
+    pub fn recipient_shared_secret(
+        &self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+    ) -> Result<SharedSecret, Error> {
+        self.recipients
+            .get(ident)
+            .ok_or_else(|| Error::UnknownIdentifier(ident.into()))?
+            .decaps(usk)
+    }
+}
+
+impl<K: IBKEM> Header<K> {
     /// Set the encryption mode.
     pub fn with_mode(mut self, mode: Mode) -> Self {
         self.mode = mode;
@@ -184,6 +400,174 @@ impl Header {
         self.algo = algo;
         self
     }
+
+    /// Set the compression codec.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the signature mode.
+    pub fn with_signature_mode(mut self, signature_mode: SignatureMode) -> Self {
+        self.signature_mode = signature_mode;
+        self
+    }
+
+    /// Set whether the segments of a stream are bound to this header.
+    pub fn with_bind_header(mut self, bind_header: bool) -> Self {
+        self.bind_header = bind_header;
+        self
+    }
+
+    /// Set the public metadata.
+    pub fn with_metadata(mut self, metadata: Vec<u8>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Checks whether two headers are equivalent, apart from their randomness.
+    ///
+    /// Compares the recipient identifiers and their hidden policies, the algorithm, the modes,
+    /// the compression and the KEM. The IV and the KEM ciphertexts, which are freshly generated
+    /// for every seal, are ignored.
+    pub fn structurally_eq(&self, other: &Self) -> bool {
+        self.recipients.len() == other.recipients.len()
+            && self
+                .recipients
+                .iter()
+                .zip(other.recipients.iter())
+                .all(|((id1, r1), (id2, r2))| id1 == id2 && r1.policy == r2.policy)
+            && core::mem::discriminant(&self.algo) == core::mem::discriminant(&other.algo)
+            && self.mode == other.mode
+            && self.compression == other.compression
+            && self.kem == other.kem
+            && self.signature_mode == other.signature_mode
+            && self.bind_header == other.bind_header
+            && self.sig_scheme == other.sig_scheme
+            && self.metadata == other.metadata
+    }
+}
+
+impl<K: Scheme> TryFrom<&[u8]> for Header<K>
+where
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    type Error = Error;
+
+    /// Parses a header from the start of a sealed bytestream, see [`Header::parse`].
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Header::parse(bytes).map(|(header, _)| header)
+    }
+}
+
+/// The header as it was serialized in [`VERSION_V3`], before it was extended.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "RecipientHeader<K>: Deserialize<'de>"))]
+struct HeaderV3<K: IBKEM> {
+    recipients: BTreeMap<String, RecipientHeader<K>>,
+    algo: Algorithm,
+    #[serde(default)]
+    mode: Mode,
+}
+
+impl<K: IBKEM> From<HeaderV3<K>> for Header<K> {
+    fn from(header: HeaderV3<K>) -> Self {
+        // The defaults are exactly what was implied before these fields existed.
+        Header {
+            recipients: header.recipients,
+            algo: header.algo,
+            mode: header.mode,
+            compression: Compression::None,
+            kem: KemScheme::CGWKV,
+            signature_mode: SignatureMode::PerSegment,
+            bind_header: false,
+            sig_scheme: SigScheme::GG,
+            metadata: None,
+        }
+    }
+}
+
+/// Deserializes a raw header in the layout of `version`, as checked by the preamble.
+pub(crate) fn header_decode<K: IBKEM>(
+    version: u16,
+    header_raw: &[u8],
+) -> Result<Header<K>, bincode::Error>
+where
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    if version == VERSION_V3 {
+        return bincode::deserialize::<HeaderV3<K>>(header_raw).map(Header::from);
+    }
+
+    bincode::deserialize::<Header<K>>(header_raw)
+}
+
+/// Parses a raw header and verifies its signature, using the signature scheme that it declares.
+///
+/// A header that cannot be parsed is verified using the default scheme, such that tampering is
+/// reported as [`Error::IncorrectSignature`]. Unknown schemes yield [`Error::UnsupportedVersion`].
+pub(crate) fn header_verified<K: IBKEM>(
+    version: u16,
+    header_raw: &[u8],
+    h_sig_ext: &SignatureExt,
+    vk: &VerifyingKey,
+) -> Result<Header<K>, Error>
+where
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    h_sig_ext.pol.validate()?;
+
+    let header = header_decode::<K>(version, header_raw);
+    let sig_scheme = header
+        .as_ref()
+        .map_or(SigScheme::default(), |header| header.sig_scheme);
+
+    match sig_scheme {
+        SigScheme::GG => {
+            let id = h_sig_ext.pol.derive_ibs()?;
+
+            if !Verifier::default()
+                .chain(header_raw)
+                .verify(&vk.0, &h_sig_ext.sig, &id)
+            {
+                return Err(Error::IncorrectSignature);
+            }
+        }
+        SigScheme::Unknown(tag) => return Err(Error::UnsupportedVersion(tag.into())),
+    }
+
+    let header = header.map_err(Error::HeaderDeserialize)?;
+    for recipient in header.recipients.values() {
+        recipient.policy.validate()?;
+    }
+
+    Ok(header)
+}
+
+/// Parses a raw header that carries no signature, see [`SignatureMode::Unsigned`].
+///
+/// Only headers that declare themselves unsigned are accepted, such that a signed header cannot
+/// be passed off as an unsigned one by stripping its signature.
+#[cfg(all(feature = "rust", feature = "stream"))]
+pub(crate) fn header_unsigned<K: IBKEM>(version: u16, header_raw: &[u8]) -> Result<Header<K>, Error>
+where
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    let header = header_decode::<K>(version, header_raw).map_err(|_e| Error::IncorrectSignature)?;
+
+    if header.signature_mode != SignatureMode::Unsigned || !header.bind_header {
+        return Err(Error::IncorrectSignature);
+    }
+
+    for recipient in header.recipients.values() {
+        recipient.policy.validate()?;
+    }
+
+    Ok(header)
 }
 
 /// An IBS signature, extended with the identity claims.
@@ -201,6 +585,125 @@ mod tests {
     use super::*;
     use crate::test::TestSetup;
 
+    #[test]
+    fn test_structurally_eq() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let (h1, _ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let (h2, _ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+
+        // Two seals of the same policies only differ in their randomness.
+        assert_ne!(h1.algo, h2.algo);
+        assert!(h1.structurally_eq(&h2));
+        assert!(h2.structurally_eq(&h1));
+
+        let h3 = h2.clone().with_signature_mode(SignatureMode::Detached);
+        assert!(!h1.structurally_eq(&h3));
+
+        let h4 = h2.clone().with_mode(Mode::InMemory { size: 1024 });
+        assert!(!h1.structurally_eq(&h4));
+
+        let mut h5 = h2.clone();
+        h5.recipients.remove("Bob");
+        assert!(!h1.structurally_eq(&h5));
+
+        let mut h6 = h2;
+        h6.recipients.get_mut("Bob").unwrap().policy.timestamp += 1;
+        assert!(!h1.structurally_eq(&h6));
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        assert!(matches!(
+            Header::new(&setup.ibe_pk, &EncryptionPolicy::new(), &mut rng),
+            Err(Error::ConstraintViolation)
+        ));
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let (header, _ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let header_buf = bincode::serialize(&header).unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&PRELUDE);
+        bytes.extend_from_slice(&VERSION_V4.to_be_bytes());
+        bytes.extend_from_slice(&(header_buf.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&header_buf);
+        let header_end = bytes.len();
+
+        // The header is followed by its signature and the payload.
+        bytes.extend_from_slice(&[0xff; 64]);
+
+        let (parsed, consumed): (Header, usize) = Header::parse(&bytes).unwrap();
+        assert_eq!(consumed, header_end);
+        assert!(parsed.structurally_eq(&header));
+        assert_eq!(parsed.algo, header.algo);
+
+        let parsed = Header::<CGWKV>::try_from(&bytes[..]).unwrap();
+        assert!(parsed.structurally_eq(&header));
+
+        assert!(matches!(
+            Header::<CGWKV>::parse(&bytes[..header_end - 1]),
+            Err(Error::HeaderTooShort)
+        ));
+        assert!(matches!(
+            Header::<CGWKV>::parse(&bytes[..PREAMBLE_SIZE - 1]),
+            Err(Error::HeaderTooShort)
+        ));
+
+        bytes[0] ^= 1;
+        assert!(matches!(
+            Header::<CGWKV>::parse(&bytes),
+            Err(Error::NotPostGuard)
+        ));
+    }
+
+    #[test]
+    fn test_decode_v3() {
+        // A header without recipients, as serialized by version 2.
+        #[rustfmt::skip]
+        const HEADER_V3: [u8; 41] = [
+            0, 0, 0, 0, 0, 0, 0, 0, // no recipients
+            0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, // AES-128-GCM and its IV
+            0, 0, 0, 0, 0, 0, 4, 0, // streaming in segments of 256 KiB
+            0, 0, 0, 0, 0, 0, 0, 0, 0, // without a size hint
+        ];
+
+        let header = header_decode::<CGWKV>(VERSION_V3, &HEADER_V3).unwrap();
+        assert!(header.recipients.is_empty());
+        assert_eq!(
+            header.algo,
+            Algorithm::Aes128Gcm(Iv([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]))
+        );
+        assert_eq!(header.mode, Mode::default());
+        assert_eq!(header.compression, Compression::None);
+        assert_eq!(header.kem, KemScheme::CGWKV);
+        assert_eq!(header.signature_mode, SignatureMode::PerSegment);
+        assert!(!header.bind_header);
+        assert_eq!(header.sig_scheme, SigScheme::GG);
+        assert_eq!(header.metadata, None);
+
+        // The fields that were added since cannot be read from it.
+        assert!(header_decode::<CGWKV>(VERSION_V4, &HEADER_V3).is_err());
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&PRELUDE);
+        bytes.extend_from_slice(&VERSION_V3.to_be_bytes());
+        bytes.extend_from_slice(&(HEADER_V3.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&HEADER_V3);
+        let (parsed, consumed) = Header::<CGWKV>::parse(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert!(parsed.structurally_eq(&header));
+    }
+
     #[test]
     fn test_enc_dec_json() {
         let mut rng = rand::thread_rng();
@@ -221,6 +724,8 @@ mod tests {
 
         assert_eq!(&decoded.algo, &header2.algo);
         assert_eq!(&decoded.mode, &header2.mode);
+        assert_eq!(&decoded.compression, &header2.compression);
+        assert_eq!(&decoded.kem, &header2.kem);
     }
 
     #[test]
@@ -241,6 +746,44 @@ mod tests {
         );
         assert_eq!(&decoded.algo, &header2.algo);
         assert_eq!(&decoded.mode, &header2.mode);
+        assert_eq!(&decoded.compression, &header2.compression);
+        assert_eq!(&decoded.kem, &header2.kem);
+    }
+
+    #[test]
+    fn test_enc_dec_binary_attributes() {
+        use crate::identity::{Attribute, AttributeValue};
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        // Attributes with a value, without one, and wildcards all survive the binary encoding.
+        let policy = EncryptionPolicy::from([(
+            String::from("Alice"),
+            Policy {
+                timestamp: 1566722350,
+                con: vec![
+                    Attribute::new("pbdf.gemeente.personalData.fullname", Some("Alice")),
+                    Attribute::new("pbdf.sidn-pbdf.email.email", None),
+                    Attribute::new_typed(
+                        "pbdf.gemeente.personalData.age",
+                        AttributeValue::Wildcard,
+                    ),
+                ],
+            },
+        )]);
+
+        for policy in [&setup.policy, &policy] {
+            let (header, _ss) = Header::new(&setup.ibe_pk, policy, &mut rng).unwrap();
+
+            let v = bincode::serialize(&header).unwrap();
+            let decoded: Header = bincode::deserialize(&v).unwrap();
+
+            for (id, pol) in policy {
+                assert_eq!(decoded.recipients[id].policy, pol.to_hidden());
+            }
+            assert_eq!(bincode::serialize(&decoded).unwrap(), v);
+        }
     }
 
     #[test]
@@ -287,4 +830,29 @@ mod tests {
         assert_eq!(&ss1, &ss2);
         assert_eq!(&ss1, &ss3);
     }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn test_recipient_shared_secret() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let (header, ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+
+        // Bob and Charlie (both email + name) must recover the same secret.
+        let ss_bob = header
+            .recipient_shared_secret("Bob", &setup.usks[2])
+            .unwrap();
+        let ss_charlie = header
+            .recipient_shared_secret("Charlie", &setup.usks[3])
+            .unwrap();
+
+        assert_eq!(&ss, &ss_bob);
+        assert_eq!(&ss, &ss_charlie);
+
+        assert!(matches!(
+            header.recipient_shared_secret("Alice", &setup.usks[0]),
+            Err(Error::UnknownIdentifier(_))
+        ));
+    }
 }
@@ -6,7 +6,13 @@
 
 mod header;
 
-pub use header::{Algorithm, Header, Mode, RecipientHeader};
+#[cfg(all(feature = "stream", any(feature = "rust", feature = "web")))]
+mod segment;
+
+pub use header::{
+    Algorithm, Compression, Header, KemScheme, Mode, RecipientHeader, Scheme, SigScheme,
+    SignatureMode,
+};
 
 #[cfg(feature = "rust")]
 pub mod rust;
@@ -15,18 +21,28 @@ pub mod rust;
 pub mod web;
 
 use crate::artifacts::VerifyingKey;
-use crate::identity::Policy;
+use crate::identity::{HiddenPolicy, Policy};
 use crate::util::*;
 use crate::{artifacts::SigningKeyExt, consts::*};
-use header::SignatureExt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(all(feature = "rust", feature = "stream"))]
+use header::header_unsigned;
+use header::{header_verified, SignatureExt};
+use ibe::kem::cgw_kv::CGWKV;
+use ibe::kem::IBKEM;
 use ibs::gg::Verifier;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 
 /// A Sealer is used to encrypt and sign data using PostGuard.
+///
+/// The identity-based KEM `K` defaults to [`CGWKV`].
 #[derive(Debug)]
-pub struct Sealer<'r, R, C> {
+pub struct Sealer<'r, R, C, K: IBKEM = CGWKV> {
     // The prebuilt header.
-    header: Header,
+    header: Header<K>,
 
     // An exclusive reference to a random number generator.
     rng: &'r mut R,
@@ -35,15 +51,15 @@ pub struct Sealer<'r, R, C> {
     config: C,
 
     // The public signing key. Used to sign public data, such as the header.
-    // The signature and claims are visible to outsiders.
-    pub_sign_key: SigningKeyExt,
+    // The signature and claims are visible to outsiders. Absent if nothing is signed.
+    pub_sign_key: Option<SigningKeyExt>,
 
     // An optional private signing key.
     // The signature and claims are encrypted and not visible to outsiders.
     priv_sign_key: Option<SigningKeyExt>,
 }
 
-impl<'r, R, C> Sealer<'r, R, C> {
+impl<'r, R, C, K: IBKEM> Sealer<'r, R, C, K> {
     /// Add a private signing key and policy.
     ///
     /// This policy is safe to include private data as it is encrypted after signing.
@@ -51,6 +67,46 @@ impl<'r, R, C> Sealer<'r, R, C> {
         self.priv_sign_key = Some(priv_sign_key);
         self
     }
+
+    /// Add public metadata to the header.
+    ///
+    /// The metadata is not encrypted, but it is covered by the header signature.
+    pub fn with_metadata(mut self, metadata: &[u8]) -> Self {
+        self.header.metadata = Some(metadata.to_vec());
+        self
+    }
+
+    /// Optional: Require the payload to be sealed for at most `max` recipients.
+    ///
+    /// Errors with [`crate::error::Error::ConstraintViolation`] otherwise, before anything is
+    /// sealed. This guards against accidentally sealing for a huge set of recipients, e.g., due to
+    /// a bug in assembling the [`EncryptionPolicy`][crate::identity::EncryptionPolicy], which
+    /// would balloon the header. A sealer always has at least one recipient.
+    pub fn with_max_recipients(self, max: usize) -> Result<Self, crate::error::Error> {
+        if self.header.recipients.len() > max {
+            return Err(crate::error::Error::ConstraintViolation);
+        }
+
+        Ok(self)
+    }
+
+    /// Optional: Require the timestamps of all recipient policies to lie at most `window` before
+    /// or after UNIX time `now`, see [`Policy::timestamp_sanity`].
+    ///
+    /// Errors with [`crate::error::Error::ConstraintViolation`] otherwise, before anything is
+    /// sealed. This catches a skewed clock before the payload is sealed for identities whose keys
+    /// cannot be retrieved yet.
+    pub fn with_timestamp_window(
+        self,
+        now: u64,
+        window: Duration,
+    ) -> Result<Self, crate::error::Error> {
+        for info in self.header.recipients.values() {
+            info.policy.timestamp_sanity(now, window)?;
+        }
+
+        Ok(self)
+    }
 }
 
 /// An Unsealer is used to decrypt and verify data using PostGuard.
@@ -62,17 +118,22 @@ impl<'r, R, C> Sealer<'r, R, C> {
 ///
 /// 2. Then, the user has input the user secret key and the recipient for which decryption should
 ///    take place.
+///
+/// The identity-based KEM `K` defaults to [`CGWKV`].
 #[derive(Debug)]
-pub struct Unsealer<R, C: UnsealerConfig> {
+pub struct Unsealer<R, C: UnsealerConfig, K: IBKEM = CGWKV> {
     /// The version found before the raw header.
     pub version: u16,
 
     /// The parsed header.
-    pub header: Header,
+    pub header: Header<K>,
 
     /// The verified public identity which was used to sign the header.
     pub pub_id: Policy,
 
+    // The header as it was serialized in the input.
+    header_raw: Vec<u8>,
+
     // The input.
     r: R,
 
@@ -86,8 +147,68 @@ pub struct Unsealer<R, C: UnsealerConfig> {
     vk: VerifyingKey,
 }
 
+impl<R, C: UnsealerConfig, K: IBKEM> Unsealer<R, C, K> {
+    /// The parsed header.
+    pub fn header(&self) -> &Header<K> {
+        &self.header
+    }
+
+    /// The header bytes, exactly as they were read from the input.
+    ///
+    /// These are the bytes covered by the header signature.
+    pub fn header_raw(&self) -> &[u8] {
+        &self.header_raw
+    }
+
+    /// The public metadata in the header, if any.
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.header.metadata.as_deref()
+    }
+
+    /// The version found before the raw header.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Optional: Require the input to be of at least version `min`.
+    ///
+    /// Errors with [`crate::error::Error::UnsupportedVersion`] otherwise, before anything is
+    /// unsealed. This allows clients to refuse inputs of a legacy format, should a later version
+    /// protect against downgrades.
+    pub fn require_min_version(self, min: u16) -> Result<Self, crate::error::Error> {
+        if self.version < min {
+            return Err(crate::error::Error::UnsupportedVersion(self.version));
+        }
+
+        Ok(self)
+    }
+
+    /// Optional: Require the public signing policy to be at most `max_age` old at UNIX time `now`.
+    ///
+    /// Errors with [`crate::error::Error::ConstraintViolation`] otherwise, before anything is
+    /// unsealed. The signing keys of a sender are issued together, so this also bounds the age
+    /// of the private signing policy. Unsigned payloads have no signing policy and are always
+    /// rejected.
+    pub fn with_max_policy_age(
+        self,
+        now: u64,
+        max_age: Duration,
+    ) -> Result<Self, crate::error::Error> {
+        if self.header.signature_mode == SignatureMode::Unsigned
+            || self.pub_id.is_expired(now, max_age)
+        {
+            return Err(crate::error::Error::ConstraintViolation);
+        }
+
+        Ok(self)
+    }
+}
+
 /// Sender verification result.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+///
+/// Can be (de)serialized, e.g., as JSON, to persist the result or to transmit it to where it is
+/// displayed. Fields that are unset are omitted, and default when deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct VerificationResult {
     /// The public signing verified claims.
     pub public: Policy,
@@ -95,6 +216,93 @@ pub struct VerificationResult {
     /// The private signing verified claims.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub private: Option<Policy>,
+
+    /// The recipient identifier that was used to unseal.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub recipient: Option<String>,
+
+    /// The hidden policy of the recipient that was used to unseal, as found in the header.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub recipient_policy: Option<HiddenPolicy>,
+
+    /// The timestamp (UNIX time) of the public signing claims, i.e., when the signing key was
+    /// issued.
+    #[serde(default)]
+    pub timestamp: u64,
+
+    /// The public signing claims, with their values hidden.
+    #[serde(default)]
+    pub public_hidden: HiddenPolicy,
+
+    /// The private signing claims, with their values hidden.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub private_hidden: Option<HiddenPolicy>,
+
+    /// Whether the payload was not signed at all, see [`SignatureMode::Unsigned`].
+    ///
+    /// If so, the sender is not authenticated and the signing claims are empty.
+    #[serde(skip_serializing_if = "core::ops::Not::not", default)]
+    pub unsigned: bool,
+
+    /// The number of segments of the payload, including the final one.
+    ///
+    /// A payload that is unsealed in memory is a single segment. Callers can compare the count of
+    /// a stream against an expected one, e.g., from an index, to detect a stream that was
+    /// truncated at the boundary of a segment that was sealed as the final one. Zero if the count
+    /// is unknown, e.g., when re-sealing a stream, as the result is recorded before the segments
+    /// are read.
+    #[serde(default)]
+    pub segments: u32,
+}
+
+impl VerificationResult {
+    /// Creates the result from the verified claims.
+    ///
+    /// The claims used to sign the payload are only reported as private if they differ from the
+    /// public claims.
+    pub(crate) fn new(
+        public: Policy,
+        payload_claims: Policy,
+        recipient: &str,
+        recipient_policy: HiddenPolicy,
+    ) -> Self {
+        // Compared in constant time, should the claims ever influence the handling of secrets.
+        let private = if bool::from(public.ct_eq(&payload_claims)) {
+            None
+        } else {
+            Some(payload_claims)
+        };
+
+        VerificationResult {
+            timestamp: public.timestamp,
+            public_hidden: public.to_hidden(),
+            private_hidden: private.as_ref().map(Policy::to_hidden),
+            public,
+            private,
+            recipient: Some(recipient.to_string()),
+            recipient_policy: Some(recipient_policy),
+            unsigned: false,
+            segments: 1,
+        }
+    }
+
+    /// Creates the result for a payload that was not signed.
+    #[cfg(all(feature = "rust", feature = "stream"))]
+    pub(crate) fn unsigned(recipient: &str, recipient_policy: HiddenPolicy) -> Self {
+        let public = Policy::default();
+
+        VerificationResult {
+            timestamp: 0,
+            public_hidden: public.to_hidden(),
+            private_hidden: None,
+            public,
+            private: None,
+            recipient: Some(recipient.to_string()),
+            recipient_policy: Some(recipient_policy),
+            unsigned: true,
+            segments: 1,
+        }
+    }
 }
 
 /// Sealer configuration.
@@ -112,6 +320,7 @@ pub trait UnsealerConfig: sealed::UnsealerConfig {}
 pub(crate) mod sealed {
     pub trait UnsealerConfig {}
     pub trait SealerConfig {}
+    pub trait Scheme {}
 }
 
 #[cfg(any(feature = "stream", target_arch = "wasm32"))]
@@ -121,10 +330,21 @@ impl From<futures::io::Error> for crate::error::Error {
     }
 }
 
+/// The parameters of [`Mode::Streaming`], as checked by [`stream_mode_checked`].
+#[cfg(feature = "stream")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(self) struct StreamMode {
+    /// The size of the plaintext of a segment (in bytes).
+    segment_size: u32,
+
+    /// The bounds on the size of the payload, as given by the [`Sealer`].
+    size_hint: (u64, Option<u64>),
+}
+
 #[cfg(feature = "stream")]
-pub(self) fn stream_mode_checked(
-    h: &Header,
-) -> Result<(u32, (u64, Option<u64>)), crate::error::Error> {
+pub(self) fn stream_mode_checked<K: IBKEM>(
+    h: &Header<K>,
+) -> Result<StreamMode, crate::error::Error> {
     let (segment_size, size_hint) = match h {
         Header {
             mode:
@@ -137,9 +357,14 @@ pub(self) fn stream_mode_checked(
         _ => return Err(crate::error::Error::ModeNotSupported(h.mode)),
     };
 
-    if *segment_size > MAX_SYMMETRIC_CHUNK_SIZE {
+    // The first segment must at least fit the policy length, and the segment size must not lead
+    // to absurd allocations.
+    if (*segment_size as usize) <= POL_SIZE_SIZE || *segment_size > MAX_SYMMETRIC_CHUNK_SIZE {
         return Err(crate::error::Error::ConstraintViolation);
     }
 
-    Ok((*segment_size, *size_hint))
+    Ok(StreamMode {
+        segment_size: *segment_size,
+        size_hint: *size_hint,
+    })
 }
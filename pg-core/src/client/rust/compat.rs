@@ -0,0 +1,49 @@
+//! Adapter to use [`tokio::io`] readers and writers as [`futures::io`] ones.
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures::io::{AsyncRead, AsyncWrite, Result};
+use futures::ready;
+use tokio::io::ReadBuf;
+
+/// Implements the [`futures::io`] traits for a [`tokio::io`] reader or writer.
+///
+/// Used by the `_tokio` methods of the stream [`Sealer`][`crate::client::Sealer`] and
+/// [`Unsealer`][`crate::client::Unsealer`], which therefore produce the same bytes as their
+/// [`futures::io`] counterparts.
+#[derive(Debug)]
+pub struct TokioCompat<T>(pub(crate) T);
+
+impl<T> TokioCompat<T> {
+    /// Returns the inner reader or writer.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: tokio::io::AsyncRead + Unpin> AsyncRead for TokioCompat<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        let mut read_buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.get_mut().0).poll_read(cx, &mut read_buf))?;
+
+        Poll::Ready(Ok(read_buf.filled().len()))
+    }
+}
+
+impl<T: tokio::io::AsyncWrite + Unpin> AsyncWrite for TokioCompat<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
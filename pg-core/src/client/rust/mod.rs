@@ -14,13 +14,17 @@ use crate::identity::EncryptionPolicy;
 
 use aead::{Aead, KeyInit};
 use aes_gcm::{Aes128Gcm, Nonce};
-use ibe::kem::cgw_kv::CGWKV;
 use ibs::gg::Signer;
 use rand::{CryptoRng, RngCore};
+use serde::de::DeserializeOwned;
+use zeroize::Zeroize;
 
 #[cfg(feature = "stream")]
 pub mod stream;
 
+#[cfg(feature = "tokio")]
+mod compat;
+
 /// In-memory configuration for a [`Sealer`].
 #[derive(Debug)]
 pub struct SealerMemoryConfig {
@@ -34,6 +38,12 @@ pub struct UnsealerMemoryConfig {
     message_len: usize,
 }
 
+impl Drop for SealerMemoryConfig {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl SealerConfig for SealerMemoryConfig {}
 impl super::sealed::SealerConfig for SealerMemoryConfig {}
 
@@ -58,10 +68,13 @@ struct MessageAndSignature {
     sig: SignatureExt,
 }
 
-impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
+impl<'r, R: RngCore + CryptoRng, K: Scheme> Sealer<'r, R, SealerMemoryConfig, K>
+where
+    Header<K>: Serialize,
+{
     /// Create a new [`Sealer`].
     pub fn new(
-        mpk: &PublicKey<CGWKV>,
+        mpk: &PublicKey<K>,
         policies: &EncryptionPolicy,
         pub_sign_key: &SigningKeyExt,
         rng: &'r mut R,
@@ -69,15 +82,14 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         let (header, ss) = Header::new(mpk, policies, rng)?;
         let Algorithm::Aes128Gcm(iv) = header.algo;
 
-        let mut key = [0u8; KEY_SIZE];
+        let key = *dem_key(ss)?;
         let mut nonce = [0u8; IV_SIZE];
-        key.copy_from_slice(&ss.0[..KEY_SIZE]);
         nonce.copy_from_slice(&iv.0[..IV_SIZE]);
 
         Ok(Self {
             rng,
             header,
-            pub_sign_key: pub_sign_key.clone(),
+            pub_sign_key: Some(pub_sign_key.clone()),
             priv_sign_key: None,
             config: SealerMemoryConfig { key, nonce },
         })
@@ -88,7 +100,7 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         let mut out = Vec::with_capacity(message.as_ref().len() + 1024);
 
         out.extend_from_slice(&PRELUDE);
-        out.extend_from_slice(&VERSION_V3.to_be_bytes());
+        out.extend_from_slice(&VERSION_V4.to_be_bytes());
 
         self.header = self.header.with_mode(Mode::InMemory {
             size: message.as_ref().len().try_into()?,
@@ -98,19 +110,22 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         out.extend_from_slice(&u32::try_from(header_buf.len())?.to_be_bytes());
         out.extend_from_slice(&header_buf);
 
+        let pub_sign_key = self
+            .pub_sign_key
+            .ok_or(Error::SignatureModeNotSupported(SignatureMode::Unsigned))?;
         let signer = Signer::new().chain(header_buf);
-        let h_sig = signer.clone().sign(&self.pub_sign_key.key.0, self.rng);
+        let h_sig = signer.clone().sign(&pub_sign_key.key.0, self.rng);
 
         let h_sig_ext = SignatureExt {
             sig: h_sig,
-            pol: self.pub_sign_key.policy.clone(),
+            pol: pub_sign_key.policy.clone(),
         };
 
         let h_sig_ext_bytes = bincode::serialize(&h_sig_ext)?;
         out.extend_from_slice(&u32::try_from(h_sig_ext_bytes.len())?.to_be_bytes());
         out.extend_from_slice(&h_sig_ext_bytes);
 
-        let m_sig_key = self.priv_sign_key.unwrap_or(self.pub_sign_key);
+        let m_sig_key = self.priv_sign_key.unwrap_or(pub_sign_key);
         let m_sig = signer.chain(&message).sign(&m_sig_key.key.0, self.rng);
 
         let aead = Aes128Gcm::new_from_slice(&self.config.key)?;
@@ -132,37 +147,51 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
     }
 }
 
-impl Unsealer<Vec<u8>, UnsealerMemoryConfig> {
+impl<K: Scheme> Unsealer<Vec<u8>, UnsealerMemoryConfig, K>
+where
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
     /// Create a new [`Unsealer`].
     pub fn new(input: impl AsRef<[u8]>, vk: &VerifyingKey) -> Result<Self, Error> {
         let b = input.as_ref();
+        if b.len() < PREAMBLE_SIZE {
+            return Err(Error::HeaderTooShort);
+        }
         let (preamble_bytes, b) = b.split_at(PREAMBLE_SIZE);
         let (version, header_len) = preamble_checked(preamble_bytes)?;
 
+        if b.len() < header_len + SIG_SIZE_SIZE {
+            return Err(Error::HeaderTooShort);
+        }
         let (header_bytes, b) = b.split_at(header_len);
         let (h_sig_len_bytes, b) = b.split_at(SIG_SIZE_SIZE);
-        let h_sig_len = u32::from_be_bytes(h_sig_len_bytes.try_into()?);
-        let (h_sig_bytes, ct) = b.split_at(h_sig_len as usize);
-
-        let h_sig_ext: SignatureExt = bincode::deserialize(h_sig_bytes)?;
-        let id = h_sig_ext.pol.derive_ibs()?;
-
-        let verifier = Verifier::default().chain(header_bytes);
+        let h_sig_len = header_sig_len_checked(h_sig_len_bytes.try_into()?)?;
 
-        if !verifier.clone().verify(&vk.0, &h_sig_ext.sig, &id) {
-            return Err(Error::IncorrectSignature);
+        if b.len() < h_sig_len {
+            return Err(Error::HeaderTooShort);
         }
+        let (h_sig_bytes, ct) = b.split_at(h_sig_len);
 
-        let header: Header = bincode::deserialize(header_bytes)?;
+        let h_sig_ext: SignatureExt =
+            bincode::deserialize(h_sig_bytes).map_err(Error::HeaderDeserialize)?;
+        let verifier = Verifier::default().chain(header_bytes);
+        let header: Header<K> =
+            header_verified(version, header_bytes, &h_sig_ext, vk)?.scheme_checked()?;
         let message_len = match header.mode {
             Mode::InMemory { size } => size as usize,
             _ => return Err(Error::ModeNotSupported(header.mode)),
         };
 
+        if header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(header.compression));
+        }
+
         Ok(Self {
             version,
             header,
             pub_id: h_sig_ext.pol,
+            header_raw: header_bytes.to_vec(),
             r: ct.to_vec(),
             verifier,
             vk: vk.clone(),
@@ -174,7 +203,7 @@ impl Unsealer<Vec<u8>, UnsealerMemoryConfig> {
     pub fn unseal(
         self,
         ident: &str,
-        usk: &UserSecretKey<CGWKV>,
+        usk: &UserSecretKey<K>,
     ) -> Result<(Vec<u8>, VerificationResult), Error> {
         let rec_info = self
             .header
@@ -182,17 +211,17 @@ impl Unsealer<Vec<u8>, UnsealerMemoryConfig> {
             .get(ident)
             .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
 
-        let ss = rec_info.decaps(usk)?;
-        let key = &ss.0[..KEY_SIZE];
+        let key = dem_key(rec_info.decaps(usk)?)?;
 
         let Algorithm::Aes128Gcm(iv) = self.header.algo;
 
-        let aead = Aes128Gcm::new_from_slice(key)?;
+        let aead = Aes128Gcm::new_from_slice(&key[..])?;
         let nonce = Nonce::from_slice(&iv.0);
 
         let plain = aead.decrypt(nonce, &*self.r)?;
 
         let msg: MessageAndSignature = bincode::deserialize(&plain)?;
+        msg.sig.pol.validate()?;
         let id = msg.sig.pol.derive_ibs()?;
 
         if !self
@@ -205,18 +234,9 @@ impl Unsealer<Vec<u8>, UnsealerMemoryConfig> {
 
         debug_assert_eq!(self.config.message_len, msg.message.len());
 
-        let private = if self.pub_id == msg.sig.pol {
-            None
-        } else {
-            Some(msg.sig.pol)
-        };
-
         Ok((
             msg.message,
-            VerificationResult {
-                public: self.pub_id,
-                private,
-            },
+            VerificationResult::new(self.pub_id, msg.sig.pol, ident, rec_info.policy.clone()),
         ))
     }
 }
@@ -261,9 +281,20 @@ mod tests {
         let expected = VerificationResult {
             public: setup.policies[0].clone(),
             private: Some(setup.policies[1].clone()),
+            recipient: Some(String::from("Bob")),
+            recipient_policy: Some(setup.policy["Bob"].to_hidden()),
+            timestamp: setup.policies[0].timestamp,
+            public_hidden: setup.policies[0].to_hidden(),
+            private_hidden: Some(setup.policies[1].to_hidden()),
+            unsigned: false,
+            segments: 1,
         };
 
         assert_eq!(&verified_policy, &expected);
+
+        let json = serde_json::to_string(&verified_policy).unwrap();
+        let restored: VerificationResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, expected);
     }
 
     #[test]
@@ -292,7 +323,69 @@ mod tests {
             .unwrap()
             .unseal("Charlie", &usk);
 
-        assert!(matches!(res, Err(Error::KEM)));
+        assert!(matches!(res, Err(Error::WrongUserSecretKey)));
+    }
+
+    #[test]
+    fn test_recipient_count() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let pub_sign_key = &setup.signing_keys[0];
+
+        // Nobody could unseal a payload without recipients.
+        assert!(matches!(
+            Sealer::<_, SealerMemoryConfig>::new(
+                &setup.ibe_pk,
+                &EncryptionPolicy::new(),
+                pub_sign_key,
+                &mut rng,
+            ),
+            Err(Error::ConstraintViolation)
+        ));
+
+        let n = setup.policy.len();
+        let sealer = |max| {
+            Sealer::<_, SealerMemoryConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                pub_sign_key,
+                &mut rand::thread_rng(),
+            )
+            .unwrap()
+            .with_max_recipients(max)
+            .map(|_| ())
+        };
+
+        assert!(sealer(n).is_ok());
+        assert!(matches!(sealer(n - 1), Err(Error::ConstraintViolation)));
+    }
+
+    #[test]
+    fn test_timestamp_window() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let timestamp = setup.policy["Bob"].timestamp;
+        let window = core::time::Duration::from_secs(60);
+
+        let sealer = |now| {
+            Sealer::<_, SealerMemoryConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rand::thread_rng(),
+            )
+            .unwrap()
+            .with_timestamp_window(now, window)
+            .map(|_| ())
+        };
+
+        assert!(sealer(timestamp).is_ok());
+
+        // The policies lie too far in the future, e.g., due to a skewed clock.
+        assert!(matches!(
+            sealer(timestamp - 61),
+            Err(Error::ConstraintViolation)
+        ));
     }
 
     #[test]
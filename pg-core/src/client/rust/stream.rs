@@ -3,69 +3,162 @@
 use alloc::string::ToString;
 
 use crate::artifacts::{PublicKey, SigningKeyExt, UserSecretKey, VerifyingKey};
+use crate::client::segment::{header_ad, policy_segments, sign_segment, ReadState, Segments};
 use crate::client::*;
-use crate::error::Error;
+use crate::error::{Error, SegmentFailure};
 use crate::identity::{EncryptionPolicy, Policy};
-use ibe::kem::cgw_kv::CGWKV;
-use ibs::gg::{Identity, Signature, Signer, Verifier, SIG_BYTES};
+#[cfg(feature = "compression")]
+use async_compression::futures::{bufread::DeflateEncoder, write::DeflateDecoder};
+#[cfg(feature = "compression")]
+use futures::io::BufReader;
+use ibe::kem::SharedSecret;
+use ibs::gg::{Signer, Verifier, SIG_BYTES};
 
-use reck::Deck;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use futures::io::{AsyncRead, AsyncWrite};
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::sync::atomic::AtomicBool;
+use core::task::{Context, Poll};
+use core::time::Duration;
+use futures::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWrite, SeekFrom};
 use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::ready;
+use futures::stream::{self, Stream};
 use futures::TryFutureExt;
 use rand::{CryptoRng, RngCore};
+use reck::Deck;
+use serde::de::DeserializeOwned;
+use zeroize::Zeroize;
+
+#[cfg(feature = "tokio")]
+pub use super::compat::TokioCompat;
+pub use crate::client::segment::{StreamSigner, StreamVerifier};
 
 /// Configures an [`Sealer`] to process a payload stream.
+///
+/// The segments are signed using the [`StreamSigner`] `S`.
 #[derive(Debug)]
-pub struct SealerStreamConfig {
+pub struct SealerStreamConfig<S = Signer> {
     /// Segment size.
     segment_size: u32,
     /// AEAD key.
     key: [u8; KEY_SIZE],
     /// AEAD nonce.
     nonce: [u8; STREAM_NONCE_SIZE],
+    /// Segment signature scheme.
+    scheme: PhantomData<S>,
 }
 
 /// Configures an [`Unsealer`] to process a payload stream.
+///
+/// The segments are verified using the [`StreamVerifier`] `V`.
 #[derive(Debug)]
-pub struct UnsealerStreamConfig {
+pub struct UnsealerStreamConfig<V = Verifier> {
     segment_size: u32,
+    size_hint: (u64, Option<u64>),
+    /// Set to abort unsealing, see [`Unsealer::with_abort_signal`].
+    abort: Option<Arc<AtomicBool>>,
+    scheme: PhantomData<V>,
+}
+
+impl<S> Drop for SealerStreamConfig<S> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
 }
 
-impl SealerConfig for SealerStreamConfig {}
-impl UnsealerConfig for UnsealerStreamConfig {}
-impl crate::client::sealed::SealerConfig for SealerStreamConfig {}
-impl crate::client::sealed::UnsealerConfig for UnsealerStreamConfig {}
+impl<S: StreamSigner> SealerConfig for SealerStreamConfig<S> {}
+impl<V: StreamVerifier> UnsealerConfig for UnsealerStreamConfig<V> {}
+impl<S: StreamSigner> crate::client::sealed::SealerConfig for SealerStreamConfig<S> {}
+impl<V: StreamVerifier> crate::client::sealed::UnsealerConfig for UnsealerStreamConfig<V> {}
 
-impl<'r, Rng: RngCore + CryptoRng> Sealer<'r, Rng, SealerStreamConfig> {
+impl<'r, Rng, K, S> Sealer<'r, Rng, SealerStreamConfig<S>, K>
+where
+    Rng: RngCore + CryptoRng,
+    K: Scheme,
+    S: StreamSigner,
+    Header<K>: Serialize,
+{
     /// Construct a new [`Sealer`] that can process streaming payloads.
     pub fn new(
-        pk: &PublicKey<CGWKV>,
+        pk: &PublicKey<K>,
         policies: &EncryptionPolicy,
         pub_sign_key: &SigningKeyExt,
         rng: &'r mut Rng,
     ) -> Result<Self, Error> {
         let (header, ss) = Header::new(pk, policies, rng)?;
 
-        let (segment_size, _) = stream_mode_checked(&header)?;
+        Self::from_shared_secret(header, ss, pub_sign_key, rng)
+    }
+
+    /// Construct a new [`Sealer`] from a header and the shared secret encapsulated in it.
+    ///
+    /// This decouples sealing from the KEM, e.g., to use a shared secret produced by an HSM or
+    /// to create reproducible test vectors. The DEM key is derived from `ss` and the nonce is
+    /// taken from the IV in the header, exactly as in [`Sealer::new`], which uses a fresh header.
+    ///
+    /// The caller is responsible for never sealing more than one payload using the same header
+    /// and shared secret. Doing so reuses the DEM key and nonce, which breaks the confidentiality
+    /// and integrity of all payloads involved.
+    pub fn from_shared_secret(
+        header: Header<K>,
+        ss: SharedSecret,
+        pub_sign_key: &SigningKeyExt,
+        rng: &'r mut Rng,
+    ) -> Result<Self, Error> {
+        Self::from_parts(
+            header.with_bind_header(true),
+            ss,
+            Some(pub_sign_key.clone()),
+            rng,
+        )
+    }
+
+    /// Construct a new [`Sealer`] that only encrypts, see [`SignatureMode::Unsigned`].
+    ///
+    /// Neither the header nor the segments are signed, so the sender is not authenticated. The
+    /// segments do not carry a signing policy either. Use [`Sealer::new`] unless the payload is
+    /// authenticated by other means.
+    pub fn new_unsigned(
+        pk: &PublicKey<K>,
+        policies: &EncryptionPolicy,
+        rng: &'r mut Rng,
+    ) -> Result<Self, Error> {
+        let (header, ss) = Header::new(pk, policies, rng)?;
+        let header = header
+            .with_bind_header(true)
+            .with_signature_mode(SignatureMode::Unsigned);
+
+        Self::from_parts(header, ss, None, rng)
+    }
+
+    /// Sets up the DEM key and nonce for the header.
+    fn from_parts(
+        header: Header<K>,
+        ss: SharedSecret,
+        pub_sign_key: Option<SigningKeyExt>,
+        rng: &'r mut Rng,
+    ) -> Result<Self, Error> {
+        let StreamMode { segment_size, .. } = stream_mode_checked(&header)?;
         let Algorithm::Aes128Gcm(iv) = header.algo;
 
-        let mut key = [0u8; KEY_SIZE];
+        let key = *dem_key(ss)?;
         let mut nonce = [0u8; STREAM_NONCE_SIZE];
 
-        key.copy_from_slice(&ss.0[..KEY_SIZE]);
         nonce.copy_from_slice(&iv.0[..STREAM_NONCE_SIZE]);
 
         Ok(Sealer {
             rng,
             header,
-            pub_sign_key: pub_sign_key.clone(),
+            pub_sign_key,
             priv_sign_key: None,
             config: SealerStreamConfig {
                 segment_size,
                 key,
                 nonce,
+                scheme: PhantomData,
             },
         })
     }
@@ -82,96 +175,339 @@ impl<'r, Rng: RngCore + CryptoRng> Sealer<'r, Rng, SealerStreamConfig> {
         self
     }
 
+    /// Optional: Compress the payload before sealing.
+    ///
+    /// The codec is recorded in the (signed) header, so that the [`Unsealer`] decompresses the
+    /// payload after decryption and verification.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.header.compression = compression;
+        self
+    }
+
+    /// Optional: Set how the payload is signed, defaults to [`SignatureMode::PerSegment`].
+    ///
+    /// The mode is recorded in the (signed) header, so that the [`Unsealer`] knows which
+    /// segments carry a signature.
+    pub fn with_signature_mode(mut self, signature_mode: SignatureMode) -> Self {
+        self.header.signature_mode = signature_mode;
+        self
+    }
+
+    /// Returns the number of bytes that sealing adds to a payload of `plaintext_len` bytes,
+    /// including the header.
+    ///
+    /// Errors if the payload is compressed, since its size is then not known beforehand.
+    pub fn overhead(&self, plaintext_len: u64) -> Result<u64, Error> {
+        if self.header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(self.header.compression));
+        }
+
+        let header_len =
+            PREAMBLE_SIZE as u64 + bincode::serialized_size(&self.header)? + SIG_SIZE_SIZE as u64;
+
+        if self.header.signature_mode == SignatureMode::Unsigned {
+            let segments = plaintext_len / u64::from(self.config.segment_size) + 1;
+
+            return Ok(header_len + segments * STREAM_TAG_SIZE as u64);
+        }
+
+        let pub_sign_key = self
+            .pub_sign_key
+            .as_ref()
+            .ok_or(Error::SignatureModeNotSupported(self.header.signature_mode))?;
+        let header_len =
+            header_len + SIG_BYTES as u64 + bincode::serialized_size(&pub_sign_key.policy)?;
+
+        let signing_key = self.priv_sign_key.as_ref().unwrap_or(pub_sign_key);
+        let payload_overhead = payload_overhead(
+            plaintext_len,
+            self.config.segment_size,
+            &signing_key.policy,
+            self.header.signature_mode,
+            S::SIG_BYTES,
+        )?;
+
+        Ok(header_len + payload_overhead)
+    }
+
     /// Seals payload data from an [`AsyncRead`] into an [`AsyncWrite`].
-    pub async fn seal<R, W>(self, mut r: R, mut w: W) -> Result<(), Error>
+    pub async fn seal<R, W>(self, r: R, w: W) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        match self.header.compression {
+            Compression::None => self.seal_raw(r, w).await,
+            #[cfg(feature = "compression")]
+            Compression::Deflate => {
+                self.seal_raw(DeflateEncoder::new(BufReader::new(r)), w)
+                    .await
+            }
+            #[cfg(not(feature = "compression"))]
+            c => Err(Error::CompressionNotSupported(c)),
+        }
+    }
+
+    /// Seals payload data like [`Sealer::seal`], returning the number of bytes written.
+    ///
+    /// The count includes the header. Without compression, it equals the length of the payload
+    /// plus [`Sealer::overhead`].
+    pub async fn seal_counted<R, W>(self, r: R, w: W) -> Result<u64, Error>
     where
         R: AsyncRead + Unpin,
         W: AsyncWrite + Unpin,
     {
-        w.write_all(&PRELUDE).await?;
-        w.write_all(&VERSION_V3.to_be_bytes()).await?;
+        let mut w = CountingWriter { w, written: 0 };
+        self.seal(r, &mut w).await?;
 
-        let header_vec = bincode::serialize(&self.header)?;
-        w.write_all(&u32::try_from(header_vec.len())?.to_be_bytes())
-            .await?;
-        w.write_all(&header_vec).await?;
+        Ok(w.written)
+    }
+
+    /// Seals payload data from a [`tokio::io::AsyncRead`] into a [`tokio::io::AsyncWrite`].
+    ///
+    /// The output is identical to that of [`Sealer::seal`].
+    #[cfg(feature = "tokio")]
+    pub async fn seal_tokio<R, W>(self, r: R, w: W) -> Result<(), Error>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        self.seal(TokioCompat(r), TokioCompat(w)).await
+    }
 
-        let mut signer = Signer::default().chain(&header_vec);
-        let header_sig = signer.clone().sign(&self.pub_sign_key.key.0, self.rng);
-        let header_sig_ext = SignatureExt {
-            sig: header_sig,
-            pol: self.pub_sign_key.policy.clone(),
+    async fn seal_raw<R, W>(mut self, r: R, mut w: W) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let (header_bytes, signer) = self.header_bytes()?;
+        w.write_all(&header_bytes).await?;
+
+        self.seal_segments(r, w, signer, 0).await
+    }
+
+    /// Resumes an interrupted [`Sealer::seal`] of the same input into the same output.
+    ///
+    /// Sealing continues under the header that was already written to the output, so the
+    /// [`Sealer`] must be constructed using [`Sealer::from_shared_secret`] from the header and
+    /// shared secret of the interrupted one. The header is read back from the output and errors
+    /// with [`Error::HeaderMismatch`] if it differs from that of the [`Sealer`]. The header
+    /// signature found in the output is kept. If the output does not contain a complete header,
+    /// it is written from the start.
+    ///
+    /// The number of fully written segments is derived from the length of the output, which is
+    /// then overwritten from the first incomplete segment onwards. The signatures cover all
+    /// preceding payload, therefore the input is read again from the start to restore the signer.
+    /// Segments that were already written are not encrypted or written again.
+    ///
+    /// Compression is not supported.
+    pub async fn resume<R, W>(mut self, mut r: R, mut w: W) -> Result<(), Error>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+        W: AsyncRead + AsyncWrite + AsyncSeek + Unpin,
+    {
+        if self.header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(self.header.compression));
+        }
+
+        let (header_bytes, signer) = self.header_bytes()?;
+        let header_len = PREAMBLE_SIZE + bincode::serialized_size(&self.header)? as usize;
+        let sig_len = match self.header.signature_mode {
+            SignatureMode::PerSegment => S::SIG_BYTES,
+            SignatureMode::Detached | SignatureMode::Unsigned => 0,
         };
-        let header_sig_bytes = bincode::serialize(&header_sig_ext)?;
+        let sealed_segment_size =
+            (self.config.segment_size as usize + sig_len + STREAM_TAG_SIZE) as u64;
 
-        w.write_all(&u32::try_from(header_sig_bytes.len())?.to_be_bytes())
-            .await?;
-        w.write_all(&header_sig_bytes).await?;
+        r.seek(SeekFrom::Start(0)).await?;
+        let written = w.seek(SeekFrom::End(0)).await?;
+        w.seek(SeekFrom::Start(0)).await?;
 
-        //let aead = Aes128Gcm::new_from_slice(&self.config.key)?;
-        //let mut enc = EncryptorBE32::from_aead(aead, &self.config.nonce.into());
-        let mut enc = Deck::new(&self.config.key, &self.config.nonce);
+        let skip = match read_header_prefix(&mut w, written).await? {
+            Some(prefix) => {
+                // The preamble and header must be identical, such that the segments that were
+                // already written are sealed under the same DEM key, nonce and header.
+                if prefix.get(..header_len) != Some(&header_bytes[..header_len]) {
+                    return Err(Error::HeaderMismatch);
+                }
 
-        // Check for a private signing key, otherwise fall back to the public one.
-        let signing_key = self.priv_sign_key.unwrap_or(self.pub_sign_key);
+                let prefix_len = prefix.len() as u64;
+                let segments = (written - prefix_len) / sealed_segment_size;
+                w.seek(SeekFrom::Start(prefix_len + segments * sealed_segment_size))
+                    .await?;
+                u32::try_from(segments)?
+            }
+            None => {
+                w.seek(SeekFrom::Start(0)).await?;
+                w.write_all(&header_bytes).await?;
+                0
+            }
+        };
 
-        let pol_bytes = bincode::serialize(&signing_key.policy)?;
-        let pol_len = pol_bytes.len();
+        self.seal_segments(r, w, signer, skip).await
+    }
 
-        if pol_len + POL_SIZE_SIZE > self.config.segment_size as usize {
-            return Err(Error::ConstraintViolation);
+    /// Seals the payload segments, the header must already have been written.
+    ///
+    /// The first `skip` segments are only signed, they have already been written.
+    async fn seal_segments<R, W>(
+        self,
+        mut r: R,
+        mut w: W,
+        mut signer: S,
+        skip: u32,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        if self.header.signature_mode == SignatureMode::Unsigned {
+            return self.seal_unsigned_segments(r, w, skip).await;
         }
 
-        let mut buf = vec![0; self.config.segment_size as usize + TAG_SIZE];
+        //let aead = Aes128Gcm::new_from_slice(&self.config.key)?;
+        //let mut enc = EncryptorBE32::from_aead(aead, &self.config.nonce.into());
+        let mut enc = self.deck(skip)?;
 
-        buf[..POL_SIZE_SIZE].copy_from_slice(&u32::try_from(pol_len)?.to_be_bytes());
-        buf[POL_SIZE_SIZE..POL_SIZE_SIZE + pol_len].copy_from_slice(&pol_bytes);
+        // Check for a private signing key, otherwise fall back to the public one.
+        let signing_key = self
+            .priv_sign_key
+            .or(self.pub_sign_key)
+            .ok_or(Error::SignatureModeNotSupported(self.header.signature_mode))?;
+        let segment_size = self.config.segment_size as usize;
+        let signature_mode = self.header.signature_mode;
 
-        let mut buf_tail = POL_SIZE_SIZE + pol_len;
+        let (leading, mut buf) = policy_segments::<S>(&signing_key, segment_size)?;
+        let mut buf_tail = buf.len();
         let mut start = buf_tail;
+        buf.resize(segment_size, 0);
 
+        // Leading segments: DEM.K (pol_len | POL_CONTINUED || pol_i || sig_i)
         // First segment: DEM.K (pol_len || pol || m_0 || sig_0 )
         // Other segments: DEM.K (m_i || sig_0)
+        // In detached mode, only the final segment carries a signature.
 
         let mut counter: u32 = 0;
 
+        for mut seg in leading {
+            if signature_mode == SignatureMode::PerSegment {
+                let start = seg.len();
+                sign_segment(
+                    &mut seg,
+                    start,
+                    &mut signer,
+                    &signing_key,
+                    counter,
+                    false,
+                    self.rng,
+                )?;
+            }
+
+            if counter >= skip {
+                enc.wrap(&mut seg).map_err(|_e| Error::Symmetric)?;
+                w.write_all(&seg).await?;
+            }
+
+            counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
+        }
+
         loop {
-            let read = r
-                .read(&mut buf[buf_tail..self.config.segment_size as usize])
-                .await?;
+            let read = r.read(&mut buf[buf_tail..segment_size]).await?;
             buf_tail += read;
 
-            if buf_tail == self.config.segment_size as usize {
+            if buf_tail == segment_size {
                 buf.truncate(buf_tail);
+                match signature_mode {
+                    SignatureMode::PerSegment => sign_segment(
+                        &mut buf,
+                        start,
+                        &mut signer,
+                        &signing_key,
+                        counter,
+                        false,
+                        self.rng,
+                    )?,
+                    SignatureMode::Detached => signer.update(&buf[start..]),
+                    // Sealed by `seal_unsigned_segments` instead.
+                    SignatureMode::Unsigned => {
+                        return Err(Error::SignatureModeNotSupported(signature_mode))
+                    }
+                }
 
-                signer.update(&buf[start..]);
-                let sig = signer
-                    .clone()
-                    .chain(&counter.to_be_bytes())
-                    .chain(&[0x00])
-                    .sign(&signing_key.key.0, self.rng);
-                bincode::serialize_into(&mut buf, &sig)?;
-
-                enc.wrap(&mut buf).unwrap();
-
-                w.write_all(&buf).await?;
+                if counter >= skip {
+                    enc.wrap(&mut buf).map_err(|_e| Error::Symmetric)?;
+                    w.write_all(&buf).await?;
+                }
 
                 buf_tail = 0;
                 start = 0;
-                counter = counter.checked_add(1).unwrap(); // cannot fail, otherwise
-                                                           // encrypt_next_in_place would have
-                                                           // failed too.                                                // encrypt_next_in_place not failing
+                counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
             } else if read == 0 {
+                // The output contains more segments than the input.
+                if counter < skip {
+                    return Err(Error::ConstraintViolation);
+                }
+
                 buf.truncate(buf_tail);
+                sign_segment(
+                    &mut buf,
+                    start,
+                    &mut signer,
+                    &signing_key,
+                    counter,
+                    true,
+                    self.rng,
+                )?;
+                enc.wrap_last(&mut buf).map_err(|_e| Error::Symmetric)?;
+
+                w.write_all(&buf).await?;
+                break;
+            }
+        }
+
+        w.flush().await?;
+        w.close().await?;
+
+        Ok(())
+    }
+
+    /// Seals the payload segments without signing them, see [`SignatureMode::Unsigned`].
+    ///
+    /// The segments only carry plaintext, the first `skip` segments have already been written.
+    async fn seal_unsigned_segments<R, W>(self, mut r: R, mut w: W, skip: u32) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut enc = self.deck(skip)?;
+        let segment_size = self.config.segment_size as usize;
+        // The segments are wrapped in place, so the buffer also fits the counter and tag.
+        let mut buf = vec![0u8; segment_size + STREAM_TAG_SIZE];
+        let mut buf_tail = 0;
+        let mut counter: u32 = 0;
+
+        loop {
+            let read = r.read(&mut buf[buf_tail..segment_size]).await?;
+            buf_tail += read;
+
+            if buf_tail == segment_size {
+                if counter >= skip {
+                    let len = enc
+                        .wrap_slice(&mut buf, segment_size)
+                        .map_err(|_e| Error::Symmetric)?;
+                    w.write_all(&buf[..len]).await?;
+                }
 
-                signer.update(&buf[start..]);
-                let sig_final = signer
-                    .chain(&counter.to_be_bytes())
-                    .chain(&[0x01])
-                    .sign(&signing_key.key.0, self.rng);
-                bincode::serialize_into(&mut buf, &sig_final)?;
+                buf_tail = 0;
+                counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
+            } else if read == 0 {
+                if counter < skip {
+                    return Err(Error::ConstraintViolation);
+                }
 
-                enc.wrap_last(&mut buf).unwrap();
+                buf.truncate(buf_tail);
+                enc.wrap_last(&mut buf).map_err(|_e| Error::Symmetric)?;
 
                 w.write_all(&buf).await?;
                 break;
@@ -183,341 +519,3285 @@ impl<'r, Rng: RngCore + CryptoRng> Sealer<'r, Rng, SealerStreamConfig> {
 
         Ok(())
     }
-}
 
-impl<R> Unsealer<R, UnsealerStreamConfig>
-where
-    R: AsyncRead + Unpin,
-{
-    /// Create a new [`Unsealer`] that starts reading from an [`AsyncRead`].
+    /// Turns the [`Sealer`] into a [`SealWriter`], which seals all data written into it.
     ///
-    /// Errors if the bytestream is not a legitimate PostGuard bytestream.
-    pub async fn new(mut r: R, pk: &VerifyingKey) -> Result<Self, Error> {
-        let mut preamble = [0u8; PREAMBLE_SIZE];
-        r.read_exact(&mut preamble)
-            .map_err(|_e| Error::NotPostGuard)
-            .await?;
+    /// The output is framed exactly like [`Sealer::seal`]. The [`SealWriter`] must be closed to
+    /// write the final segment. Compression and [`SignatureMode::Unsigned`] are not supported.
+    pub fn into_writer<W>(mut self, w: W) -> Result<SealWriter<'r, Rng, W, S>, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(self.header.compression));
+        }
 
-        let (version, header_len) = preamble_checked(&preamble)?;
-        let mut header_raw = Vec::with_capacity(header_len);
+        if self.header.signature_mode == SignatureMode::Unsigned {
+            return Err(Error::SignatureModeNotSupported(SignatureMode::Unsigned));
+        }
 
-        // Limit reader to not read past header
-        let mut r = r.take(header_len as u64);
+        let (mut out, mut signer) = self.header_bytes()?;
+        let mut enc = self.deck(0)?;
+        let signing_key = self
+            .priv_sign_key
+            .or(self.pub_sign_key)
+            .ok_or(Error::SignatureModeNotSupported(self.header.signature_mode))?;
+        let segment_size = self.config.segment_size as usize;
+        let signature_mode = self.header.signature_mode;
+        let (leading, buf) = policy_segments::<S>(&signing_key, segment_size)?;
 
-        r.read_to_end(&mut header_raw)
-            .map_err(|_e| Error::ConstraintViolation)
-            .await?;
+        // The leading segments only carry the signing policy, they are sealed right away.
+        let mut counter: u32 = 0;
+        for mut seg in leading {
+            if signature_mode == SignatureMode::PerSegment {
+                let start = seg.len();
+                sign_segment(
+                    &mut seg,
+                    start,
+                    &mut signer,
+                    &signing_key,
+                    counter,
+                    false,
+                    self.rng,
+                )?;
+            }
 
-        let mut r = r.into_inner();
+            enc.wrap(&mut seg).map_err(|_e| Error::Symmetric)?;
+            out.extend_from_slice(&seg);
+            counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
+        }
 
-        let mut header_sig_len_bytes = [0u8; SIG_SIZE_SIZE];
-        r.read_exact(&mut header_sig_len_bytes)
-            .map_err(|_e| Error::FormatViolation("no header signature length".to_string()))
-            .await?;
-        let header_sig_len = u32::from_be_bytes(header_sig_len_bytes);
+        Ok(SealWriter {
+            rng: self.rng,
+            w,
+            enc: Some(enc),
+            signer,
+            signature_mode,
+            start: buf.len(),
+            signing_key,
+            segment_size,
+            buf,
+            counter,
+            out,
+            out_pos: 0,
+        })
+    }
 
-        let mut header_sig_raw = Vec::with_capacity(header_sig_len as usize);
-        let mut r = r.take(header_sig_len as u64);
+    /// Sets up the DEM, continuing at segment `counter`.
+    fn deck(&self, counter: u32) -> Result<Deck, Error> {
+        let enc = Deck::resume(&self.config.key, &self.config.nonce, counter)
+            .map_err(|_e| Error::Symmetric)?;
 
-        r.read_to_end(&mut header_sig_raw).await?;
+        if !self.header.bind_header {
+            return Ok(enc);
+        }
 
-        let h_sig_ext: SignatureExt = bincode::deserialize(&header_sig_raw)?;
+        let header_vec = bincode::serialize(&self.header)?;
 
-        let verifier = Verifier::default().chain(&header_raw);
-        let pub_id = h_sig_ext.pol.derive_ibs()?;
+        Ok(enc.with_ad(&header_ad(&header_vec)))
+    }
 
-        if !verifier.clone().verify(&pk.0, &h_sig_ext.sig, &pub_id) {
-            return Err(Error::IncorrectSignature);
-        }
+    /// Serializes the preamble, header and header signature.
+    ///
+    /// Also returns the segment signer, which has already processed the header. In
+    /// [`SignatureMode::Unsigned`], the header signature is empty.
+    fn header_bytes(&mut self) -> Result<(Vec<u8>, S), Error> {
+        let header_vec = bincode::serialize(&self.header)?;
 
-        let header: Header = bincode::deserialize(&header_raw)?;
-        let (segment_size, _) = stream_mode_checked(&header)?;
+        let signer = S::default().chain(&header_vec);
+        let header_sig_bytes = match (&self.pub_sign_key, self.header.signature_mode) {
+            (_, SignatureMode::Unsigned) => Vec::new(),
+            (Some(pub_sign_key), _) => {
+                let header_sig = Signer::default()
+                    .chain(&header_vec)
+                    .sign(&pub_sign_key.key.0, self.rng);
+                let header_sig_ext = SignatureExt {
+                    sig: header_sig,
+                    pol: pub_sign_key.policy.clone(),
+                };
 
-        Ok(Unsealer {
-            version,
-            header,
-            pub_id: h_sig_ext.pol,
-            config: UnsealerStreamConfig { segment_size },
-            r: r.into_inner(), // This (new) reader is locked to the payload.
-            verifier,
-            vk: pk.clone(),
-        })
+                bincode::serialize(&header_sig_ext)?
+            }
+            (None, mode) => return Err(Error::SignatureModeNotSupported(mode)),
+        };
+
+        let mut out = Vec::with_capacity(
+            PREAMBLE_SIZE + header_vec.len() + SIG_SIZE_SIZE + header_sig_bytes.len(),
+        );
+        out.extend_from_slice(&PRELUDE);
+        out.extend_from_slice(&VERSION_V4.to_be_bytes());
+        out.extend_from_slice(&u32::try_from(header_vec.len())?.to_be_bytes());
+        out.extend_from_slice(&header_vec);
+        out.extend_from_slice(&u32::try_from(header_sig_bytes.len())?.to_be_bytes());
+        out.extend_from_slice(&header_sig_bytes);
+
+        Ok((out, signer))
     }
+}
 
-    /// Unseal the remaining data (which is now only payload) into an [`AsyncWrite`].
-    pub async fn unseal<W: AsyncWrite + Unpin>(
-        mut self,
-        ident: &str,
-        usk: &UserSecretKey<CGWKV>,
-        mut w: W,
-    ) -> Result<VerificationResult, Error> {
-        let rec_info = self
-            .header
-            .recipients
-            .get(ident)
-            .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
+/// The layout of the segments of a sealed stream, e.g., for implementations in other languages.
+///
+/// A sealed segment consists of its plaintext, its signature, the counter of the segment as a
+/// big-endian `u32` and the tag of the DEM, in that order. All segments but the final one carry
+/// `segment_size` bytes of plaintext. The plaintext of the leading segments is prefixed with (a
+/// part of) the signing policy. Every part is preceded by its length as a big-endian `u32`, in
+/// which the `policy_continued` flag is set if the policy continues in the next segment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentLayout {
+    /// The size of the plaintext of all segments but the final one.
+    pub segment_size: u32,
 
-        let ss = rec_info.decaps(usk)?;
-        let key = &ss.0[..KEY_SIZE];
-        //let aead = Aes128Gcm::new_from_slice(key)?;
+    /// The size of the signature of all segments but the final one.
+    pub sig_bytes: usize,
 
-        let Algorithm::Aes128Gcm(iv) = self.header.algo;
-        let nonce = &iv.0[..STREAM_NONCE_SIZE];
+    /// The size of the signature of the final segment.
+    pub final_sig_bytes: usize,
 
-        let mut dec =  Deck::new(&key, &nonce);
+    /// The size of the counter.
+    pub counter_len: usize,
 
-        let bufsize: usize = self.config.segment_size as usize + SIG_BYTES + TAG_SIZE;
-        let mut buf = vec![0u8; bufsize];
-        let mut buf_tail = 0;
-        let mut counter: u32 = 0;
-        let mut pol_id: Option<(Policy, Identity)> = None;
+    /// The size of the tag.
+    pub tag_len: usize,
 
-        fn extract_policy(buf: &mut Vec<u8>) -> Result<Option<(Policy, Identity)>, Error> {
-            let pol_len = u32::from_be_bytes(buf[..POL_SIZE_SIZE].try_into()?) as usize;
-            let pol_bytes = &buf[POL_SIZE_SIZE..POL_SIZE_SIZE + pol_len];
-            let pol: Policy = bincode::deserialize(pol_bytes)?;
-            let id = pol.derive_ibs()?;
+    /// The size of the length of (a part of) the signing policy, zero if there is none.
+    pub policy_len_size: usize,
 
-            buf.drain(..POL_SIZE_SIZE + pol_len);
+    /// The flag in the length of a part of the signing policy that marks a continued policy.
+    pub policy_continued: u32,
+}
 
-            Ok(Some((pol, id)))
-        }
+impl SegmentLayout {
+    /// Derives the layout of the segments from the header of a stream.
+    ///
+    /// The segments are assumed to be signed using the scheme of the header signature, as done by
+    /// a [`Sealer`] with the default [`StreamSigner`].
+    pub fn from_header<K: IBKEM>(header: &Header<K>) -> Result<Self, Error> {
+        let StreamMode { segment_size, .. } = stream_mode_checked(header)?;
 
-        fn verify_segment<'a>(
-            seg: &'a [u8],
-            verifier: &mut Verifier,
-            vk: &VerifyingKey,
-            id: &Identity,
-            counter: u32,
-            is_last: bool,
-        ) -> Result<&'a [u8], Error> {
-            debug_assert!(seg.len() > SIG_BYTES);
+        let sig_bytes = match header.sig_scheme {
+            SigScheme::GG => SIG_BYTES,
+            SigScheme::Unknown(tag) => return Err(Error::UnsupportedVersion(tag.into())),
+        };
+        let (sig_bytes, final_sig_bytes, policy_len_size) = match header.signature_mode {
+            SignatureMode::PerSegment => (sig_bytes, sig_bytes, POL_SIZE_SIZE),
+            SignatureMode::Detached => (0, sig_bytes, POL_SIZE_SIZE),
+            SignatureMode::Unsigned => (0, 0, 0),
+        };
 
-            let (m, sig_bytes) = seg.split_at(seg.len() - SIG_BYTES);
-            let sig: Signature = bincode::deserialize(sig_bytes)?;
-            verifier.update(m);
+        Ok(SegmentLayout {
+            segment_size,
+            sig_bytes,
+            final_sig_bytes,
+            counter_len: STREAM_TAG_SIZE - reck::TAG_LEN,
+            tag_len: reck::TAG_LEN,
+            policy_len_size,
+            policy_continued: POL_CONTINUED,
+        })
+    }
 
-            if !verifier
-                .clone()
-                .chain(&counter.to_be_bytes())
-                .chain(&[is_last as u8])
-                .verify(&vk.0, &sig, id)
-            {
-                return Err(Error::IncorrectSignature);
-            }
+    /// The size of a sealed segment, except for the final one.
+    pub fn sealed_segment_size(&self) -> usize {
+        self.segment_size as usize + self.sig_bytes + self.counter_len + self.tag_len
+    }
+}
 
-            Ok(m)
-        }
+/// Returns the number of bytes that sealing adds to a payload of `plaintext_len` bytes,
+/// excluding the header.
+///
+/// Every segment carries `segment_size` bytes of plaintext, followed by a signature and the
+/// counter and tag of the DEM. The payload is prefixed with the (length of the) signing policy,
+/// which may span several leading segments. The final segment carries the remainder, possibly nothing, and is always present. See
+/// [`Sealer::overhead`] to include the header.
+pub fn overhead_for(
+    plaintext_len: u64,
+    segment_size: u32,
+    signing_policy: &Policy,
+) -> Result<u64, Error> {
+    payload_overhead(
+        plaintext_len,
+        segment_size,
+        signing_policy,
+        SignatureMode::PerSegment,
+        SIG_BYTES,
+    )
+}
 
-        loop {
-            let read = self.r.read(&mut buf[buf_tail..bufsize]).await?;
-            buf_tail += read;
+/// Returns the number of segments that a payload of `plaintext_len` bytes is sealed into.
+///
+/// The first segments carry less plaintext, since they are prefixed with the (length of the)
+/// signing policy. The final segment is always present, even if it carries no plaintext.
+pub fn total_segments(
+    plaintext_len: u64,
+    segment_size: u32,
+    signing_policy: &Policy,
+) -> Result<u32, Error> {
+    let prefix_len = prefix_len_checked(segment_size, signing_policy)?;
+    let segments = prefix_len
+        .checked_add(plaintext_len)
+        .ok_or(Error::ConstraintViolation)?
+        / u64::from(segment_size)
+        + 1;
 
-            if buf_tail == bufsize {
-                dec.unwrap(&mut buf).unwrap();
+    Ok(u32::try_from(segments)?)
+}
 
-                if counter == 0 {
-                    pol_id = extract_policy(&mut buf)?;
-                }
+/// Returns the index of the segment that carries the plaintext byte at `offset`.
+///
+/// An offset equal to the length of the plaintext maps to the final segment. See
+/// [`total_segments`] for the framing.
+pub fn segment_of_offset(
+    offset: u64,
+    segment_size: u32,
+    signing_policy: &Policy,
+) -> Result<u32, Error> {
+    let prefix_len = prefix_len_checked(segment_size, signing_policy)?;
+    let index = prefix_len
+        .checked_add(offset)
+        .ok_or(Error::ConstraintViolation)?
+        / u64::from(segment_size);
 
-                let m = verify_segment(
-                    &buf,
-                    &mut self.verifier,
-                    &self.vk,
-                    &pol_id.as_ref().unwrap().1,
-                    counter,
-                    false,
-                )?;
+    Ok(u32::try_from(index)?)
+}
 
-                w.write_all(m).await?;
+/// Returns the length of the signing policy prefix, see [`policy_segments`].
+fn prefix_len_checked(segment_size: u32, signing_policy: &Policy) -> Result<u64, Error> {
+    let pol_len = bincode::serialized_size(signing_policy)?;
+    let chunk_size = u64::from(segment_size)
+        .checked_sub(POL_SIZE_SIZE as u64)
+        .filter(|&n| n > 0)
+        .ok_or(Error::ConstraintViolation)?;
 
-                buf_tail = 0;
-                buf.resize(bufsize, 0);
-                counter += 1;
-            } else if read == 0 {
-                buf.truncate(buf_tail);
-                dec.unwrap_last(&mut buf).unwrap();
+    // Every segment that carries a part of the policy is prefixed with the length of that part.
+    let chunks = pol_len.saturating_sub(1) / chunk_size + 1;
 
-                if counter == 0 {
-                    pol_id = extract_policy(&mut buf)?;
-                }
+    Ok(pol_len + chunks * POL_SIZE_SIZE as u64)
+}
 
-                let m = verify_segment(
-                    &buf,
-                    &mut self.verifier,
-                    &self.vk,
-                    &pol_id.as_ref().unwrap().1,
-                    counter,
-                    true,
-                )?;
+fn payload_overhead(
+    plaintext_len: u64,
+    segment_size: u32,
+    signing_policy: &Policy,
+    signature_mode: SignatureMode,
+    sig_bytes: usize,
+) -> Result<u64, Error> {
+    let prefix_len = prefix_len_checked(segment_size, signing_policy)?;
+    let segments = (prefix_len + plaintext_len) / u64::from(segment_size) + 1;
+    let signatures = match signature_mode {
+        SignatureMode::PerSegment => segments,
+        SignatureMode::Detached => 1,
+        SignatureMode::Unsigned => 0,
+    };
 
-                w.write_all(m).await?;
+    Ok(prefix_len + segments * STREAM_TAG_SIZE as u64 + signatures * sig_bytes as u64)
+}
 
-                break;
-            }
-        }
+/// Reads the preamble, header and header signature from the start of a sealed output of `len`
+/// bytes, see [`Sealer::resume`].
+///
+/// Returns `None` if the output ends before the header signature does.
+async fn read_header_prefix<W: AsyncRead + Unpin>(
+    w: &mut W,
+    len: u64,
+) -> Result<Option<Vec<u8>>, Error> {
+    if len < PREAMBLE_SIZE as u64 {
+        return Ok(None);
+    }
 
-        w.close().await?;
+    let mut prefix = vec![0u8; PREAMBLE_SIZE];
+    w.read_exact(&mut prefix).await?;
+    let (_, header_len) = preamble_checked(&prefix)?;
+
+    let sig_len_end = PREAMBLE_SIZE + header_len + SIG_SIZE_SIZE;
+    if len < sig_len_end as u64 {
+        return Ok(None);
+    }
+
+    prefix.resize(sig_len_end, 0);
+    w.read_exact(&mut prefix[PREAMBLE_SIZE..]).await?;
+
+    let mut sig_len_bytes = [0u8; SIG_SIZE_SIZE];
+    sig_len_bytes.copy_from_slice(&prefix[sig_len_end - SIG_SIZE_SIZE..]);
+    let end = sig_len_end + header_sig_len_checked(sig_len_bytes)?;
+    if len < end as u64 {
+        return Ok(None);
+    }
+
+    prefix.resize(end, 0);
+    w.read_exact(&mut prefix[sig_len_end..]).await?;
+
+    Ok(Some(prefix))
+}
+
+/// Seals all data that is written into it, see [`Sealer::into_writer`].
+///
+/// Segments are sealed and written to the inner writer as soon as they are full. Flushing does
+/// not seal a partial segment, only closing writes the final segment.
+pub struct SealWriter<'r, Rng, W, S = Signer> {
+    rng: &'r mut Rng,
+    w: W,
+    // The DEM, taken when the final segment has been sealed.
+    enc: Option<Deck>,
+    signer: S,
+    signature_mode: SignatureMode,
+    signing_key: SigningKeyExt,
+    segment_size: usize,
+    // The plaintext of the current segment.
+    buf: Vec<u8>,
+    // The start of the bytes in the buffer that have not been signed yet.
+    start: usize,
+    counter: u32,
+    // Sealed bytes that have not been written to the inner writer yet.
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<Rng, W, S> core::fmt::Debug for SealWriter<'_, Rng, W, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SealWriter")
+            .field("segment_size", &self.segment_size)
+            .field("counter", &self.counter)
+            .field("closed", &self.enc.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+fn to_io_error(e: Error) -> futures::io::Error {
+    futures::io::Error::new(futures::io::ErrorKind::Other, e.to_string())
+}
+
+impl<Rng, W, S> SealWriter<'_, Rng, W, S>
+where
+    Rng: RngCore + CryptoRng,
+    W: AsyncWrite + Unpin,
+    S: StreamSigner,
+{
+    /// Writes the pending sealed bytes to the inner writer.
+    fn poll_write_out(&mut self, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        while self.out_pos < self.out.len() {
+            let n = ready!(Pin::new(&mut self.w).poll_write(cx, &self.out[self.out_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(futures::io::ErrorKind::WriteZero.into()));
+            }
+            self.out_pos += n;
+        }
+
+        self.out.clear();
+        self.out_pos = 0;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Seals the current segment into the pending output, which must be empty.
+    fn seal_segment(&mut self, is_last: bool) -> Result<(), Error> {
+        let mut buf = core::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(self.segment_size + S::SIG_BYTES + STREAM_TAG_SIZE),
+        );
+
+        if is_last || self.signature_mode == SignatureMode::PerSegment {
+            sign_segment(
+                &mut buf,
+                self.start,
+                &mut self.signer,
+                &self.signing_key,
+                self.counter,
+                is_last,
+                self.rng,
+            )?;
+        } else {
+            self.signer.update(&buf[self.start..]);
+        }
+
+        if is_last {
+            let enc = self.enc.take().ok_or(Error::ConstraintViolation)?;
+            enc.wrap_last(&mut buf).map_err(|_e| Error::Symmetric)?;
+        } else {
+            let enc = self.enc.as_mut().ok_or(Error::ConstraintViolation)?;
+            enc.wrap(&mut buf).map_err(|_e| Error::Symmetric)?;
+            self.counter = self.counter.checked_add(1).ok_or(Error::Symmetric)?;
+        }
+
+        self.out = buf;
+        self.out_pos = 0;
+        self.start = 0;
+
+        Ok(())
+    }
+}
+
+impl<Rng, W, S> AsyncWrite for SealWriter<'_, Rng, W, S>
+where
+    Rng: RngCore + CryptoRng,
+    W: AsyncWrite + Unpin,
+    S: StreamSigner,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<futures::io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.enc.is_none() {
+            return Poll::Ready(Err(futures::io::Error::new(
+                futures::io::ErrorKind::Other,
+                "write after close",
+            )));
+        }
+
+        ready!(this.poll_write_out(cx))?;
+
+        let n = data.len().min(this.segment_size - this.buf.len());
+        this.buf.extend_from_slice(&data[..n]);
+
+        if this.buf.len() == this.segment_size {
+            this.seal_segment(false).map_err(to_io_error)?;
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.w).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_write_out(cx))?;
+
+        if this.enc.is_some() {
+            this.seal_segment(true).map_err(to_io_error)?;
+            ready!(this.poll_write_out(cx))?;
+        }
+
+        ready!(Pin::new(&mut this.w).poll_flush(cx))?;
+        Pin::new(&mut this.w).poll_close(cx)
+    }
+}
+
+impl<R, K, V> Unsealer<R, UnsealerStreamConfig<V>, K>
+where
+    R: AsyncRead + Unpin,
+    K: Scheme,
+    V: StreamVerifier,
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    /// Create a new [`Unsealer`] that starts reading from an [`AsyncRead`].
+    ///
+    /// Errors if the bytestream is not a legitimate PostGuard bytestream.
+    pub async fn new(r: R, pk: &VerifyingKey) -> Result<Self, Error> {
+        Self::new_with_max_header_size(r, pk, MAX_HEADER_SIZE).await
+    }
+
+    /// Create a new [`Unsealer`] like [`Unsealer::new`], accepting at most `max_header_size`
+    /// bytes of header and of header signature each.
+    ///
+    /// The declared lengths are checked before anything is allocated or read. Larger lengths
+    /// yield [`Error::ConstraintViolation`]. Lengths within `max_header_size` that exceed the
+    /// limits of the format still yield [`Error::BadLengthPrefix`], as for [`Unsealer::new`].
+    pub async fn new_with_max_header_size(
+        mut r: R,
+        pk: &VerifyingKey,
+        max_header_size: usize,
+    ) -> Result<Self, Error> {
+        let mut preamble = [0u8; PREAMBLE_SIZE];
+        r.read_exact(&mut preamble)
+            .map_err(|_e| Error::HeaderTooShort)
+            .await?;
+
+        // A length beyond both limits is reported as exceeding the configured one.
+        let exceeded = |e: Error, format_limit: usize| match e {
+            Error::BadLengthPrefix if max_header_size < format_limit => Error::ConstraintViolation,
+            e => e,
+        };
+
+        let (version, header_len) =
+            preamble_checked(&preamble).map_err(|e| exceeded(e, MAX_HEADER_SIZE))?;
+        if header_len > max_header_size {
+            return Err(Error::ConstraintViolation);
+        }
+
+        let mut header_raw = Vec::with_capacity(header_len);
+
+        // Limit reader to not read past header
+        let mut r = r.take(header_len as u64);
+
+        r.read_to_end(&mut header_raw).await?;
+
+        if header_raw.len() != header_len {
+            return Err(Error::HeaderTooShort);
+        }
+
+        let mut r = r.into_inner();
+
+        let mut header_sig_len_bytes = [0u8; SIG_SIZE_SIZE];
+        r.read_exact(&mut header_sig_len_bytes)
+            .map_err(|_e| Error::HeaderTooShort)
+            .await?;
+        let header_sig_len = header_sig_len_checked(header_sig_len_bytes)
+            .map_err(|e| exceeded(e, MAX_HEADER_SIG_SIZE))?;
+        if header_sig_len > max_header_size {
+            return Err(Error::ConstraintViolation);
+        }
+
+        let mut header_sig_raw = Vec::with_capacity(header_sig_len);
+        let mut r = r.take(header_sig_len as u64);
+
+        r.read_to_end(&mut header_sig_raw).await?;
+
+        if header_sig_raw.len() != header_sig_len {
+            return Err(Error::HeaderTooShort);
+        }
+
+        // An empty header signature marks an unsigned stream, which the header must confirm.
+        let (header, pub_id) = if header_sig_raw.is_empty() {
+            (
+                header_unsigned::<K>(version, &header_raw)?,
+                Policy::default(),
+            )
+        } else {
+            let h_sig_ext: SignatureExt =
+                bincode::deserialize(&header_sig_raw).map_err(Error::HeaderDeserialize)?;
+            let header = header_verified::<K>(version, &header_raw, &h_sig_ext, pk)?;
+
+            if header.signature_mode == SignatureMode::Unsigned {
+                return Err(Error::FormatViolation(
+                    "signed header declares an unsigned payload".to_string(),
+                ));
+            }
+
+            (header, h_sig_ext.pol)
+        };
+
+        let verifier = Verifier::default().chain(&header_raw);
+        let header: Header<K> = header.scheme_checked()?;
+        let StreamMode {
+            segment_size,
+            size_hint,
+        } = stream_mode_checked(&header)?;
+
+        Ok(Unsealer {
+            version,
+            header,
+            pub_id,
+            header_raw,
+            config: UnsealerStreamConfig {
+                segment_size,
+                size_hint,
+                abort: None,
+                scheme: PhantomData,
+            },
+            r: r.into_inner(), // This (new) reader is locked to the payload.
+            verifier,
+            vk: pk.clone(),
+        })
+    }
+
+    /// Unseal the remaining data (which is now only payload) into an [`AsyncWrite`].
+    pub async fn unseal<W: AsyncWrite + Unpin>(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        w: W,
+    ) -> Result<VerificationResult, Error> {
+        match self.header.compression {
+            Compression::None => self.unseal_raw(ident, usk, w).await,
+            #[cfg(feature = "compression")]
+            Compression::Deflate => self.unseal_raw(ident, usk, DeflateDecoder::new(w)).await,
+            #[cfg(not(feature = "compression"))]
+            c => Err(Error::CompressionNotSupported(c)),
+        }
+    }
+
+    /// Unseal the remaining data (which is now only payload) into a [`tokio::io::AsyncWrite`].
+    #[cfg(feature = "tokio")]
+    pub async fn unseal_tokio<W: tokio::io::AsyncWrite + Unpin>(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        w: W,
+    ) -> Result<VerificationResult, Error> {
+        self.unseal(ident, usk, TokioCompat(w)).await
+    }
+
+    /// The bounds on the size of the plaintext (in bytes), as given by the sealer.
+    ///
+    /// Taken from the (signed) header, but only a hint: it is neither checked during unsealing,
+    /// nor is it reliable if the sealer did not know the size in advance. Can be used to, e.g.,
+    /// preallocate the output or display progress.
+    pub fn size_hint(&self) -> (u64, Option<u64>) {
+        self.config.size_hint
+    }
+
+    /// Optional: Abort unsealing once `abort` is set, e.g., when the user cancels a download.
+    ///
+    /// The signal is checked before every segment. Once set, unsealing stops with
+    /// [`Error::Aborted`] without reading the rest of the payload, and the writer is closed. The
+    /// plaintext that was written up to that point has been verified, but is incomplete.
+    pub fn with_abort_signal(mut self, abort: Arc<AtomicBool>) -> Self {
+        self.config.abort = Some(abort);
+        self
+    }
+
+    /// Replaces the reader of the payload.
+    fn map_reader<R2>(self, f: impl FnOnce(R) -> R2) -> Unsealer<R2, UnsealerStreamConfig<V>, K> {
+        Unsealer {
+            version: self.version,
+            header: self.header,
+            pub_id: self.pub_id,
+            header_raw: self.header_raw,
+            r: f(self.r),
+            config: self.config,
+            verifier: self.verifier,
+            vk: self.vk,
+        }
+    }
+
+    /// Unseal the remaining data using the first candidate that can decrypt it.
+    ///
+    /// Useful when the user holds keys for several recipients in the header, but does not know
+    /// which one was intended for them. Only the first segment is buffered to try the
+    /// candidates, the input is read only once. The identifier of the candidate that succeeded is
+    /// returned in [`VerificationResult::recipient`]. If none of the candidates authenticates the
+    /// first segment, this errors with [`Error::Segment`] for the first segment, with
+    /// [`SegmentFailure::WrongTag`].
+    pub async fn unseal_any<W: AsyncWrite + Unpin>(
+        mut self,
+        candidates: &[(&str, &UserSecretKey<K>)],
+        w: W,
+    ) -> Result<VerificationResult, Error> {
+        let bufsize = self.bufsize();
+        let capacity = self.segment_buffer_size();
+        let mut buf = vec![0u8; capacity];
+        let mut buf_tail = 0;
+        let mut eof = false;
+
+        loop {
+            let read = self.r.read(&mut buf[buf_tail..capacity]).await?;
+            buf_tail += read;
+
+            if buf_tail == capacity || (read == 0 && eof) {
+                break;
+            }
+
+            // See `unseal_segments`, EOF must be confirmed by a second empty read.
+            eof = read == 0;
+        }
+
+        // If the buffer is full, bytes follow the first segment, so it is not the final one.
+        let first_len = if buf_tail == capacity {
+            bufsize
+        } else {
+            buf_tail
+        };
+
+        for &(ident, usk) in candidates {
+            let dec = match self.deck(ident, usk) {
+                Ok(dec) => dec,
+                Err(_) => continue,
+            };
+
+            let mut first = buf[..first_len].to_vec();
+            if dec.clone().unwrap(&mut first).is_err() {
+                continue;
+            }
+
+            return match self.header.compression {
+                Compression::None => self.unseal_segments(ident, dec, buf, buf_tail, w).await,
+                #[cfg(feature = "compression")]
+                Compression::Deflate => {
+                    self.unseal_segments(ident, dec, buf, buf_tail, DeflateDecoder::new(w))
+                        .await
+                }
+                #[cfg(not(feature = "compression"))]
+                c => Err(Error::CompressionNotSupported(c)),
+            };
+        }
+
+        // None of the candidates authenticates the first segment.
+        Err(Error::Segment {
+            index: 0,
+            offset: 0,
+            failure: SegmentFailure::WrongTag,
+        })
+    }
+
+    /// The size (in bytes) of the buffer used to read the segments of the payload.
+    ///
+    /// This is the size of a sealed segment, plus the bytes that are read ahead to know whether a
+    /// segment is the final one. The segment size is taken from the header, which is bounded by
+    /// [`MAX_SYMMETRIC_CHUNK_SIZE`][crate::consts::MAX_SYMMETRIC_CHUNK_SIZE]. Unsealing allocates
+    /// two buffers of this size once, which are reused for all segments. Callers can use this
+    /// to, e.g., reserve memory before unsealing.
+    pub fn segment_buffer_size(&self) -> usize {
+        // One byte beyond the lookahead tells whether a segment is followed by another one, also
+        // when the final segment happens to be as large as the others.
+        self.bufsize() + self.lookahead() + 1
+    }
+
+    /// The size of a sealed segment, except for the final one.
+    fn bufsize(&self) -> usize {
+        match self.header.signature_mode {
+            SignatureMode::PerSegment => {
+                self.config.segment_size as usize + V::SIG_BYTES + STREAM_TAG_SIZE
+            }
+            SignatureMode::Detached | SignatureMode::Unsigned => {
+                self.config.segment_size as usize + STREAM_TAG_SIZE
+            }
+        }
+    }
+
+    /// The number of bytes to read beyond a segment to know whether it is the final one.
+    ///
+    /// In detached mode, the final segment can be larger than the others, since only it carries
+    /// a signature.
+    fn lookahead(&self) -> usize {
+        match self.header.signature_mode {
+            SignatureMode::PerSegment | SignatureMode::Unsigned => 0,
+            SignatureMode::Detached => V::SIG_BYTES,
+        }
+    }
+
+    /// Sets up the DEM for a recipient.
+    fn deck(&self, ident: &str, usk: &UserSecretKey<K>) -> Result<Deck, Error> {
+        let rec_info = self
+            .header
+            .recipients
+            .get(ident)
+            .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
+
+        let key = dem_key(rec_info.decaps(usk)?)?;
+
+        let Algorithm::Aes128Gcm(iv) = self.header.algo;
+        let nonce = &iv.0[..STREAM_NONCE_SIZE];
+
+        let dec = Deck::new(&key[..], nonce).map_err(|_e| Error::Symmetric)?;
+
+        if !self.header.bind_header {
+            return Ok(dec);
+        }
+
+        Ok(dec.with_ad(&header_ad(&self.header_raw)))
+    }
+
+    async fn unseal_raw<W: AsyncWrite + Unpin>(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        w: W,
+    ) -> Result<VerificationResult, Error> {
+        let dec = self.deck(ident, usk)?;
+        let buf = vec![0u8; self.segment_buffer_size()];
+
+        self.unseal_segments(ident, dec, buf, 0, w).await
+    }
+
+    /// Decrypts and verifies all segments.
+    ///
+    /// The buffer may already contain the first `buf_tail` bytes of the payload.
+    async fn unseal_segments<W: AsyncWrite + Unpin>(
+        mut self,
+        ident: &str,
+        dec: Deck,
+        buf: Vec<u8>,
+        buf_tail: usize,
+        mut w: W,
+    ) -> Result<VerificationResult, Error> {
+        let pub_id = core::mem::take(&mut self.pub_id);
+        let recipient_policy = self
+            .header
+            .recipients
+            .get(ident)
+            .map(|rec_info| rec_info.policy.clone())
+            .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
+        let mut segments = self.into_segments(dec, buf, buf_tail);
+
+        loop {
+            match segments.next_segment().await {
+                Ok(Some(m)) => w.write_all(m).await?,
+                Ok(None) => break,
+                Err(Error::Aborted) => {
+                    w.close().await?;
+                    return Err(Error::Aborted);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        w.close().await?;
+
+        // The counter is that of the final segment, which cannot be the last possible one.
+        let count = segments.counter + 1;
+
+        if segments.signature_mode == SignatureMode::Unsigned {
+            return Ok(VerificationResult {
+                segments: count,
+                ..VerificationResult::unsigned(ident, recipient_policy)
+            });
+        }
+
+        Ok(VerificationResult {
+            segments: count,
+            ..VerificationResult::new(pub_id, segments.pol_id.unwrap().0, ident, recipient_policy)
+        })
+    }
+
+    /// Sets up the reading of the segments.
+    ///
+    /// The buffer may already contain the first `buf_tail` bytes of the payload.
+    fn into_segments(self, dec: Deck, mut buf: Vec<u8>, buf_tail: usize) -> Segments<R, V> {
+        let bufsize = self.bufsize();
+        let capacity = self.segment_buffer_size();
+        debug_assert!(buf_tail <= capacity);
+        buf.resize(capacity, 0);
+
+        Segments {
+            // The header signature was verified using ibs::gg, the segments use the scheme of
+            // choice.
+            verifier: V::default().chain(&self.header_raw),
+            signature_mode: self.header.signature_mode,
+            r: self.r,
+            dec,
+            vk: self.vk,
+            pol_id: None,
+            pol_bytes: Vec::new(),
+            deferred: Vec::new(),
+            buf,
+            buf_tail,
+            seg: Vec::with_capacity(capacity),
+            bufsize,
+            capacity,
+            counter: 0,
+            state: ReadState::Reading,
+            abort: self.config.abort,
+        }
+    }
+
+    /// Turns the [`Unsealer`] into a [`Stream`] of the plaintext of the segments.
+    ///
+    /// Every item is the verified plaintext of one segment, possibly empty. The stream ends after
+    /// the final segment, or after the first error, e.g., a segment that could not be
+    /// authenticated. The input is only read when the stream is polled, at most one segment
+    /// ahead. Compression is not supported.
+    ///
+    /// In [`SignatureMode::Detached`], the segments are only signed as a whole. Their plaintext
+    /// can only be trusted once the stream has ended without errors.
+    pub fn into_stream(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+    ) -> impl Stream<Item = Result<Vec<u8>, Error>> {
+        let segments = if self.header.compression != Compression::None {
+            Err(Error::CompressionNotSupported(self.header.compression))
+        } else {
+            match self.deck(ident, usk) {
+                Ok(dec) => {
+                    let buf = vec![0u8; self.segment_buffer_size()];
+                    Ok(self.into_segments(dec, buf, 0))
+                }
+                Err(e) => Err(e),
+            }
+        };
+
+        stream::unfold(Some(segments), |state| async move {
+            let mut segments = match state? {
+                Ok(segments) => segments,
+                Err(e) => return Some((Err(e), None)),
+            };
+
+            let m = match segments.next_segment().await {
+                Ok(Some(m)) => m.to_vec(),
+                Ok(None) => return None,
+                Err(e) => return Some((Err(e), None)),
+            };
+
+            Some((Ok(m), Some(Ok(segments))))
+        })
+    }
+
+    /// Verify the remaining data (which is now only payload) without outputting the plaintext.
+    ///
+    /// Every segment is still decrypted, and its tag and signature are checked up to and
+    /// including the last segment.
+    pub async fn verify_only(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+    ) -> Result<VerificationResult, Error> {
+        self.unseal(ident, usk, futures::io::sink()).await
+    }
+
+    /// Unseal the remaining data into an [`AsyncWrite`], writing at most `max_bytes` bytes.
+    ///
+    /// Errors with [`Error::ConstraintViolation`] once the plaintext would exceed `max_bytes`,
+    /// regardless of the size hint in the header. The segment that would exceed the limit is not
+    /// written.
+    pub async fn unseal_bounded<W: AsyncWrite + Unpin>(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        w: W,
+        max_bytes: u64,
+    ) -> Result<VerificationResult, Error> {
+        let mut bounded = BoundedWriter {
+            w,
+            remaining: max_bytes,
+            exceeded: false,
+        };
+
+        let res = self.unseal(ident, usk, &mut bounded).await;
+        if bounded.exceeded {
+            return Err(Error::ConstraintViolation);
+        }
+
+        res
+    }
+
+    /// Re-seals the remaining payload using `sealer`, e.g., to another set of recipients.
+    ///
+    /// Every segment is decrypted and verified, and its plaintext is immediately sealed again, so
+    /// that the plaintext never leaves memory. The [`VerificationResult`] of this payload is
+    /// returned and recorded as JSON in the metadata of the new header, see
+    /// [`Unsealer::metadata`]. It is determined once the signing policy has been read. In
+    /// [`SignatureMode::Detached`], the payload is only verified as a whole at the end. If that
+    /// fails, the final segment of the output is not written, such that it cannot be unsealed
+    /// either. Compression is not supported.
+    pub async fn rewrap<Rng, W, S>(
+        mut self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        sealer: Sealer<'_, Rng, SealerStreamConfig<S>, K>,
+        w: W,
+    ) -> Result<VerificationResult, Error>
+    where
+        Rng: RngCore + CryptoRng,
+        W: AsyncWrite + Unpin,
+        S: StreamSigner,
+        Header<K>: Serialize,
+    {
+        if self.header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(self.header.compression));
+        }
+
+        let dec = self.deck(ident, usk)?;
+        let pub_id = core::mem::take(&mut self.pub_id);
+        let recipient_policy = self
+            .header
+            .recipients
+            .get(ident)
+            .map(|rec_info| rec_info.policy.clone())
+            .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
+        let unsigned = self.header.signature_mode == SignatureMode::Unsigned;
+        let buf = vec![0u8; self.segment_buffer_size()];
+        let mut segments = self.into_segments(dec, buf, 0);
+
+        // Read the leading segments, up to and including the one that completes the policy.
+        let mut first = Vec::new();
+        while !unsigned && segments.pol_id.is_none() {
+            match segments.next_segment().await? {
+                Some(m) => first.extend_from_slice(m),
+                None => break,
+            }
+        }
+
+        let vr = match &segments.pol_id {
+            _ if unsigned => VerificationResult::unsigned(ident, recipient_policy),
+            Some((pol, _)) => VerificationResult::new(pub_id, pol.clone(), ident, recipient_policy),
+            None => return Err(Error::FormatViolation("missing policy".to_string())),
+        };
+        // The remaining segments are not read yet.
+        let vr = VerificationResult { segments: 0, ..vr };
+        let metadata = serde_json::to_vec(&vr).map_err(Error::Json)?;
+
+        let mut writer = sealer.with_metadata(&metadata).into_writer(w)?;
+        writer.write_all(&first).await?;
+
+        while let Some(m) = segments.next_segment().await? {
+            writer.write_all(m).await?;
+        }
+
+        writer.close().await?;
+
+        Ok(vr)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R, K, V> Unsealer<TokioCompat<R>, UnsealerStreamConfig<V>, K>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    K: Scheme,
+    V: StreamVerifier,
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    /// Create a new [`Unsealer`] that starts reading from a [`tokio::io::AsyncRead`].
+    ///
+    /// Errors if the bytestream is not a legitimate PostGuard bytestream.
+    pub async fn new_tokio(r: R, pk: &VerifyingKey) -> Result<Self, Error> {
+        Self::new(TokioCompat(r), pk).await
+    }
+}
+
+impl<R, K, V> Unsealer<R, UnsealerStreamConfig<V>, K>
+where
+    R: AsyncBufRead + Unpin,
+    K: Scheme,
+    V: StreamVerifier,
+    Header<K>: DeserializeOwned,
+    RecipientHeader<K>: DeserializeOwned,
+{
+    /// Unseal the remaining data (which is now only payload) from an [`AsyncBufRead`] into an
+    /// [`AsyncWrite`].
+    ///
+    /// Behaves like [`Unsealer::unseal`], but fills the segments directly from the internal
+    /// buffer of the reader, which is only refilled once it has been drained. On an unbuffered
+    /// input, e.g., a socket wrapped in a [`futures::io::BufReader`], this requires fewer reads
+    /// of the underlying input.
+    pub async fn unseal_buffered<W: AsyncWrite + Unpin>(
+        self,
+        ident: &str,
+        usk: &UserSecretKey<K>,
+        w: W,
+    ) -> Result<VerificationResult, Error> {
+        self.map_reader(BufFill).unseal(ident, usk, w).await
+    }
+}
+
+/// Measures the throughput of sealing or unsealing, e.g., to report progress in a CLI.
+///
+/// The meter does not affect the processing of the payload, and it does not read a clock itself:
+/// whoever reports progress, e.g., a reader or writer wrapped around the payload, records the
+/// number of bytes processed along with the current time, such as the time elapsed since an
+/// `Instant`. The rate is computed over a sliding window of the most recent records.
+#[derive(Debug, Clone)]
+pub struct ThroughputMeter {
+    window: Duration,
+    total: u64,
+    // The time of every record within the window and the total after it, preceded by the last
+    // record before the window.
+    records: VecDeque<(Duration, u64)>,
+}
+
+impl ThroughputMeter {
+    /// Creates a meter that computes the rate over the last `window`, starting at time `now`.
+    pub fn new(window: Duration, now: Duration) -> Self {
+        let mut records = VecDeque::new();
+        records.push_back((now, 0));
+
+        ThroughputMeter {
+            window,
+            total: 0,
+            records,
+        }
+    }
+
+    /// Records that `bytes` more bytes have been processed at time `now`.
+    ///
+    /// The time is expected to be non-decreasing.
+    pub fn record(&mut self, bytes: u64, now: Duration) {
+        self.total = self.total.saturating_add(bytes);
+        self.records.push_back((now, self.total));
+
+        // Keep the last record before the window, from which the rate is measured.
+        while self.records.len() > 2 && now.saturating_sub(self.records[1].0) >= self.window {
+            self.records.pop_front();
+        }
+    }
+
+    /// The total number of bytes recorded.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The throughput (in bytes per second) over the window, or zero if no time has passed.
+    pub fn rate(&self) -> f64 {
+        match (self.records.front(), self.records.back()) {
+            (Some(&(t0, n0)), Some(&(t1, n1))) if t1 > t0 => {
+                (n1 - n0) as f64 / (t1 - t0).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Forwards writes to the inner writer, but fails any write that exceeds the limit.
+struct BoundedWriter<W> {
+    w: W,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BoundedWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<futures::io::Result<usize>> {
+        let this = self.get_mut();
+
+        if buf.len() as u64 > this.remaining {
+            this.exceeded = true;
+            return Poll::Ready(Err(futures::io::Error::new(
+                futures::io::ErrorKind::Other,
+                "output limit exceeded",
+            )));
+        }
+
+        let n = ready!(Pin::new(&mut this.w).poll_write(cx, buf))?;
+        this.remaining -= n as u64;
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        Pin::new(&mut self.get_mut().w).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        Pin::new(&mut self.get_mut().w).poll_close(cx)
+    }
+}
+
+/// Forwards writes to the inner writer, counting the bytes written.
+struct CountingWriter<W> {
+    w: W,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<futures::io::Result<usize>> {
+        let this = self.get_mut();
+
+        let n = ready!(Pin::new(&mut this.w).poll_write(cx, buf))?;
+        this.written += n as u64;
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        Pin::new(&mut self.get_mut().w).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<futures::io::Result<()>> {
+        Pin::new(&mut self.get_mut().w).poll_close(cx)
+    }
+}
+
+/// Reads through the internal buffer of an [`AsyncBufRead`], filling as much of the output as
+/// is buffered before refilling.
+struct BufFill<R>(R);
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for BufFill<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<futures::io::Result<usize>> {
+        let this = self.get_mut();
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let available = match Pin::new(&mut this.0).poll_fill_buf(cx) {
+                Poll::Ready(res) => res?,
+                // Return what has been copied so far, the next read waits for more input.
+                Poll::Pending if filled > 0 => break,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if available.is_empty() {
+                break;
+            }
+
+            let n = available.len().min(buf.len() - filled);
+            buf[filled..filled + n].copy_from_slice(&available[..n]);
+            Pin::new(&mut this.0).consume(n);
+            filled += n;
+        }
+
+        Poll::Ready(Ok(filled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        overhead_for, segment_of_offset, total_segments, Sealer, SealerStreamConfig, SegmentLayout,
+        StreamSigner, StreamVerifier, ThroughputMeter, Unsealer, UnsealerStreamConfig,
+    };
+    use crate::artifacts::{SigningKey, VerifyingKey};
+    use crate::client::{
+        Algorithm, Header, Mode, SigScheme, SignatureExt, SignatureMode, VerificationResult,
+    };
+    use crate::error::{Error, SegmentFailure};
+    use crate::identity::{EncryptionPolicy, Policy};
+    use crate::test::TestSetup;
+    use crate::{
+        HEADER_SIZE_SIZE, KEY_SIZE, POL_SIZE_SIZE, PREAMBLE_SIZE, PRELUDE, PRELUDE_SIZE,
+        SIG_SIZE_SIZE, STREAM_NONCE_SIZE, STREAM_TAG_SIZE, SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
+        VERSION_SIZE, VERSION_V2, VERSION_V3, VERSION_V4,
+    };
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use futures::{executor::block_on, io::AllowStdIo};
+    use ibs::gg::{Signer, Verifier, SIG_BYTES};
+    use rand::{thread_rng, CryptoRng, Rng, RngCore};
+    use reck::Deck;
+    use std::io::Cursor;
+    use tokio::io::AsyncReadExt;
+
+    const LENGTHS: &[u32] = &[
+        1,
+        512,
+        SYMMETRIC_CRYPTO_DEFAULT_CHUNK - 3,
+        SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
+        SYMMETRIC_CRYPTO_DEFAULT_CHUNK + 3,
+        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
+        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK + 16,
+        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK - 17,
+    ];
+
+    fn seal_helper(setup: &TestSetup, plain: &[u8]) -> Vec<u8> {
+        seal_mode_helper(setup, plain, SignatureMode::PerSegment)
+    }
+
+    fn seal_mode_helper(setup: &TestSetup, plain: &[u8], mode: SignatureMode) -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+
+        let mut input = AllowStdIo::new(Cursor::new(plain));
+        let mut output = AllowStdIo::new(Vec::new());
+
+        let signing_key = &setup.signing_keys[0];
+
+        block_on(async {
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &signing_key,
+                &mut rng,
+            )
+            .unwrap()
+            .with_signature_mode(mode)
+            .seal(&mut input, &mut output)
+            .await
+            .unwrap();
+        });
+
+        output.into_inner()
+    }
+
+    /// The offset of the first segment, following the header and its signature.
+    fn payload_offset(ct: &[u8]) -> usize {
+        let header_len = u32::from_be_bytes(
+            ct[PREAMBLE_SIZE - HEADER_SIZE_SIZE..PREAMBLE_SIZE]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let sig_offset = PREAMBLE_SIZE + header_len;
+        let sig_len = u32::from_be_bytes(
+            ct[sig_offset..sig_offset + SIG_SIZE_SIZE]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+
+        sig_offset + SIG_SIZE_SIZE + sig_len
+    }
+
+    fn unseal_helper(setup: &TestSetup, ct: &[u8]) -> (Vec<u8>, VerificationResult) {
+        try_unseal_helper(setup, ct).unwrap()
+    }
+
+    fn try_unseal_helper(
+        setup: &TestSetup,
+        ct: &[u8],
+    ) -> Result<(Vec<u8>, VerificationResult), Error> {
+        let mut input = AllowStdIo::new(Cursor::new(ct));
+        let mut output = AllowStdIo::new(Vec::new());
+
+        // sometimes decrypt as Bob, sometimes decrypt as Charlie
+        let (id, usk_id) = if thread_rng().gen::<bool>() {
+            ("Bob", setup.usks[2].clone())
+        } else {
+            ("Charlie", setup.usks[3].clone())
+        };
+
+        let vr = block_on(async {
+            let unsealer = Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                .await
+                .unwrap();
+
+            // Normally, a user would need to retrieve a usk here via the PKG,
+            // but in this case we own the master key pair.
+            unsealer.unseal(id, &usk_id, &mut output).await
+        })?;
+
+        Ok((output.into_inner(), vr))
+    }
+
+    /// Produces a validly signed preamble and header with a custom mode, without payload.
+    fn header_helper(setup: &TestSetup, mode: Mode) -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+        let (header, _) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+
+        signed_header_helper(setup, &header.with_mode(mode))
+    }
+
+    fn signed_header_helper(setup: &TestSetup, header: &Header) -> Vec<u8> {
+        signed_raw_header_helper(setup, VERSION_V4, &bincode::serialize(&header).unwrap())
+    }
+
+    fn signed_raw_header_helper(setup: &TestSetup, version: u16, header_vec: &[u8]) -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+        let signing_key = &setup.signing_keys[0];
+
+        let sig = Signer::default()
+            .chain(&header_vec)
+            .sign(&signing_key.key.0, &mut rng);
+        let sig_vec = bincode::serialize(&SignatureExt {
+            sig,
+            pol: signing_key.policy.clone(),
+        })
+        .unwrap();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&PRELUDE);
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(header_vec.len() as u32).to_be_bytes());
+        out.extend_from_slice(header_vec);
+        out.extend_from_slice(&(sig_vec.len() as u32).to_be_bytes());
+        out.extend_from_slice(&sig_vec);
+
+        out
+    }
+
+    fn seal_and_unseal(setup: &TestSetup, plain: Vec<u8>) {
+        let ct = seal_helper(setup, &plain);
+        let (plain2, vr) = unseal_helper(setup, &ct);
+
+        assert_eq!(&plain, &plain2);
+        assert_eq!(&vr.public, &setup.signing_keys[0].policy);
+        assert_eq!(vr.private, None);
+    }
+
+    fn rand_vec(length: usize) -> Vec<u8> {
+        let mut vec = vec![0u8; length];
+        rand::thread_rng().fill_bytes(&mut vec);
+        vec
+    }
+
+    #[test]
+    fn test_reflection_seal_unsealer() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            seal_and_unseal(&setup, rand_vec(*l as usize));
+        }
+    }
+
+    #[test]
+    fn test_empty_plaintext() {
+        use futures::{pin_mut, StreamExt};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+
+        for mode in [
+            SignatureMode::PerSegment,
+            SignatureMode::Detached,
+            SignatureMode::Unsigned,
+        ] {
+            // The final segment is the only one, and carries at most the policy and signature.
+            let ct = seal_mode_helper(&setup, &[], mode);
+            let (plain, _) = unseal_helper(&setup, &ct);
+            assert!(plain.is_empty());
+
+            block_on(async {
+                let s = Unsealer::<_, UnsealerStreamConfig>::new(
+                    AllowStdIo::new(Cursor::new(ct)),
+                    &setup.ibs_pk,
+                )
+                .await
+                .unwrap()
+                .into_stream("Bob", &setup.usks[2]);
+                pin_mut!(s);
+
+                assert!(s.next().await.unwrap().unwrap().is_empty());
+                assert!(s.next().await.is_none());
+            });
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    fn seal_compressed_helper(setup: &TestSetup, plain: &[u8]) -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+
+        let mut input = AllowStdIo::new(Cursor::new(plain));
+        let mut output = AllowStdIo::new(Vec::new());
+
+        block_on(async {
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .with_compression(crate::client::Compression::Deflate)
+            .seal(&mut input, &mut output)
+            .await
+            .unwrap();
+        });
+
+        output.into_inner()
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_compression_incompressible() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            let plain = rand_vec(*l as usize);
+
+            let ct = seal_helper(&setup, &plain);
+            let ct_compressed = seal_compressed_helper(&setup, &plain);
+
+            // DEFLATE falls back to stored blocks, which only adds a small overhead, possibly
+            // spilling into one more segment.
+            let overhead = plain.len() / 1000 + 64 + ibs::gg::SIG_BYTES + STREAM_TAG_SIZE;
+            assert!(ct_compressed.len() <= ct.len() + overhead);
+
+            let (plain2, _) = unseal_helper(&setup, &ct_compressed);
+            assert_eq!(plain, plain2);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_compression_compressible() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            let plain: Vec<u8> = b"PostGuard compresses text-heavy payloads. "
+                .iter()
+                .cycle()
+                .take(*l as usize)
+                .copied()
+                .collect();
+
+            let ct = seal_helper(&setup, &plain);
+            let ct_compressed = seal_compressed_helper(&setup, &plain);
+
+            if *l > 4096 {
+                assert!(ct_compressed.len() < ct.len() / 2);
+            }
+
+            let (plain2, _) = unseal_helper(&setup, &ct_compressed);
+            assert_eq!(plain, plain2);
+        }
+    }
+
+    #[test]
+    fn test_seal_writer() {
+        use futures::io::AsyncWriteExt;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let signing_key = &setup.signing_keys[0];
+
+        for l in LENGTHS {
+            let plain = rand_vec(*l as usize);
+
+            // Seal twice using the same randomness, so that the output must be byte-identical.
+            let mut rng = StdRng::seed_from_u64(*l as u64);
+            let mut expected = Vec::new();
+            block_on(
+                Sealer::<_, SealerStreamConfig>::new(
+                    &setup.ibe_pk,
+                    &setup.policy,
+                    signing_key,
+                    &mut rng,
+                )
+                .unwrap()
+                .seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut expected),
+            )
+            .unwrap();
+
+            let mut rng = StdRng::seed_from_u64(*l as u64);
+            let mut output = Vec::new();
+            block_on(async {
+                let mut writer = Sealer::<_, SealerStreamConfig>::new(
+                    &setup.ibe_pk,
+                    &setup.policy,
+                    signing_key,
+                    &mut rng,
+                )
+                .unwrap()
+                .into_writer(&mut output)
+                .unwrap();
+
+                // Write in chunks that do not align with the segments.
+                for chunk in plain.chunks(1000) {
+                    writer.write_all(chunk).await.unwrap();
+                }
+
+                writer.close().await.unwrap();
+            });
+
+            assert_eq!(expected, output);
+
+            let (plain2, _) = unseal_helper(&setup, &output);
+            assert_eq!(plain, plain2);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_seal_tokio() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 17);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut expected = Vec::new();
+        block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut expected),
+        )
+        .unwrap();
+
+        // The tokio path produces the same bytes as the futures path.
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut ct = Vec::new();
+        block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .seal_tokio(&plain[..], &mut ct),
+        )
+        .unwrap();
+        assert_eq!(ct, expected);
+
+        let mut output = Vec::new();
+        block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new_tokio(&ct[..], &setup.ibs_pk)
+                .await
+                .unwrap()
+                .unseal_tokio("Bob", &setup.usks[2], &mut output)
+                .await
+                .unwrap();
+        });
+        assert_eq!(output, plain);
+    }
+
+    #[test]
+    fn test_seal_resume() {
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::AsyncWrite;
+        use ibe::kem::SharedSecret;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        // A writer that fails after `limit` bytes, like a connection that drops.
+        struct FailingWriter {
+            out: Vec<u8>,
+            limit: usize,
+        }
+
+        impl AsyncWrite for FailingWriter {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                let n = buf.len().min(self.limit - self.out.len());
+                if n == 0 {
+                    return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+                }
+
+                self.out.extend_from_slice(&buf[..n]);
+                Poll::Ready(Ok(n))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let signing_key = &setup.signing_keys[0];
+        let plain = rand_vec(5 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 17);
+
+        // The header and shared secret of the interrupted seal, which the caller keeps to resume.
+        let (header, ss) =
+            Header::new(&setup.ibe_pk, &setup.policy, &mut rand::thread_rng()).unwrap();
+
+        // Signing is randomized, the RNG is seeded to compare the output.
+        let seal = |w: &mut (dyn AsyncWrite + Unpin)| {
+            let mut rng = StdRng::seed_from_u64(42);
+            block_on(
+                Sealer::<_, SealerStreamConfig>::from_shared_secret(
+                    header.clone(),
+                    SharedSecret(ss.0),
+                    signing_key,
+                    &mut rng,
+                )
+                .unwrap()
+                .seal(&mut AllowStdIo::new(Cursor::new(&plain)), w),
+            )
+        };
+
+        let resume = |seed: u64, input: &mut Cursor<&Vec<u8>>, output: &mut Cursor<Vec<u8>>| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            block_on(
+                Sealer::<_, SealerStreamConfig>::from_shared_secret(
+                    header.clone(),
+                    SharedSecret(ss.0),
+                    signing_key,
+                    &mut rng,
+                )
+                .unwrap()
+                .resume(AllowStdIo::new(input), AllowStdIo::new(output)),
+            )
+        };
+
+        let mut expected = Vec::new();
+        seal(&mut expected).unwrap();
+
+        // Crash halfway the third segment.
+        let sealed_segment_size =
+            SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + SIG_BYTES + STREAM_TAG_SIZE;
+        let written = payload_offset(&expected) + 2 * sealed_segment_size;
+        let limit = written + 100;
+
+        let mut crashed = FailingWriter {
+            out: Vec::new(),
+            limit,
+        };
+        assert!(seal(&mut crashed).is_err());
+        assert_eq!(crashed.out.len(), limit);
+
+        // A new sealer has another header, under which the output cannot be continued.
+        let mut output = Cursor::new(crashed.out.clone());
+        let res = block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                signing_key,
+                &mut rand::thread_rng(),
+            )
+            .unwrap()
+            .resume(
+                AllowStdIo::new(Cursor::new(&plain)),
+                AllowStdIo::new(&mut output),
+            ),
+        );
+        assert!(matches!(res, Err(Error::HeaderMismatch)));
+        assert_eq!(output.into_inner(), crashed.out);
+
+        // Resume under the same header, using another RNG and an input that has been partially
+        // read. The segments that were written are kept.
+        let mut input = Cursor::new(&plain);
+        input.set_position(1000);
+        let mut output = Cursor::new(crashed.out.clone());
+        resume(7, &mut input, &mut output).unwrap();
+
+        let resumed = output.into_inner();
+        assert_eq!(&resumed[..written], &expected[..written]);
+        assert_eq!(unseal_helper(&setup, &resumed).0, plain);
+
+        // With the same RNG, the output is identical to an uninterrupted seal.
+        let mut output = Cursor::new(crashed.out);
+        resume(42, &mut Cursor::new(&plain), &mut output).unwrap();
+
+        assert_eq!(expected, output.into_inner());
+    }
+
+    #[test]
+    fn test_verify_only() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            let ct = seal_helper(&setup, &rand_vec(*l as usize));
+            let mut input = AllowStdIo::new(Cursor::new(ct));
+
+            let vr = block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                    .await
+                    .unwrap()
+                    .verify_only("Bob", &setup.usks[2])
+                    .await
+                    .unwrap()
+            });
+
+            assert_eq!(&vr.public, &setup.signing_keys[0].policy);
+            assert_eq!(vr.private, None);
+        }
+    }
+
+    #[test]
+    fn test_unseal_any() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            let plain = rand_vec(*l as usize);
+            let ct = seal_helper(&setup, &plain);
+            let mut input = AllowStdIo::new(Cursor::new(ct));
+            let mut output = AllowStdIo::new(Vec::new());
+
+            // Unknown identifiers and wrong keys are skipped.
+            let candidates = [
+                ("Alice", &setup.usks[0]),
+                ("Bob", &setup.usks[0]),
+                ("Charlie", &setup.usks[3]),
+                ("Bob", &setup.usks[2]),
+            ];
+
+            let vr = block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                    .await
+                    .unwrap()
+                    .unseal_any(&candidates, &mut output)
+                    .await
+                    .unwrap()
+            });
+
+            assert_eq!(&plain, &output.into_inner());
+            assert_eq!(vr.recipient.as_deref(), Some("Charlie"));
+            assert_eq!(
+                vr.recipient_policy,
+                Some(setup.policy.get("Charlie").unwrap().to_hidden())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unseal_any_none() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(512));
+        let mut input = AllowStdIo::new(Cursor::new(ct));
+
+        let res = block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                .await
+                .unwrap()
+                .unseal_any(&[("Bob", &setup.usks[0])], futures::io::sink())
+                .await
+        });
+
+        assert!(matches!(
+            res,
+            Err(Error::Segment {
+                index: 0,
+                failure: SegmentFailure::WrongTag,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_max_policy_age() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(512);
+        let ct = seal_helper(&setup, &plain);
+        let issued = setup.signing_keys[0].policy.timestamp;
+        let max_age = core::time::Duration::from_secs(60 * 60 * 24);
+
+        let unseal = |now: u64| {
+            let mut output = AllowStdIo::new(Vec::new());
+            block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(
+                    AllowStdIo::new(Cursor::new(&ct)),
+                    &setup.ibs_pk,
+                )
+                .await?
+                .with_max_policy_age(now, max_age)?
+                .unseal("Bob", &setup.usks[2], &mut output)
+                .await
+            })
+            .map(|_| output.into_inner())
+        };
+
+        assert_eq!(unseal(issued + 60 * 60 * 24).unwrap(), plain);
+        assert!(matches!(
+            unseal(issued + 60 * 60 * 24 + 1),
+            Err(Error::ConstraintViolation)
+        ));
+    }
+
+    #[test]
+    fn test_unseal_wrong_usk() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(512));
+        let mut input = AllowStdIo::new(Cursor::new(ct));
+
+        // Charlie's key, used for Bob.
+        let res = block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                .await
+                .unwrap()
+                .unseal("Bob", &setup.usks[3], futures::io::sink())
+                .await
+        });
+
+        assert!(matches!(res, Err(Error::WrongUserSecretKey)));
+    }
+
+    #[test]
+    fn test_verify_only_corrupt_payload() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize);
+        let mut ct = seal_helper(&setup, &plain);
+
+        // Flip a byte in the last segment.
+        let ct_len = ct.len();
+        ct[ct_len - STREAM_TAG_SIZE - 5] = !ct[ct_len - STREAM_TAG_SIZE - 5];
+
+        let mut input = AllowStdIo::new(Cursor::new(ct));
+        let res = block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                .await
+                .unwrap()
+                .verify_only("Bob", &setup.usks[2])
+                .await
+        });
+
+        // The policy shifts the payload, so it spans four segments.
+        let bufsize =
+            (SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + SIG_BYTES + STREAM_TAG_SIZE) as u64;
+        assert!(matches!(
+            res,
+            Err(Error::Segment {
+                index: 3,
+                offset,
+                failure: SegmentFailure::WrongTag,
+            }) if offset == 3 * bufsize
+        ));
+    }
+
+    #[test]
+    fn test_header_access() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(100));
+        let mut input = AllowStdIo::new(Cursor::new(&ct));
+
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut input,
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+
+        let header_len = unsealer.header_raw().len();
+        assert_eq!(unsealer.version(), VERSION_V4);
+        assert_eq!(
+            unsealer.header_raw(),
+            &ct[PREAMBLE_SIZE..PREAMBLE_SIZE + header_len]
+        );
+        assert_eq!(unsealer.header().recipients.len(), setup.policy.len());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let metadata = b"subject: quarterly report";
+
+        let mut input = AllowStdIo::new(Cursor::new(rand_vec(100)));
+        let mut output = AllowStdIo::new(Vec::new());
+
+        block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .with_metadata(metadata)
+            .seal(&mut input, &mut output),
+        )
+        .unwrap();
+        let mut ct = output.into_inner();
+
+        let mut input = AllowStdIo::new(Cursor::new(&ct));
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut input,
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+        assert_eq!(unsealer.metadata(), Some(&metadata[..]));
+
+        // Without metadata there is nothing to read.
+        let ct_plain = seal_helper(&setup, &rand_vec(100));
+        let mut input = AllowStdIo::new(Cursor::new(&ct_plain));
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut input,
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+        assert_eq!(unsealer.metadata(), None);
+
+        // The metadata is covered by the header signature.
+        let pos = ct
+            .windows(metadata.len())
+            .position(|w| w == metadata)
+            .unwrap();
+        ct[pos] ^= 0x01;
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct),
+            Err(Error::IncorrectSignature)
+        ));
+    }
+
+    fn unsealer_new_helper(setup: &TestSetup, ct: &[u8]) -> Result<(), Error> {
+        let mut input = AllowStdIo::new(Cursor::new(ct));
+
+        block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut input,
+            &setup.ibs_pk,
+        ))
+        .map(|_| ())
+    }
+
+    #[test]
+    fn test_unknown_sig_scheme() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let (mut header, _) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        assert_eq!(header.sig_scheme, SigScheme::GG);
+
+        // A header that declares a signature scheme from the future.
+        header.sig_scheme = SigScheme::Unknown(7);
+        let ct = signed_header_helper(&setup, &header);
+
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct),
+            Err(Error::UnsupportedVersion(7))
+        ));
+
+        // The registered scheme is accepted.
+        header.sig_scheme = SigScheme::GG;
+        let ct = signed_header_helper(&setup, &header);
+        assert!(unsealer_new_helper(&setup, &ct).is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(100);
+        let mut ct = seal_helper(&setup, &plain);
+
+        // Flip a byte that is guaranteed to be in the header.
+        ct[PREAMBLE_SIZE + 2] = !ct[PREAMBLE_SIZE + 2];
+
+        // This should fail, because of the header signature.
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct),
+            Err(Error::IncorrectSignature)
+        ));
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(100));
+        let header_len =
+            u32::from_be_bytes(ct[PREAMBLE_SIZE - 4..PREAMBLE_SIZE].try_into().unwrap());
+
+        // Cut off in the preamble, the header, the header signature length and the signature.
+        for len in [
+            PREAMBLE_SIZE - 1,
+            PREAMBLE_SIZE + 10,
+            PREAMBLE_SIZE + header_len as usize + 2,
+            PREAMBLE_SIZE + header_len as usize + 10,
+        ] {
+            assert!(matches!(
+                unsealer_new_helper(&setup, &ct[..len]),
+                Err(Error::HeaderTooShort)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_bad_length_prefix() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(100));
+
+        // Claim an absurd header length.
+        let mut ct1 = ct.clone();
+        ct1[PREAMBLE_SIZE - 4..PREAMBLE_SIZE].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct1),
+            Err(Error::BadLengthPrefix)
+        ));
+
+        // Claim an absurd header signature length.
+        let header_len =
+            u32::from_be_bytes(ct[PREAMBLE_SIZE - 4..PREAMBLE_SIZE].try_into().unwrap()) as usize;
+        let mut ct2 = ct;
+        ct2[PREAMBLE_SIZE + header_len..PREAMBLE_SIZE + header_len + 4]
+            .copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct2),
+            Err(Error::BadLengthPrefix)
+        ));
+    }
+
+    #[test]
+    fn test_max_header_size() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let new = |ct: &[u8], max_header_size: usize| {
+            let mut input = AllowStdIo::new(Cursor::new(ct));
+
+            block_on(
+                Unsealer::<_, UnsealerStreamConfig>::new_with_max_header_size(
+                    &mut input,
+                    &setup.ibs_pk,
+                    max_header_size,
+                ),
+            )
+            .map(|_| ())
+        };
+
+        // A preamble that claims a gigabyte of header, followed by nothing.
+        let mut ct = PRELUDE.to_vec();
+        ct.extend_from_slice(&VERSION_V4.to_be_bytes());
+        ct.extend_from_slice(&(1u32 << 30).to_be_bytes());
+        assert!(matches!(new(&ct, 4096), Err(Error::ConstraintViolation)));
+
+        // The format does not allow it either.
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct),
+            Err(Error::BadLengthPrefix)
+        ));
+
+        // The limit applies to both the header and its signature.
+        let ct = seal_helper(&setup, &rand_vec(100));
+        let header_len =
+            u32::from_be_bytes(ct[PREAMBLE_SIZE - 4..PREAMBLE_SIZE].try_into().unwrap()) as usize;
+        let header_sig_len = payload_offset(&ct) - PREAMBLE_SIZE - header_len - SIG_SIZE_SIZE;
+        assert!(new(&ct, header_len.max(header_sig_len)).is_ok());
+        assert!(matches!(
+            new(&ct, header_len.max(header_sig_len) - 1),
+            Err(Error::ConstraintViolation)
+        ));
+    }
+
+    #[test]
+    fn test_not_postguard() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let ct = seal_helper(&setup, &rand_vec(100));
+
+        // Corrupt the prelude.
+        let mut ct1 = ct.clone();
+        ct1[0] ^= 0xFF;
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct1),
+            Err(Error::NotPostGuard)
+        ));
+
+        // Bump the version.
+        let mut ct2 = ct;
+        ct2[PRELUDE_SIZE..PRELUDE_SIZE + VERSION_SIZE]
+            .copy_from_slice(&(VERSION_V4 + 1).to_be_bytes());
+        assert!(matches!(
+            unsealer_new_helper(&setup, &ct2),
+            Err(Error::UnsupportedVersion(v)) if v == VERSION_V4 + 1
+        ));
+    }
+
+    #[test]
+    fn test_min_version() {
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let ct = seal_helper(&setup, &rand_vec(100));
+        let unsealer = || {
+            block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+                AllowStdIo::new(Cursor::new(ct.clone())),
+                &setup.ibs_pk,
+            ))
+            .unwrap()
+        };
+
+        assert!(unsealer().require_min_version(VERSION_V2).is_ok());
+        assert!(unsealer().require_min_version(VERSION_V4).is_ok());
+        assert!(matches!(
+            unsealer().require_min_version(VERSION_V4 + 1),
+            Err(Error::UnsupportedVersion(v)) if v == VERSION_V4
+        ));
+
+        // Older versions are not even parsed.
+        let mut old = ct.clone();
+        old[PRELUDE_SIZE..PRELUDE_SIZE + VERSION_SIZE].copy_from_slice(&VERSION_V2.to_be_bytes());
+        assert!(matches!(
+            unsealer_new_helper(&setup, &old),
+            Err(Error::UnsupportedVersion(v)) if v == VERSION_V2
+        ));
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        use crate::client::RecipientHeader;
+        use alloc::collections::BTreeMap;
+        use serde::Serialize;
+
+        // The header as it was sealed under version 2, before it was extended.
+        #[derive(Serialize)]
+        struct HeaderV3<'a> {
+            recipients: &'a BTreeMap<String, RecipientHeader>,
+            algo: Algorithm,
+            mode: Mode,
+        }
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let (header, _) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let header_v3 = bincode::serialize(&HeaderV3 {
+            recipients: &header.recipients,
+            algo: header.algo,
+            mode: header.mode,
+        })
+        .unwrap();
+
+        let ct = signed_raw_header_helper(&setup, VERSION_V3, &header_v3);
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            AllowStdIo::new(Cursor::new(ct)),
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+        assert_eq!(unsealer.version(), VERSION_V3);
+        assert!(unsealer.header().structurally_eq(&header));
+        assert_eq!(unsealer.header().algo, header.algo);
+
+        // The legacy layout is not a header of the current version.
+        assert!(matches!(
+            unsealer_new_helper(
+                &setup,
+                &signed_raw_header_helper(&setup, VERSION_V4, &header_v3)
+            ),
+            Err(Error::HeaderDeserialize(_))
+        ));
+    }
+
+    #[test]
+    fn test_throughput_meter() {
+        let secs = core::time::Duration::from_secs;
+        let mut meter = ThroughputMeter::new(secs(2), secs(10));
+        assert_eq!(meter.rate(), 0.0);
+
+        meter.record(1000, secs(11));
+        assert_eq!(meter.total(), 1000);
+        assert_eq!(meter.rate(), 1000.0);
+
+        meter.record(3000, secs(12));
+        assert_eq!(meter.rate(), 2000.0);
+
+        // Only the last two seconds count, in which nothing was processed.
+        meter.record(0, secs(14));
+        assert_eq!(meter.total(), 4000);
+        assert_eq!(meter.rate(), 0.0);
+
+        meter.record(500, secs(16));
+        assert_eq!(meter.rate(), 250.0);
+    }
+
+    #[test]
+    fn test_unseal_bounded() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(10 * 1024);
+        let ct = seal_helper(&setup, &plain);
+
+        let unseal_bounded = |max_bytes| {
+            let mut output = Vec::new();
+            let res = block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(
+                    &mut AllowStdIo::new(Cursor::new(&ct)),
+                    &setup.ibs_pk,
+                )
+                .await
+                .unwrap()
+                .unseal_bounded("Bob", &setup.usks[2], &mut output, max_bytes)
+                .await
+            });
+
+            (res, output)
+        };
+
+        let (res, output) = unseal_bounded(4096);
+        assert!(matches!(res, Err(Error::ConstraintViolation)));
+        assert!(output.is_empty());
+
+        let (res, output) = unseal_bounded(plain.len() as u64);
+        assert!(res.is_ok());
+        assert_eq!(output, plain);
+    }
+
+    #[test]
+    fn test_overhead() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let signing_key = &setup.signing_keys[0];
+
+        for mode in [SignatureMode::PerSegment, SignatureMode::Detached] {
+            for l in LENGTHS {
+                let plain = rand_vec(*l as usize);
+                let sealer = Sealer::<_, SealerStreamConfig>::new(
+                    &setup.ibe_pk,
+                    &setup.policy,
+                    signing_key,
+                    &mut rng,
+                )
+                .unwrap()
+                .with_signature_mode(mode);
+                let overhead = sealer.overhead(plain.len() as u64).unwrap();
+
+                let mut ct = Vec::new();
+                let written = block_on(
+                    sealer.seal_counted(&mut AllowStdIo::new(Cursor::new(&plain)), &mut ct),
+                )
+                .unwrap();
+                assert_eq!(written, ct.len() as u64);
+                assert_eq!(ct.len() as u64, plain.len() as u64 + overhead);
+
+                if mode == SignatureMode::PerSegment {
+                    let payload_overhead = overhead_for(
+                        plain.len() as u64,
+                        SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
+                        &signing_key.policy,
+                    )
+                    .unwrap();
+                    assert_eq!(
+                        (ct.len() - payload_offset(&ct)) as u64,
+                        plain.len() as u64 + payload_overhead
+                    );
+                }
+            }
+        }
+    }
+
+    /// A segment signature scheme that wraps [`ibs::gg`], prefixing every signature with a marker.
+    #[derive(Debug, Default, Clone)]
+    struct Marked<T>(T);
+
+    const MARKER: u8 = 0xAA;
+
+    impl StreamSigner for Marked<Signer> {
+        const SIG_BYTES: usize = SIG_BYTES + 1;
+
+        fn update(&mut self, data: &[u8]) {
+            StreamSigner::update(&mut self.0, data);
+        }
+
+        fn sign<R: RngCore + CryptoRng>(
+            self,
+            key: &SigningKey,
+            rng: &mut R,
+        ) -> Result<Vec<u8>, Error> {
+            let mut sig = vec![MARKER];
+            sig.extend(StreamSigner::sign(self.0, key, rng)?);
+            Ok(sig)
+        }
+    }
+
+    impl StreamVerifier for Marked<Verifier> {
+        const SIG_BYTES: usize = SIG_BYTES + 1;
+
+        type Identity = <Verifier as StreamVerifier>::Identity;
+
+        fn identity(pol: &Policy) -> Result<Self::Identity, Error> {
+            <Verifier as StreamVerifier>::identity(pol)
+        }
+
+        fn update(&mut self, data: &[u8]) {
+            StreamVerifier::update(&mut self.0, data);
+        }
+
+        fn verify(self, vk: &VerifyingKey, sig: &[u8], id: &Self::Identity) -> Result<bool, Error> {
+            Ok(sig[0] == MARKER && StreamVerifier::verify(self.0, vk, &sig[1..], id)?)
+        }
+    }
+
+    #[test]
+    fn test_segment_layout() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 16);
+
+        for mode in [SignatureMode::PerSegment, SignatureMode::Detached] {
+            let ct = seal_mode_helper(&setup, &plain, mode);
+            let offset = payload_offset(&ct);
+            let header: Header = bincode::deserialize(&ct[PREAMBLE_SIZE..]).unwrap();
+
+            let layout = SegmentLayout::from_header(&header).unwrap();
+            let json = serde_json::to_string(&layout).unwrap();
+            assert_eq!(
+                serde_json::from_str::<SegmentLayout>(&json).unwrap(),
+                layout
+            );
+
+            // Every segment but the final one is of the same size, followed by its counter.
+            let policy = &setup.signing_keys[0].policy;
+            let segments = total_segments(plain.len() as u64, layout.segment_size, policy).unwrap();
+            let sealed = layout.sealed_segment_size();
+            for i in 0..segments as usize {
+                let end = (offset + (i + 1) * sealed).min(ct.len());
+                let counter = &ct[end - layout.tag_len - layout.counter_len..end - layout.tag_len];
+                assert_eq!(counter, &(i as u32).to_be_bytes());
+            }
+
+            // The final segment carries the remainder.
+            let prefix =
+                bincode::serialized_size(policy).unwrap() as usize + layout.policy_len_size;
+            let final_len = (prefix + plain.len()) % layout.segment_size as usize
+                + layout.final_sig_bytes
+                + layout.counter_len
+                + layout.tag_len;
+            assert_eq!(
+                ct.len(),
+                offset + (segments as usize - 1) * sealed + final_len
+            );
+        }
+    }
+
+    #[test]
+    fn test_segment_math() {
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::AsyncWrite;
+
+        // Records the plaintext written per segment.
+        #[derive(Default)]
+        struct SegmentWriter(Vec<usize>);
+
+        impl AsyncWrite for SegmentWriter {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.0.push(buf.len());
+                Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let policy = &setup.signing_keys[0].policy;
+        let seg = SYMMETRIC_CRYPTO_DEFAULT_CHUNK;
+        let sealed_segment_size = seg as usize + SIG_BYTES + STREAM_TAG_SIZE;
+
+        for l in LENGTHS.iter().chain(&[0]) {
+            let plain = rand_vec(*l as usize);
+            let ct = seal_helper(&setup, &plain);
+
+            // Only the final segment is shorter than a full one.
+            let payload_len = ct.len() - payload_offset(&ct);
+            let segments = total_segments(plain.len() as u64, seg, policy).unwrap();
+            assert_eq!(segments as usize, payload_len / sealed_segment_size + 1);
+
+            let mut w = SegmentWriter::default();
+            let vr = block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(
+                    &mut AllowStdIo::new(Cursor::new(&ct)),
+                    &setup.ibs_pk,
+                )
+                .await
+                .unwrap()
+                .unseal("Bob", &setup.usks[2], &mut w)
+                .await
+                .unwrap()
+            });
+            assert_eq!(vr.segments, segments);
+
+            // Every segment is written at once, except for an empty final segment.
+            let mut offset = 0u64;
+            for (i, len) in w.0.iter().enumerate() {
+                for o in [offset, offset + *len as u64 - 1] {
+                    assert_eq!(segment_of_offset(o, seg, policy).unwrap(), i as u32);
+                }
+                offset += *len as u64;
+            }
+            assert_eq!(
+                segment_of_offset(plain.len() as u64, seg, policy).unwrap(),
+                segments - 1
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_signer() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in [0, 100, SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize * 2 + 3] {
+            let plain = rand_vec(l);
+
+            let sealer = Sealer::<_, SealerStreamConfig<Marked<Signer>>>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap();
+            let overhead = sealer.overhead(plain.len() as u64).unwrap();
+
+            let mut ct = Vec::new();
+            block_on(sealer.seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut ct)).unwrap();
+            assert_eq!(ct.len() as u64, plain.len() as u64 + overhead);
+
+            let mut output = Vec::new();
+            block_on(async {
+                Unsealer::<_, UnsealerStreamConfig<Marked<Verifier>>>::new(
+                    &mut AllowStdIo::new(Cursor::new(&ct)),
+                    &setup.ibs_pk,
+                )
+                .await
+                .unwrap()
+                .unseal("Bob", &setup.usks[2], &mut output)
+                .await
+                .unwrap();
+            });
+            assert_eq!(output, plain);
+
+            // The default scheme cannot verify the segments.
+            assert!(try_unseal_helper(&setup, &ct).is_err());
+        }
+    }
+
+    #[test]
+    fn test_detached() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for l in LENGTHS {
+            let plain = rand_vec(*l as usize);
+            let ct = seal_mode_helper(&setup, &plain, SignatureMode::Detached);
+            let (plain2, vr) = unseal_helper(&setup, &ct);
+
+            assert_eq!(&plain, &plain2);
+            assert_eq!(&vr.public, &setup.signing_keys[0].policy);
+
+            // Only the final segment carries a signature.
+            let ct_per_segment = seal_helper(&setup, &plain);
+            let saved = ct_per_segment.len() - ct.len();
+            let payload_len = ct_per_segment.len() - payload_offset(&ct_per_segment);
+            let full_segments = payload_len
+                / (SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + SIG_BYTES + STREAM_TAG_SIZE);
+            assert_eq!(saved, full_segments * SIG_BYTES);
+        }
+    }
+
+    #[test]
+    fn test_policy_spill() {
+        use futures::io::AsyncWriteExt;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let signing_key = &setup.signing_keys[0];
+
+        // Segments that are too small to carry the signing policy.
+        let segment_size = 16;
+        let pol_len = bincode::serialized_size(&signing_key.policy).unwrap();
+        assert!(pol_len + POL_SIZE_SIZE as u64 > u64::from(segment_size));
+
+        fn small_sealer<'r>(
+            setup: &TestSetup,
+            rng: &'r mut StdRng,
+            segment_size: u32,
+        ) -> Sealer<'r, StdRng, SealerStreamConfig> {
+            let mut sealer = Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                rng,
+            )
+            .unwrap();
+            sealer.config.segment_size = segment_size;
+            sealer.header.mode = Mode::Streaming {
+                segment_size,
+                size_hint: (0, None),
+            };
+            sealer
+        }
+
+        for mode in [SignatureMode::PerSegment, SignatureMode::Detached] {
+            for l in [0, 1, 100] {
+                let plain = rand_vec(l);
+
+                let mut rng = StdRng::seed_from_u64(l as u64);
+                let sealer = small_sealer(&setup, &mut rng, segment_size).with_signature_mode(mode);
+                let overhead = sealer.overhead(plain.len() as u64).unwrap();
+
+                let mut ct = Vec::new();
+                block_on(sealer.seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut ct)).unwrap();
+                assert_eq!(ct.len() as u64, plain.len() as u64 + overhead);
+
+                let (plain2, vr) = unseal_helper(&setup, &ct);
+                assert_eq!(plain, plain2);
+                assert_eq!(vr.public, signing_key.policy);
+
+                // The writer spills the policy in the same way.
+                let mut rng = StdRng::seed_from_u64(l as u64);
+                let mut output = Vec::new();
+                block_on(async {
+                    let mut writer = small_sealer(&setup, &mut rng, segment_size)
+                        .with_signature_mode(mode)
+                        .into_writer(&mut output)
+                        .unwrap();
+                    writer.write_all(&plain).await.unwrap();
+                    writer.close().await.unwrap();
+                });
+                assert_eq!(output, ct);
+
+                // The leading segments are authenticated like any other.
+                let mut ct = ct;
+                let offset = payload_offset(&ct);
+                ct[offset] ^= 0x01;
+                assert!(matches!(
+                    try_unseal_helper(&setup, &ct),
+                    Err(Error::Segment {
+                        index: 0,
+                        offset: 0,
+                        failure: SegmentFailure::WrongTag,
+                    })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_detached_writer() {
+        use futures::io::AsyncWriteExt;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let signing_key = &setup.signing_keys[0];
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 16);
+
+        // Seal twice using the same randomness, so that the output must be byte-identical.
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut expected = Vec::new();
+        block_on(
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                signing_key,
+                &mut rng,
+            )
+            .unwrap()
+            .with_signature_mode(SignatureMode::Detached)
+            .seal(&mut AllowStdIo::new(Cursor::new(&plain)), &mut expected),
+        )
+        .unwrap();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut output = Vec::new();
+        block_on(async {
+            let mut writer = Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                signing_key,
+                &mut rng,
+            )
+            .unwrap()
+            .with_signature_mode(SignatureMode::Detached)
+            .into_writer(&mut output)
+            .unwrap();
+
+            writer.write_all(&plain).await.unwrap();
+            writer.close().await.unwrap();
+        });
+
+        assert_eq!(expected, output);
+
+        let (plain2, _) = unseal_helper(&setup, &output);
+        assert_eq!(plain, plain2);
+    }
+
+    #[test]
+    fn test_detached_tampered() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 16);
+        let ct = seal_mode_helper(&setup, &plain, SignatureMode::Detached);
+        let offset = payload_offset(&ct);
+        let bufsize = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + STREAM_TAG_SIZE;
+
+        // A modified segment is still detected by its tag.
+        let mut modified = ct.clone();
+        modified[offset + bufsize + 5] ^= 1;
+        assert!(matches!(
+            try_unseal_helper(&setup, &modified),
+            Err(Error::Segment {
+                index: 1,
+                failure: SegmentFailure::WrongTag,
+                ..
+            })
+        ));
+
+        // Dropping a segment is only detected by the final signature.
+        let mut dropped = ct.clone();
+        dropped.drain(offset + bufsize..offset + 2 * bufsize);
+        assert!(matches!(
+            try_unseal_helper(&setup, &dropped),
+            Err(Error::Segment {
+                index: 2,
+                failure: SegmentFailure::IncorrectSignature,
+                ..
+            })
+        ));
+
+        // Swapping two segments as well.
+        let mut swapped = ct;
+        let second = swapped[offset + bufsize..offset + 2 * bufsize].to_vec();
+        swapped.copy_within(offset + 2 * bufsize..offset + 3 * bufsize, offset + bufsize);
+        swapped[offset + 2 * bufsize..offset + 3 * bufsize].copy_from_slice(&second);
+        assert!(matches!(
+            try_unseal_helper(&setup, &swapped),
+            Err(Error::Segment {
+                index: 3,
+                failure: SegmentFailure::IncorrectSignature,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let seal_unsigned = |plain: &[u8]| {
+            let mut rng = rand::thread_rng();
+            let mut output = AllowStdIo::new(Vec::new());
+            let sealer = Sealer::<_, SealerStreamConfig>::new_unsigned(
+                &setup.ibe_pk,
+                &setup.policy,
+                &mut rng,
+            )
+            .unwrap();
+            let overhead = sealer.overhead(plain.len() as u64).unwrap();
+
+            block_on(sealer.seal(AllowStdIo::new(Cursor::new(plain)), &mut output)).unwrap();
+            let ct = output.into_inner();
+            assert_eq!(ct.len() as u64, plain.len() as u64 + overhead);
+
+            ct
+        };
+
+        for l in LENGTHS {
+            let plain = rand_vec(*l as usize);
+            let ct = seal_unsigned(&plain);
+            let (plain2, vr) = unseal_helper(&setup, &ct);
+
+            assert_eq!(&plain, &plain2);
+            assert!(vr.unsigned);
+            assert_eq!(vr.public, Policy::default());
+            assert!(vr.private.is_none());
+        }
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 16);
+        let ct = seal_unsigned(&plain);
+        let offset = payload_offset(&ct);
+        let header_len = offset - PREAMBLE_SIZE - SIG_SIZE_SIZE;
+
+        // The header carries no signature and the segments no signing policy.
+        assert_eq!(&ct[offset - SIG_SIZE_SIZE..offset], &[0u8; SIG_SIZE_SIZE]);
+        let bufsize = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + STREAM_TAG_SIZE;
+        assert_eq!(ct.len() - offset, plain.len() + 4 * STREAM_TAG_SIZE);
+
+        // A modified segment is still detected by its tag.
+        let mut modified = ct.clone();
+        modified[offset + bufsize + 5] ^= 1;
+        assert!(matches!(
+            try_unseal_helper(&setup, &modified),
+            Err(Error::Segment {
+                index: 1,
+                failure: SegmentFailure::WrongTag,
+                ..
+            })
+        ));
+
+        // Segments cannot be reordered, duplicated or dropped.
+        let segment = |i: usize| &ct[offset + i * bufsize..offset + (i + 1) * bufsize];
+        let mut swapped = ct[..offset].to_vec();
+        swapped.extend_from_slice(segment(1));
+        swapped.extend_from_slice(segment(0));
+        swapped.extend_from_slice(&ct[offset + 2 * bufsize..]);
+        assert!(matches!(
+            try_unseal_helper(&setup, &swapped),
+            Err(Error::Segment {
+                index: 0,
+                failure: SegmentFailure::OutOfOrder,
+                ..
+            })
+        ));
+
+        let mut duplicated = ct[..offset + 2 * bufsize].to_vec();
+        duplicated.extend_from_slice(segment(1));
+        duplicated.extend_from_slice(&ct[offset + 2 * bufsize..]);
+        assert!(matches!(
+            try_unseal_helper(&setup, &duplicated),
+            Err(Error::Segment {
+                index: 2,
+                failure: SegmentFailure::OutOfOrder,
+                ..
+            })
+        ));
+
+        let mut dropped = ct[..offset + bufsize].to_vec();
+        dropped.extend_from_slice(&ct[offset + 2 * bufsize..]);
+        assert!(matches!(
+            try_unseal_helper(&setup, &dropped),
+            Err(Error::Segment {
+                index: 1,
+                failure: SegmentFailure::OutOfOrder,
+                ..
+            })
+        ));
+
+        // Neither can the stream be truncated at a segment boundary.
+        for segments in 1..=3 {
+            assert!(matches!(
+                try_unseal_helper(&setup, &ct[..offset + segments * bufsize]),
+                Err(Error::Segment {
+                    index,
+                    failure: SegmentFailure::Truncated,
+                    ..
+                }) if index as usize == segments - 1
+            ));
+        }
+
+        // A modified header no longer matches the segments it is bound to.
+        let header: Header =
+            bincode::deserialize(&ct[PREAMBLE_SIZE..PREAMBLE_SIZE + header_len]).unwrap();
+        let header_vec = bincode::serialize(&header.with_metadata(b"forged".to_vec())).unwrap();
+        let mut forged = ct[..PREAMBLE_SIZE - HEADER_SIZE_SIZE].to_vec();
+        forged.extend_from_slice(&(header_vec.len() as u32).to_be_bytes());
+        forged.extend_from_slice(&header_vec);
+        forged.extend_from_slice(&ct[offset - SIG_SIZE_SIZE..]);
+        assert!(matches!(
+            try_unseal_helper(&setup, &forged),
+            Err(Error::Segment {
+                index: 0,
+                failure: SegmentFailure::WrongTag,
+                ..
+            })
+        ));
+
+        // Stripping the signature of a signed stream does not make it unsigned.
+        let signed = seal_helper(&setup, &plain);
+        let offset = payload_offset(&signed);
+        let sig_offset = PREAMBLE_SIZE
+            + u32::from_be_bytes(
+                signed[PREAMBLE_SIZE - HEADER_SIZE_SIZE..PREAMBLE_SIZE]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+        let mut stripped = signed[..sig_offset].to_vec();
+        stripped.extend_from_slice(&[0u8; SIG_SIZE_SIZE]);
+        stripped.extend_from_slice(&signed[offset..]);
+        assert!(matches!(
+            unsealer_new_helper(&setup, &stripped),
+            Err(Error::IncorrectSignature)
+        ));
+
+        // Neither does a signed header that declares an unsigned payload.
+        let (header, _) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let header = header
+            .with_bind_header(true)
+            .with_signature_mode(SignatureMode::Unsigned);
+        assert!(matches!(
+            unsealer_new_helper(&setup, &signed_header_helper(&setup, &header)),
+            Err(Error::FormatViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_rewrap() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 16);
+        let ct = seal_helper(&setup, &plain);
+
+        // Bob forwards the payload to Charlie, signing it himself.
+        let forward =
+            EncryptionPolicy::from([(String::from("Charlie"), setup.policies[4].clone())]);
+        let mut output = AllowStdIo::new(Vec::new());
+        let vr = block_on(async {
+            let unsealer = Unsealer::<_, UnsealerStreamConfig>::new(
+                AllowStdIo::new(Cursor::new(&ct)),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap();
+            let sealer = Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &forward,
+                &setup.signing_keys[2],
+                &mut rng,
+            )
+            .unwrap();
+
+            unsealer
+                .rewrap("Bob", &setup.usks[2], sealer, &mut output)
+                .await
+        })
+        .unwrap();
+        assert_eq!(&vr.public, &setup.signing_keys[0].policy);
+
+        let rewrapped = output.into_inner();
+        let mut plain2 = AllowStdIo::new(Vec::new());
+        let (metadata, vr2) = block_on(async {
+            let unsealer = Unsealer::<_, UnsealerStreamConfig>::new(
+                AllowStdIo::new(Cursor::new(&rewrapped)),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap();
+            let metadata: VerificationResult =
+                serde_json::from_slice(unsealer.metadata().unwrap()).unwrap();
+            let vr2 = unsealer
+                .unseal("Charlie", &setup.usks[4], &mut plain2)
+                .await
+                .unwrap();
+
+            (metadata, vr2)
+        });
+
+        assert_eq!(&plain2.into_inner(), &plain);
+        assert_eq!(&vr2.public, &setup.signing_keys[2].policy);
+        assert_eq!(&metadata, &vr);
+        assert_eq!(vr.segments, 0);
+        assert!(vr2.segments > 1);
+
+        // A tampered source is not re-sealed.
+        let mut modified = ct.clone();
+        let ct_len = modified.len();
+        modified[ct_len - STREAM_TAG_SIZE - 5] ^= 1;
+        let res = block_on(async {
+            let unsealer = Unsealer::<_, UnsealerStreamConfig>::new(
+                AllowStdIo::new(Cursor::new(&modified)),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap();
+            let sealer = Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &forward,
+                &setup.signing_keys[2],
+                &mut rng,
+            )
+            .unwrap();
+
+            unsealer
+                .rewrap("Bob", &setup.usks[2], sealer, futures::io::sink())
+                .await
+        });
+        assert!(matches!(res, Err(Error::Segment { index: 3, .. })));
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(100);
+        let mut ct = seal_helper(&setup, &plain);
+
+        // Flip a byte that is guaranteed to be in the encrypted payload.
+        let ct_len = ct.len();
+        ct[ct_len - STREAM_TAG_SIZE - 5] = !ct[ct_len - STREAM_TAG_SIZE - 5];
+
+        // This should fail, because of the AEAD.
+        assert!(matches!(
+            try_unseal_helper(&setup, &ct),
+            Err(Error::Segment {
+                index: 0,
+                offset: 0,
+                failure: SegmentFailure::WrongTag,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_tag() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(100);
+        let mut ct = seal_helper(&setup, &plain);
 
-        let private_id = pol_id.unwrap().0;
-        let private = if self.pub_id == private_id {
-            None
-        } else {
-            Some(private_id)
-        };
+        let len = ct.len();
+        ct[len - 5] = !ct[len - 5];
 
-        Ok(VerificationResult {
-            public: self.pub_id,
-            private,
-        })
+        // This should fail as well.
+        assert!(matches!(
+            try_unseal_helper(&setup, &ct),
+            Err(Error::Segment {
+                index: 0,
+                offset: 0,
+                failure: SegmentFailure::WrongTag,
+            })
+        ));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Sealer, SealerStreamConfig, Unsealer, UnsealerStreamConfig};
-    use crate::client::VerificationResult;
-    use crate::error::Error;
-    use crate::test::TestSetup;
-    use crate::{PREAMBLE_SIZE, SYMMETRIC_CRYPTO_DEFAULT_CHUNK, TAG_SIZE};
-    use alloc::string::String;
-    use alloc::vec::Vec;
-    use futures::{executor::block_on, io::AllowStdIo};
-    use rand::{thread_rng, Rng, RngCore};
-    use std::io::Cursor;
-    use tokio::io::AsyncReadExt;
+    #[test]
+    fn test_segment_size_bounds() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
 
-    const LENGTHS: &[u32] = &[
-        1,
-        512,
-        SYMMETRIC_CRYPTO_DEFAULT_CHUNK - 3,
-        SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
-        SYMMETRIC_CRYPTO_DEFAULT_CHUNK + 3,
-        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK,
-        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK + 16,
-        3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK - 17,
-    ];
+        for segment_size in [0, 3 * 1024 * 1024 * 1024, u32::MAX] {
+            let ct = header_helper(
+                &setup,
+                Mode::Streaming {
+                    segment_size,
+                    size_hint: (0, None),
+                },
+            );
 
-    fn seal_helper(setup: &TestSetup, plain: &[u8]) -> Vec<u8> {
+            let mut input = AllowStdIo::new(Cursor::new(ct));
+            let res = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+                &mut input,
+                &setup.ibs_pk,
+            ));
+
+            assert!(matches!(res, Err(Error::ConstraintViolation)));
+        }
+    }
+
+    #[test]
+    fn test_short_segment() {
         let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
 
-        let mut input = AllowStdIo::new(Cursor::new(plain));
-        let mut output = AllowStdIo::new(Vec::new());
+        let (header, ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let mut ct = signed_header_helper(&setup, &header);
 
-        let signing_key = &setup.signing_keys[0];
+        // A correctly encrypted final segment, which is too short to contain a signature.
+        let pol = bincode::serialize(&setup.signing_keys[0].policy).unwrap();
+        let mut seg = Vec::new();
+        seg.extend_from_slice(&(pol.len() as u32).to_be_bytes());
+        seg.extend_from_slice(&pol);
+        seg.extend_from_slice(&[0u8; 8]);
 
-        block_on(async {
-            Sealer::<_, SealerStreamConfig>::new(
-                &setup.ibe_pk,
-                &setup.policy,
-                &signing_key,
-                &mut rng,
-            )
+        let Algorithm::Aes128Gcm(iv) = header.algo;
+        Deck::new(&ss.0[..KEY_SIZE], &iv.0[..STREAM_NONCE_SIZE])
             .unwrap()
-            .seal(&mut input, &mut output)
-            .await
+            .wrap_last(&mut seg)
             .unwrap();
-        });
-
-        output.into_inner()
-    }
+        ct.extend_from_slice(&seg);
 
-    fn unseal_helper(setup: &TestSetup, ct: &[u8]) -> (Vec<u8>, VerificationResult) {
         let mut input = AllowStdIo::new(Cursor::new(ct));
-        let mut output = AllowStdIo::new(Vec::new());
-
-        // sometimes decrypt as Bob, sometimes decrypt as Charlie
-        let (id, usk_id) = if thread_rng().gen::<bool>() {
-            ("Bob", setup.usks[2].clone())
-        } else {
-            ("Charlie", setup.usks[3].clone())
-        };
-
-        let vr = block_on(async {
-            let unsealer = Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+        let res = block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                .await
+                .unwrap()
+                .unseal("Bob", &setup.usks[2], futures::io::sink())
                 .await
-                .unwrap();
-
-            // Normally, a user would need to retrieve a usk here via the PKG,
-            // but in this case we own the master key pair.
-            unsealer.unseal(id, &usk_id, &mut output).await.unwrap()
         });
 
-        (output.into_inner(), vr)
+        assert!(matches!(res, Err(Error::FormatViolation(_))));
     }
 
-    fn seal_and_unseal(setup: &TestSetup, plain: Vec<u8>) {
-        let ct = seal_helper(setup, &plain);
-        let (plain2, vr) = unseal_helper(setup, &ct);
+    #[test]
+    fn test_spurious_eof() {
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::AsyncRead;
 
-        assert_eq!(&plain, &plain2);
-        assert_eq!(&vr.public, &setup.signing_keys[0].policy);
-        assert_eq!(vr.private, None);
-    }
+        /// Returns no bytes once at a given position, before yielding the remaining bytes.
+        struct SpuriousEof {
+            inner: Cursor<Vec<u8>>,
+            at: u64,
+            done: bool,
+        }
 
-    fn rand_vec(length: usize) -> Vec<u8> {
-        let mut vec = vec![0u8; length];
-        rand::thread_rng().fill_bytes(&mut vec);
-        vec
-    }
+        impl AsyncRead for SpuriousEof {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                let pos = self.inner.position();
+                if !self.done && pos == self.at {
+                    self.done = true;
+                    return Poll::Ready(Ok(0));
+                }
+
+                let max = if self.done {
+                    buf.len()
+                } else {
+                    buf.len().min((self.at - pos) as usize)
+                };
+
+                Poll::Ready(std::io::Read::read(&mut self.inner, &mut buf[..max]))
+            }
+        }
 
-    #[test]
-    fn test_reflection_seal_unsealer() {
         let mut rng = rand::thread_rng();
         let setup = TestSetup::new(&mut rng);
 
-        for l in LENGTHS {
-            seal_and_unseal(&setup, rand_vec(*l as usize));
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 100);
+        let ct = seal_helper(&setup, &plain);
+        let seg = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + SIG_BYTES + STREAM_TAG_SIZE;
+
+        // Within the final segment, within a full segment and at a segment boundary.
+        for at in [ct.len() - 1, ct.len() - seg, ct.len() - 100 - seg / 2] {
+            let r = SpuriousEof {
+                inner: Cursor::new(ct.clone()),
+                at: at as u64,
+                done: false,
+            };
+
+            let mut output = Vec::new();
+            block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(r, &setup.ibs_pk)
+                    .await
+                    .unwrap()
+                    .unseal("Bob", &setup.usks[2], &mut output)
+                    .await
+                    .unwrap();
+            });
+
+            assert_eq!(output, plain);
         }
     }
 
     #[test]
-    #[should_panic]
-    fn test_corrupt_header() {
+    fn test_segment_boundary() {
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::AsyncRead;
+
+        /// Returns at most a few bytes per read.
+        struct Trickle {
+            inner: Cursor<Vec<u8>>,
+            step: usize,
+        }
+
+        impl AsyncRead for Trickle {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                let max = buf.len().min(self.step);
+                Poll::Ready(std::io::Read::read(&mut self.inner, &mut buf[..max]))
+            }
+        }
+
         let mut rng = rand::thread_rng();
         let setup = TestSetup::new(&mut rng);
 
-        let plain = rand_vec(100);
-        let mut ct = seal_helper(&setup, &plain);
+        // The first segment also carries the signing policy.
+        let pol_len = bincode::serialized_size(&setup.signing_keys[0].policy).unwrap() as usize;
+        let first = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize - POL_SIZE_SIZE - pol_len;
 
-        // Flip a byte that is guaranteed to be in the header.
-        ct[PREAMBLE_SIZE + 2] = !ct[PREAMBLE_SIZE + 2];
+        for mode in [SignatureMode::PerSegment, SignatureMode::Detached] {
+            // Payloads that fill their last segment exactly.
+            for l in [first, first + SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize] {
+                let plain = rand_vec(l);
+                let ct = seal_mode_helper(&setup, &plain, mode);
+
+                for step in [1, 7, 4096, ct.len()] {
+                    let r = Trickle {
+                        inner: Cursor::new(ct.clone()),
+                        step,
+                    };
+
+                    let mut output = Vec::new();
+                    block_on(async {
+                        Unsealer::<_, UnsealerStreamConfig>::new(r, &setup.ibs_pk)
+                            .await
+                            .unwrap()
+                            .unseal("Bob", &setup.usks[2], &mut output)
+                            .await
+                            .unwrap();
+                    });
 
-        // This should panic, because of the header signature.
-        let _plain2 = unseal_helper(&setup, &ct);
+                    assert_eq!(output, plain);
+                }
+
+                // Without the (empty) final segment, the stream ends at a segment boundary.
+                let truncated = &ct[..ct.len() - SIG_BYTES - STREAM_TAG_SIZE];
+                assert!(try_unseal_helper(&setup, truncated).is_err());
+            }
+        }
     }
 
     #[test]
-    #[should_panic]
-    fn test_corrupt_payload() {
+    fn test_abort() {
+        use core::pin::Pin;
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use core::task::{Context, Poll};
+        use futures::io::AsyncWrite;
+        use std::sync::Arc;
+
+        /// Sets the abort signal after a number of writes.
+        struct AbortAfter {
+            inner: Vec<u8>,
+            writes: usize,
+            abort: Arc<AtomicBool>,
+            closed: bool,
+        }
+
+        impl AsyncWrite for AbortAfter {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.inner.extend_from_slice(buf);
+                self.writes -= 1;
+                if self.writes == 0 {
+                    self.abort.store(true, Ordering::Relaxed);
+                }
+
+                Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_close(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                self.closed = true;
+                Poll::Ready(Ok(()))
+            }
+        }
+
         let mut rng = rand::thread_rng();
         let setup = TestSetup::new(&mut rng);
 
-        let plain = rand_vec(100);
-        let mut ct = seal_helper(&setup, &plain);
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 100);
+        let ct = seal_helper(&setup, &plain);
 
-        // Flip a byte that is guaranteed to be in the encrypted payload.
-        let ct_len = ct.len();
-        ct[ct_len - TAG_SIZE - 5] = !ct[ct_len - TAG_SIZE - 5];
+        let abort = Arc::new(AtomicBool::new(false));
+        let mut w = AbortAfter {
+            inner: Vec::new(),
+            writes: 2,
+            abort: abort.clone(),
+            closed: false,
+        };
+
+        let res = block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(
+                &mut AllowStdIo::new(Cursor::new(&ct)),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap()
+            .with_abort_signal(abort)
+            .unseal("Bob", &setup.usks[2], &mut w)
+            .await
+        });
 
-        // This should panic, because of the AEAD.
-        let _plain2 = unseal_helper(&setup, &ct);
+        assert!(matches!(res, Err(Error::Aborted)));
+        assert!(w.closed);
+
+        // Only the plaintext of the first two segments was written.
+        let pol_len = bincode::serialized_size(&setup.signing_keys[0].policy).unwrap() as usize;
+        let len = 2 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize - POL_SIZE_SIZE - pol_len;
+        assert_eq!(w.inner, plain[..len]);
     }
 
     #[test]
-    #[should_panic]
-    fn test_corrupt_tag() {
+    fn test_oversized_policy_length() {
         let mut rng = rand::thread_rng();
         let setup = TestSetup::new(&mut rng);
 
-        let plain = rand_vec(100);
-        let mut ct = seal_helper(&setup, &plain);
+        for pol_len in [101, 1024 * 1024, u32::MAX] {
+            let (header, ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+            let mut ct = signed_header_helper(&setup, &header);
 
-        let len = ct.len();
-        ct[len - 5] = !ct[len - 5];
+            // A correctly encrypted final segment, of which the policy length exceeds the segment.
+            let mut seg = Vec::new();
+            seg.extend_from_slice(&pol_len.to_be_bytes());
+            seg.extend_from_slice(&[0u8; 100]);
+
+            let Algorithm::Aes128Gcm(iv) = header.algo;
+            Deck::new(&ss.0[..KEY_SIZE], &iv.0[..STREAM_NONCE_SIZE])
+                .unwrap()
+                .wrap_last(&mut seg)
+                .unwrap();
+            ct.extend_from_slice(&seg);
 
-        // This should panic as well.
-        let _plain2 = unseal_helper(&setup, &ct);
+            let mut input = AllowStdIo::new(Cursor::new(ct));
+            let res = block_on(async {
+                Unsealer::<_, UnsealerStreamConfig>::new(&mut input, &setup.ibs_pk)
+                    .await
+                    .unwrap()
+                    .unseal("Bob", &setup.usks[2], futures::io::sink())
+                    .await
+            });
+
+            assert!(matches!(res, Err(Error::FormatViolation(_))));
+        }
     }
 
     #[tokio::test]
@@ -620,4 +3900,320 @@ mod tests {
         assert_eq!(input.into_inner().to_vec(), original);
         Ok(())
     }
+
+    #[test]
+    fn test_into_stream() {
+        use core::cell::Cell;
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::AsyncRead;
+        use futures::{pin_mut, StreamExt};
+        use std::rc::Rc;
+
+        /// Counts the bytes read from the inner reader.
+        struct CountingReader {
+            inner: Cursor<Vec<u8>>,
+            read: Rc<Cell<usize>>,
+        }
+
+        impl AsyncRead for CountingReader {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                let n = std::io::Read::read(&mut self.inner, buf)?;
+                self.read.set(self.read.get() + n);
+                Poll::Ready(Ok(n))
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let policy = &setup.signing_keys[0].policy;
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 100);
+        let seg = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + SIG_BYTES + STREAM_TAG_SIZE;
+        let segments =
+            total_segments(plain.len() as u64, SYMMETRIC_CRYPTO_DEFAULT_CHUNK, policy).unwrap();
+
+        for mode in [SignatureMode::PerSegment, SignatureMode::Detached] {
+            let ct = seal_mode_helper(&setup, &plain, mode);
+            let read = Rc::new(Cell::new(0));
+            let r = CountingReader {
+                inner: Cursor::new(ct.clone()),
+                read: read.clone(),
+            };
+
+            block_on(async {
+                let s = Unsealer::<_, UnsealerStreamConfig>::new(r, &setup.ibs_pk)
+                    .await
+                    .unwrap()
+                    .into_stream("Bob", &setup.usks[2]);
+                pin_mut!(s);
+
+                // Only the first segment and the lookahead have been read.
+                let mut output = s.next().await.unwrap().unwrap();
+                assert!(read.get() < ct.len() - seg);
+
+                let mut items = 1;
+                while let Some(m) = s.next().await {
+                    output.extend_from_slice(&m.unwrap());
+                    items += 1;
+                }
+
+                assert_eq!(output, plain);
+                assert_eq!(items, segments);
+            });
+
+            // A corrupted final segment ends the stream with an error.
+            let mut modified = ct.clone();
+            *modified.last_mut().unwrap() ^= 1;
+
+            block_on(async {
+                let s = Unsealer::<_, UnsealerStreamConfig>::new(
+                    AllowStdIo::new(Cursor::new(modified)),
+                    &setup.ibs_pk,
+                )
+                .await
+                .unwrap()
+                .into_stream("Bob", &setup.usks[2]);
+                pin_mut!(s);
+
+                for _ in 0..segments - 1 {
+                    assert!(s.next().await.unwrap().is_ok());
+                }
+
+                let index = segments - 1;
+                assert!(matches!(
+                    s.next().await,
+                    Some(Err(Error::Segment {
+                        index: i,
+                        failure: SegmentFailure::WrongTag,
+                        ..
+                    })) if i == index
+                ));
+                assert!(s.next().await.is_none());
+            });
+        }
+    }
+
+    #[test]
+    fn test_unseal_buffered() {
+        use core::cell::Cell;
+        use core::pin::Pin;
+        use core::task::{Context, Poll};
+        use futures::io::{AsyncRead, BufReader};
+        use std::rc::Rc;
+
+        /// Yields the input in packets, counting the reads.
+        struct Drip {
+            inner: Cursor<Vec<u8>>,
+            packet: u64,
+            reads: Rc<Cell<usize>>,
+        }
+
+        impl AsyncRead for Drip {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.reads.set(self.reads.get() + 1);
+
+                // At most the remainder of the current packet is available.
+                let left = self.packet - self.inner.position() % self.packet;
+                let max = buf.len().min(left as usize);
+
+                Poll::Ready(std::io::Read::read(&mut self.inner, &mut buf[..max]))
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 100);
+        let ct = seal_helper(&setup, &plain);
+
+        let drip = |reads: &Rc<Cell<usize>>| Drip {
+            inner: Cursor::new(ct.clone()),
+            packet: 1500,
+            reads: reads.clone(),
+        };
+
+        let unbuffered = Rc::new(Cell::new(0));
+        let buffered = Rc::new(Cell::new(0));
+
+        let (mut out1, mut out2) = (Vec::new(), Vec::new());
+        block_on(async {
+            Unsealer::<_, UnsealerStreamConfig>::new(drip(&unbuffered), &setup.ibs_pk)
+                .await
+                .unwrap()
+                .unseal("Bob", &setup.usks[2], &mut out1)
+                .await
+                .unwrap();
+
+            Unsealer::<_, UnsealerStreamConfig>::new(
+                BufReader::new(drip(&buffered)),
+                &setup.ibs_pk,
+            )
+            .await
+            .unwrap()
+            .unseal_buffered("Bob", &setup.usks[2], &mut out2)
+            .await
+            .unwrap();
+        });
+
+        assert_eq!(out1, plain);
+        assert_eq!(out2, plain);
+        assert!(buffered.get() < unbuffered.get());
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let plain = rand_vec(100);
+        let hint = (plain.len() as u64, Some(plain.len() as u64));
+
+        let mut ct = Vec::new();
+        block_on(async {
+            Sealer::<_, SealerStreamConfig>::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap()
+            .with_size_hint(hint)
+            .seal(AllowStdIo::new(Cursor::new(&plain)), &mut ct)
+            .await
+            .unwrap();
+        });
+
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut AllowStdIo::new(Cursor::new(&ct)),
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+        assert_eq!(unsealer.size_hint(), hint);
+
+        // Without a hint, the size is unknown.
+        let ct = seal_helper(&setup, &plain);
+        let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+            &mut AllowStdIo::new(Cursor::new(&ct)),
+            &setup.ibs_pk,
+        ))
+        .unwrap();
+        assert_eq!(unsealer.size_hint(), (0, None));
+    }
+
+    #[test]
+    fn test_segment_buffer_size() {
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let plain = rand_vec(3 * SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 17);
+        let segment = SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + STREAM_TAG_SIZE;
+
+        for (mode, expected) in [
+            (SignatureMode::PerSegment, segment + SIG_BYTES + 1),
+            (SignatureMode::Detached, segment + SIG_BYTES + 1),
+            (SignatureMode::Unsigned, segment + 1),
+        ] {
+            let ct = seal_mode_helper(&setup, &plain, mode);
+            let unsealer = block_on(Unsealer::<_, UnsealerStreamConfig>::new(
+                &mut AllowStdIo::new(Cursor::new(&ct)),
+                &setup.ibs_pk,
+            ))
+            .unwrap();
+            assert_eq!(unsealer.segment_buffer_size(), expected);
+
+            // The reused buffers yield the same plaintext.
+            let mut output = Vec::new();
+            block_on(unsealer.unseal("Bob", &setup.usks[2], &mut output)).unwrap();
+            assert_eq!(output, plain);
+        }
+    }
+
+    #[test]
+    fn test_from_shared_secret() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+        let plain = rand_vec(100);
+
+        let seal = |sealer: Sealer<'_, StdRng, SealerStreamConfig>| {
+            let mut output = Vec::new();
+            block_on(sealer.seal(AllowStdIo::new(Cursor::new(&plain)), &mut output)).unwrap();
+            output
+        };
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let ct1 = seal(
+            Sealer::new(
+                &setup.ibe_pk,
+                &setup.policy,
+                &setup.signing_keys[0],
+                &mut rng,
+            )
+            .unwrap(),
+        );
+
+        // Encapsulating separately yields the same stream.
+        let mut rng = StdRng::seed_from_u64(42);
+        let (header, ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let ct2 =
+            seal(Sealer::from_shared_secret(header, ss, &setup.signing_keys[0], &mut rng).unwrap());
+
+        assert_eq!(ct1, ct2);
+        assert_eq!(unseal_helper(&setup, &ct2).0, plain);
+    }
+
+    #[test]
+    fn test_splice_headers() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+        let plain = rand_vec(SYMMETRIC_CRYPTO_DEFAULT_CHUNK as usize + 100);
+
+        // Two streams with the same DEM key and nonce, but different headers.
+        let seal = |size_hint: (u64, Option<u64>)| {
+            let mut rng = StdRng::seed_from_u64(42);
+            let mut output = Vec::new();
+
+            block_on(async {
+                Sealer::<_, SealerStreamConfig>::new(
+                    &setup.ibe_pk,
+                    &setup.policy,
+                    &setup.signing_keys[0],
+                    &mut rng,
+                )
+                .unwrap()
+                .with_size_hint(size_hint)
+                .seal(AllowStdIo::new(Cursor::new(&plain)), &mut output)
+                .await
+                .unwrap();
+            });
+
+            output
+        };
+
+        let ct1 = seal((0, None));
+        let ct2 = seal((plain.len() as u64, None));
+        assert_eq!(unseal_helper(&setup, &ct1).0, plain);
+        assert_eq!(unseal_helper(&setup, &ct2).0, plain);
+
+        // The payload of the second stream, following the (signed) header of the first.
+        let mut spliced = ct1[..payload_offset(&ct1)].to_vec();
+        spliced.extend_from_slice(&ct2[payload_offset(&ct2)..]);
+
+        assert!(matches!(
+            try_unseal_helper(&setup, &spliced),
+            Err(Error::Segment {
+                index: 0,
+                offset: 0,
+                failure: SegmentFailure::WrongTag,
+            })
+        ));
+    }
 }
@@ -0,0 +1,424 @@
+//! The segments of a payload stream, shared by the Rust and web implementations.
+//!
+//! Both implementations seal and unseal the same format, such that a payload sealed by one of
+//! them can be unsealed by the other.
+
+use crate::artifacts::{SigningKey, SigningKeyExt, VerifyingKey};
+use crate::client::SignatureMode;
+use crate::consts::*;
+use crate::error::{Error, SegmentFailure};
+use crate::identity::Policy;
+
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use futures::io::{AsyncRead, AsyncReadExt};
+use ibs::gg::{Identity, Signature, Signer, Verifier, SIG_BYTES};
+use rand::{CryptoRng, RngCore};
+use reck::Deck;
+use tiny_keccak::{Hasher, Sha3};
+
+/// Signs the segments of a payload stream.
+///
+/// The signer first processes the header and then the plaintext of every segment. A signature
+/// covers all data processed so far, followed by the counter of the segment and whether it is the
+/// final one. The header itself is always signed using [`ibs::gg`], which is also the default
+/// scheme for the segments.
+pub trait StreamSigner: Default + Clone {
+    /// The size of a serialized signature.
+    const SIG_BYTES: usize;
+
+    /// Processes data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Processes data and returns the signer.
+    fn chain(mut self, data: &[u8]) -> Self {
+        self.update(data);
+        self
+    }
+
+    /// Signs all data processed so far, returning a serialized signature of
+    /// [`StreamSigner::SIG_BYTES`] bytes.
+    fn sign<R: RngCore + CryptoRng>(self, key: &SigningKey, rng: &mut R) -> Result<Vec<u8>, Error>;
+}
+
+/// Verifies the segments of a payload stream, the counterpart of [`StreamSigner`].
+pub trait StreamVerifier: Default + Clone {
+    /// The size of a serialized signature.
+    const SIG_BYTES: usize;
+
+    /// The identity of a signer.
+    type Identity;
+
+    /// Derives the identity of a signer from its policy.
+    fn identity(pol: &Policy) -> Result<Self::Identity, Error>;
+
+    /// Processes data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Processes data and returns the verifier.
+    fn chain(mut self, data: &[u8]) -> Self {
+        self.update(data);
+        self
+    }
+
+    /// Verifies a serialized signature on all data processed so far.
+    fn verify(self, vk: &VerifyingKey, sig: &[u8], id: &Self::Identity) -> Result<bool, Error>;
+}
+
+impl StreamSigner for Signer {
+    const SIG_BYTES: usize = SIG_BYTES;
+
+    fn update(&mut self, data: &[u8]) {
+        Signer::update(self, data);
+    }
+
+    fn sign<R: RngCore + CryptoRng>(self, key: &SigningKey, rng: &mut R) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(&Signer::sign(self, &key.0, rng))?)
+    }
+}
+
+impl StreamVerifier for Verifier {
+    const SIG_BYTES: usize = SIG_BYTES;
+
+    type Identity = Identity;
+
+    fn identity(pol: &Policy) -> Result<Identity, Error> {
+        pol.derive_ibs()
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Verifier::update(self, data);
+    }
+
+    fn verify(self, vk: &VerifyingKey, sig: &[u8], id: &Identity) -> Result<bool, Error> {
+        let sig: Signature = bincode::deserialize(sig)?;
+        Ok(Verifier::verify(self, &vk.0, &sig, id))
+    }
+}
+
+/// The associated data that binds the segments to a header, see [`Header::bind_header`].
+pub(super) fn header_ad(header_raw: &[u8]) -> [u8; 32] {
+    let mut h = Sha3::v256();
+    let mut out = [0u8; 32];
+
+    h.update(header_raw);
+    h.finalize(&mut out);
+
+    out
+}
+
+/// Splits the (length of the) signing policy over the segments that it prefixes.
+///
+/// Returns the plaintext of the leading segments that are filled with a part of the policy, of
+/// which the length is flagged with [`POL_CONTINUED`], and the start of the first segment, which
+/// carries the remainder of the policy. A policy that fits in one segment is not split.
+pub(super) fn policy_segments<S: StreamSigner>(
+    signing_key: &SigningKeyExt,
+    segment_size: usize,
+) -> Result<(Vec<Vec<u8>>, Vec<u8>), Error> {
+    let pol_bytes = bincode::serialize(&signing_key.policy)?;
+    if u32::try_from(pol_bytes.len())? & POL_CONTINUED != 0 {
+        return Err(Error::ConstraintViolation);
+    }
+
+    let chunk_size = segment_size
+        .checked_sub(POL_SIZE_SIZE)
+        .filter(|&n| n > 0)
+        .ok_or(Error::ConstraintViolation)?;
+
+    let mut rest = &pol_bytes[..];
+    let mut leading = Vec::new();
+
+    while rest.len() > chunk_size {
+        let (chunk, tail) = rest.split_at(chunk_size);
+
+        let mut seg = Vec::with_capacity(segment_size + S::SIG_BYTES + STREAM_TAG_SIZE);
+        seg.extend_from_slice(&(u32::try_from(chunk_size)? | POL_CONTINUED).to_be_bytes());
+        seg.extend_from_slice(chunk);
+        leading.push(seg);
+
+        rest = tail;
+    }
+
+    let mut buf = Vec::with_capacity(segment_size + S::SIG_BYTES + STREAM_TAG_SIZE);
+    buf.extend_from_slice(&u32::try_from(rest.len())?.to_be_bytes());
+    buf.extend_from_slice(rest);
+
+    Ok((leading, buf))
+}
+
+/// Signs the segment and appends the signature.
+///
+/// Only the bytes from `start` onwards have not been processed by the signer yet.
+pub(super) fn sign_segment<Rng: RngCore + CryptoRng, S: StreamSigner>(
+    buf: &mut Vec<u8>,
+    start: usize,
+    signer: &mut S,
+    signing_key: &SigningKeyExt,
+    counter: u32,
+    is_last: bool,
+    rng: &mut Rng,
+) -> Result<(), Error> {
+    signer.update(&buf[start..]);
+    let sig = signer
+        .clone()
+        .chain(&counter.to_be_bytes())
+        .chain(&[is_last as u8])
+        .sign(&signing_key.key, rng)?;
+    buf.extend_from_slice(&sig);
+
+    Ok(())
+}
+
+/// The progress of reading the segments of a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ReadState {
+    /// The end of the stream has not been seen.
+    Reading,
+    /// The previous read returned no bytes, which is not yet taken as the end of the stream.
+    EmptyRead,
+    /// The final segment has been returned.
+    Done,
+}
+
+/// Reads, decrypts and verifies the segments of a payload, one at a time.
+pub(super) struct Segments<R, V: StreamVerifier> {
+    pub(super) r: R,
+    pub(super) dec: Deck,
+    pub(super) vk: VerifyingKey,
+    pub(super) signature_mode: SignatureMode,
+    pub(super) verifier: V,
+    // The signing policy and its identity, taken from the leading segments.
+    pub(super) pol_id: Option<(Policy, V::Identity)>,
+    // The parts of the signing policy that have been read so far.
+    pub(super) pol_bytes: Vec<u8>,
+    // The signatures of the segments that only carry a part of the signing policy, which can
+    // only be verified once the policy is complete.
+    pub(super) deferred: Vec<(u32, V, Vec<u8>)>,
+    // The current segment, followed by the lookahead and a single byte of the next segment.
+    pub(super) buf: Vec<u8>,
+    pub(super) buf_tail: usize,
+    // The segment that is being opened, which holds its plaintext afterwards. Like `buf`, it is
+    // allocated once and reused for all segments.
+    pub(super) seg: Vec<u8>,
+    pub(super) bufsize: usize,
+    pub(super) capacity: usize,
+    pub(super) counter: u32,
+    pub(super) state: ReadState,
+    pub(super) abort: Option<Arc<AtomicBool>>,
+}
+
+impl<R: AsyncRead + Unpin, V: StreamVerifier> Segments<R, V> {
+    /// Returns the plaintext of the next segment, or `None` after the final segment.
+    pub(super) async fn next_segment(&mut self) -> Result<Option<&[u8]>, Error> {
+        if self.state == ReadState::Done {
+            return Ok(None);
+        }
+
+        if self
+            .abort
+            .as_ref()
+            .is_some_and(|a| a.load(Ordering::Relaxed))
+        {
+            return Err(Error::Aborted);
+        }
+
+        loop {
+            let read = self
+                .r
+                .read(&mut self.buf[self.buf_tail..self.capacity])
+                .await?;
+            self.buf_tail += read;
+
+            if read > 0 {
+                self.state = ReadState::Reading;
+            }
+
+            if self.buf_tail == self.capacity {
+                // The bytes beyond this segment belong to the next one, so this one is not final.
+                // A full buffer is never taken as the end of the stream.
+                self.open_buffered(self.bufsize, false)?;
+                self.buf.copy_within(self.bufsize..self.capacity, 0);
+                self.buf_tail = self.capacity - self.bufsize;
+                self.counter += 1;
+
+                return Ok(Some(&self.seg));
+            } else if read == 0 && self.state == ReadState::Reading {
+                // Some readers spuriously return no bytes before yielding more. Only a second
+                // empty read is taken as the end of the stream, such that the final segment is
+                // never unwrapped while more bytes follow.
+                self.state = ReadState::EmptyRead;
+            } else if read == 0 {
+                // The final segment is the remainder of the buffer, which may be as large as any
+                // other segment (plus the lookahead).
+                self.open_buffered(self.buf_tail, true)?;
+                self.state = ReadState::Done;
+
+                return Ok(Some(&self.seg));
+            }
+        }
+    }
+
+    /// Opens the first `len` bytes of the buffer as a segment, leaving its plaintext in `seg`.
+    fn open_buffered(&mut self, len: usize, is_last: bool) -> Result<(), Error> {
+        // Neither clearing nor truncating the segment gives up its capacity.
+        let mut seg = core::mem::take(&mut self.seg);
+        seg.clear();
+        seg.extend_from_slice(&self.buf[..len]);
+
+        let res = self.open(&mut seg, is_last);
+        self.seg = seg;
+
+        res
+    }
+
+    /// Decrypts and verifies a segment in place, such that only its plaintext remains.
+    fn open(&mut self, seg: &mut Vec<u8>, is_last: bool) -> Result<(), Error> {
+        // Attributes an authentication failure to the segment at which it occurred.
+        let (counter, bufsize) = (self.counter, self.bufsize);
+        let at_segment = |failure: SegmentFailure| Error::Segment {
+            index: counter,
+            offset: u64::from(counter) * bufsize as u64,
+            failure,
+        };
+
+        // The DEM authenticates the counter of a segment, but not that it is the expected one.
+        if seg.len() >= STREAM_TAG_SIZE {
+            let at = seg.len() - STREAM_TAG_SIZE;
+            if seg[at..at + STREAM_TAG_SIZE - reck::TAG_LEN] != counter.to_be_bytes() {
+                return Err(at_segment(SegmentFailure::OutOfOrder));
+            }
+        }
+
+        // Without a signature over the final segment, a stream that is truncated at a segment
+        // boundary is only detected by the length of the final segment: the sealer always seals
+        // a full segment as a non-final one.
+        if is_last && self.signature_mode == SignatureMode::Unsigned && seg.len() >= bufsize {
+            return Err(at_segment(SegmentFailure::Truncated));
+        }
+
+        if is_last {
+            self.dec.unwrap_last(seg)
+        } else {
+            self.dec.unwrap(seg)
+        }
+        .map_err(|_e| at_segment(SegmentFailure::WrongTag))?;
+
+        // Unsigned segments carry only plaintext.
+        if self.signature_mode == SignatureMode::Unsigned {
+            return Ok(());
+        }
+
+        if self.pol_id.is_none() {
+            self.pol_id = extract_policy::<V>(seg, &mut self.pol_bytes)?;
+
+            match &self.pol_id {
+                Some((_, id)) => {
+                    for (index, verifier, sig_bytes) in self.deferred.drain(..) {
+                        if !verifier.verify(&self.vk, &sig_bytes, id)? {
+                            return Err(Error::Segment {
+                                index,
+                                offset: u64::from(index) * bufsize as u64,
+                                failure: SegmentFailure::IncorrectSignature,
+                            });
+                        }
+                    }
+                }
+                None if is_last => {
+                    return Err(Error::FormatViolation(
+                        "policy continues beyond the final segment".to_string(),
+                    ))
+                }
+                None => (),
+            }
+        }
+
+        // A segment that carries a part of the policy carries no plaintext.
+        let continued = self.pol_id.is_none();
+
+        // In detached mode, only the final segment carries a signature.
+        if is_last || self.signature_mode == SignatureMode::PerSegment {
+            let m_len = self.verify(seg, is_last).map_err(|e| match e {
+                Error::IncorrectSignature => at_segment(SegmentFailure::IncorrectSignature),
+                e => e,
+            })?;
+            seg.truncate(m_len);
+        } else {
+            self.verifier.update(seg);
+        }
+
+        if continued && !seg.is_empty() {
+            return Err(Error::FormatViolation(
+                "plaintext precedes the end of the policy".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the signature at the end of a segment, returning the length of its plaintext.
+    fn verify(&mut self, seg: &[u8], is_last: bool) -> Result<usize, Error> {
+        if seg.len() < V::SIG_BYTES {
+            return Err(Error::FormatViolation("segment too short".to_string()));
+        }
+
+        let (m, sig_bytes) = seg.split_at(seg.len() - V::SIG_BYTES);
+        self.verifier.update(m);
+
+        let verifier = self
+            .verifier
+            .clone()
+            .chain(&self.counter.to_be_bytes())
+            .chain(&[is_last as u8]);
+
+        match &self.pol_id {
+            Some((_, id)) => {
+                if !verifier.verify(&self.vk, sig_bytes, id)? {
+                    return Err(Error::IncorrectSignature);
+                }
+            }
+            None => self
+                .deferred
+                .push((self.counter, verifier, sig_bytes.to_vec())),
+        }
+
+        Ok(m.len())
+    }
+}
+
+/// Extracts (a part of) the signing policy that prefixes the plaintext of a leading segment.
+///
+/// The parts are gathered in `pol_bytes`. Returns `None` if the policy continues in the next
+/// segment, see [`policy_segments`].
+fn extract_policy<V: StreamVerifier>(
+    buf: &mut Vec<u8>,
+    pol_bytes: &mut Vec<u8>,
+) -> Result<Option<(Policy, V::Identity)>, Error> {
+    if buf.len() < POL_SIZE_SIZE {
+        return Err(Error::FormatViolation("segment too short".to_string()));
+    }
+
+    let pol_len = u32::from_be_bytes(buf[..POL_SIZE_SIZE].try_into()?);
+    let continued = pol_len & POL_CONTINUED != 0;
+    let pol_len = (pol_len & !POL_CONTINUED) as usize;
+    if buf.len() - POL_SIZE_SIZE < pol_len || pol_bytes.len() + pol_len > MAX_POL_SIZE {
+        return Err(Error::FormatViolation(
+            "policy length too large".to_string(),
+        ));
+    }
+
+    pol_bytes.extend_from_slice(&buf[POL_SIZE_SIZE..POL_SIZE_SIZE + pol_len]);
+    buf.drain(..POL_SIZE_SIZE + pol_len);
+
+    if continued {
+        return Ok(None);
+    }
+
+    let pol: Policy = bincode::deserialize(pol_bytes)?;
+    pol.validate()?;
+    let id = V::identity(&pol)?;
+
+    Ok(Some((pol, id)))
+}
@@ -7,6 +7,10 @@
 //! [`Sink<Uint8Array, Error = JsValue>`][`futures::sink::Sink`]. These can easily interact with
 //! [Web Streams](https://developer.mozilla.org/en-US/docs/Web/API/Streams_API) using the
 //! [wasm-streams](https://docs.rs/wasm-streams/latest/wasm_streams/index.html) crate.
+//! The streaming interface does not use Web Crypto, it seals the payload in the same format as
+//! the streaming interface of the Rust implementation, so that both can unseal each other's
+//! payloads. Only payloads sealed before [`VERSION_V4`](crate::consts::VERSION_V4) are still
+//! unsealed using Web Crypto.
 //!
 //! This module is only available on the `target = "wasm32-unknown-unknown"` and the output
 //! _should_ be used in browser environments. This also greatly reduces the bundle size.
@@ -38,6 +42,7 @@ use js_sys::Error as JsError;
 use js_sys::Uint8Array;
 use rand::{CryptoRng, RngCore};
 use wasm_bindgen::JsValue;
+use zeroize::Zeroize;
 
 use alloc::string::ToString;
 use alloc::vec::Vec;
@@ -55,6 +60,12 @@ pub struct UnsealerMemoryConfig {
     message_len: usize,
 }
 
+impl Drop for SealerMemoryConfig {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl SealerConfig for SealerMemoryConfig {}
 impl super::sealed::SealerConfig for SealerMemoryConfig {}
 
@@ -80,13 +91,13 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
 
         let mut key = [0u8; KEY_SIZE];
         let mut nonce = [0u8; IV_SIZE];
-        key.copy_from_slice(&ss.0[..KEY_SIZE]);
+        key.copy_from_slice(&dem_key(ss)?[..]);
         nonce.copy_from_slice(&iv.0[..IV_SIZE]);
 
         Ok(Self {
             rng,
             header,
-            pub_sign_key: pub_sign_key.clone(),
+            pub_sign_key: Some(pub_sign_key.clone()),
             priv_sign_key: None,
             config: SealerMemoryConfig { key, nonce },
         })
@@ -97,7 +108,7 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         let mut out = Vec::with_capacity(message.byte_length() as usize + 1024);
 
         out.extend_from_slice(&PRELUDE);
-        out.extend_from_slice(&VERSION_V3.to_be_bytes());
+        out.extend_from_slice(&VERSION_V4.to_be_bytes());
         self.header = self.header.with_mode(Mode::InMemory {
             size: message.byte_length(),
         });
@@ -106,12 +117,15 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         out.extend_from_slice(&(header_buf.len() as u32).to_be_bytes());
         out.extend_from_slice(&header_buf);
 
+        let pub_sign_key = self
+            .pub_sign_key
+            .ok_or(Error::SignatureModeNotSupported(SignatureMode::Unsigned))?;
         let signer = Signer::new().chain(header_buf);
-        let h_sig = signer.clone().sign(&self.pub_sign_key.key.0, self.rng);
+        let h_sig = signer.clone().sign(&pub_sign_key.key.0, self.rng);
 
         let h_sig_ext = SignatureExt {
             sig: h_sig,
-            pol: self.pub_sign_key.policy.clone(),
+            pol: pub_sign_key.policy.clone(),
         };
 
         let h_sig_ext_bytes = bincode::serialize(&h_sig_ext)?;
@@ -119,7 +133,7 @@ impl<'r, R: RngCore + CryptoRng> Sealer<'r, R, SealerMemoryConfig> {
         out.extend_from_slice(&h_sig_ext_bytes);
 
         let m = message.to_vec();
-        let m_sig_key = self.priv_sign_key.unwrap_or(self.pub_sign_key);
+        let m_sig_key = self.priv_sign_key.unwrap_or(pub_sign_key);
         let m_sig = signer.chain(&m).sign(&m_sig_key.key.0, self.rng);
 
         let input = bincode::serialize(&MessageAndSignature {
@@ -149,33 +163,43 @@ impl Unsealer<Uint8Array, UnsealerMemoryConfig> {
     /// Create a new [`Unsealer`].
     pub fn new(input: &Uint8Array, vk: &VerifyingKey) -> Result<Self, Error> {
         let b = input.to_vec();
+        if b.len() < PREAMBLE_SIZE {
+            return Err(Error::HeaderTooShort.into());
+        }
         let (preamble_bytes, b) = b.split_at(PREAMBLE_SIZE);
         let (version, header_len) = preamble_checked(preamble_bytes)?;
 
+        if b.len() < header_len + SIG_SIZE_SIZE {
+            return Err(Error::HeaderTooShort.into());
+        }
         let (header_bytes, b) = b.split_at(header_len);
         let (h_sig_len_bytes, b) = b.split_at(SIG_SIZE_SIZE);
-        let h_sig_len = u32::from_be_bytes(h_sig_len_bytes.try_into()?);
-        let (h_sig_bytes, ct) = b.split_at(h_sig_len as usize);
-
-        let h_sig_ext: SignatureExt = bincode::deserialize(h_sig_bytes)?;
-        let id = h_sig_ext.pol.derive_ibs()?;
+        let h_sig_len = header_sig_len_checked(h_sig_len_bytes.try_into()?)?;
 
-        let verifier = Verifier::default().chain(&header_bytes);
-
-        if !verifier.clone().verify(&vk.0, &h_sig_ext.sig, &id) {
-            return Err(Error::IncorrectSignature.into());
+        if b.len() < h_sig_len {
+            return Err(Error::HeaderTooShort.into());
         }
+        let (h_sig_bytes, ct) = b.split_at(h_sig_len);
 
-        let header: Header = bincode::deserialize(header_bytes)?;
+        let h_sig_ext: SignatureExt =
+            bincode::deserialize(h_sig_bytes).map_err(Error::HeaderDeserialize)?;
+        let verifier = Verifier::default().chain(&header_bytes);
+        let header: Header =
+            header_verified(version, header_bytes, &h_sig_ext, vk)?.scheme_checked()?;
         let message_len = match header.mode {
             Mode::InMemory { size } => size as usize,
             _ => return Err(Error::ModeNotSupported(header.mode).into()),
         };
 
+        if header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(header.compression).into());
+        }
+
         Ok(Self {
             version,
             header,
             pub_id: h_sig_ext.pol,
+            header_raw: header_bytes.to_vec(),
             r: Uint8Array::from(ct),
             verifier,
             vk: vk.clone(),
@@ -195,8 +219,7 @@ impl Unsealer<Uint8Array, UnsealerMemoryConfig> {
             .get(ident)
             .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
 
-        let ss = rec_info.decaps(usk)?;
-        let key = get_key(&ss.0[..KEY_SIZE]).await?;
+        let key = get_key(&dem_key(rec_info.decaps(usk)?)?[..]).await?;
 
         let Algorithm::Aes128Gcm(iv) = self.header.algo;
 
@@ -205,6 +228,7 @@ impl Unsealer<Uint8Array, UnsealerMemoryConfig> {
             .to_vec();
 
         let msg: MessageAndSignature = bincode::deserialize(&plain).map_err(Into::<Error>::into)?;
+        msg.sig.pol.validate()?;
         let id = msg.sig.pol.derive_ibs()?;
         let verified = self
             .verifier
@@ -219,22 +243,49 @@ impl Unsealer<Uint8Array, UnsealerMemoryConfig> {
 
         let res = Uint8Array::from(msg.message.as_slice());
 
-        let private = if self.pub_id == msg.sig.pol {
-            None
-        } else {
-            Some(msg.sig.pol)
-        };
-
         Ok((
             res,
-            VerificationResult {
-                public: self.pub_id,
-                private,
-            },
+            VerificationResult::new(self.pub_id, msg.sig.pol, ident, rec_info.policy.clone()),
         ))
     }
 }
 
+/// Seals a short message in one go.
+///
+/// Shorthand for constructing an in-memory [`Sealer`] and sealing `message`. The output can be
+/// unsealed by [`unseal_bytes`] and by the in-memory [`Unsealer`] using the Rust Crypto backend.
+pub async fn seal_bytes<R: RngCore + CryptoRng>(
+    mpk: &PublicKey<CGWKV>,
+    policies: &EncryptionPolicy,
+    pub_sign_key: &SigningKeyExt,
+    priv_sign_key: Option<&SigningKeyExt>,
+    rng: &mut R,
+    message: &Uint8Array,
+) -> Result<Uint8Array, Error> {
+    let mut sealer = Sealer::<_, SealerMemoryConfig>::new(mpk, policies, pub_sign_key, rng)?;
+
+    if let Some(priv_sign_key) = priv_sign_key {
+        sealer = sealer.with_priv_signing_key(priv_sign_key.clone());
+    }
+
+    sealer.seal(message).await
+}
+
+/// Unseals a message sealed in memory in one go.
+///
+/// Shorthand for constructing an in-memory [`Unsealer`] and unsealing it for `ident`, for when
+/// the recipient is known in advance and the header does not have to be inspected first.
+pub async fn unseal_bytes(
+    input: &Uint8Array,
+    vk: &VerifyingKey,
+    ident: &str,
+    usk: &UserSecretKey<CGWKV>,
+) -> Result<(Uint8Array, VerificationResult), Error> {
+    Unsealer::<_, UnsealerMemoryConfig>::new(input, vk)?
+        .unseal(ident, usk)
+        .await
+}
+
 impl From<Error> for JsValue {
     fn from(err: Error) -> Self {
         JsError::new(&err.to_string()).into()
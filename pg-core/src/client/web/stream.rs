@@ -1,19 +1,30 @@
 //! Streaming mode.
+//!
+//! The payload is sealed in the same format as by the streaming mode of the Rust implementation,
+//! using the DEM of the [`reck`] crate instead of Web Crypto. A payload sealed in the browser can
+//! therefore be unsealed by the Rust implementation, and vice versa.
+//!
+//! Before [`VERSION_V4`], the browser sealed each segment using Web Crypto AES-GCM instead. Such
+//! payloads can still be unsealed, but only by this implementation.
 
-use super::aesgcm::{decrypt, encrypt, get_key};
+use super::aesgcm::{decrypt, get_key};
 
 use crate::artifacts::{PublicKey, SigningKeyExt, UserSecretKey, VerifyingKey};
+use crate::client::segment::{header_ad, policy_segments, sign_segment, ReadState, Segments};
 use crate::client::*;
-use crate::error::Error;
+use crate::error::{Error, SegmentFailure};
 use crate::identity::{EncryptionPolicy, Policy};
-use crate::util::preamble_checked;
+use crate::util::{dem_key, header_sig_len_checked, preamble_checked};
 use ibs::gg::{Identity, Signature, Signer, Verifier, SIG_BYTES};
 
-use futures::{Sink, SinkExt, Stream, StreamExt};
+use futures::io::{Error as IoError, ErrorKind};
+use futures::{stream, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
 use ibe::kem::cgw_kv::CGWKV;
 use js_sys::Uint8Array;
 use rand::{CryptoRng, RngCore};
+use reck::Deck;
 use wasm_bindgen::{JsCast, JsValue};
+use zeroize::Zeroize;
 
 use alloc::string::ToString;
 use alloc::vec::Vec;
@@ -33,6 +44,12 @@ pub struct StreamUnsealerConfig {
     spill: Vec<u8>,
 }
 
+impl Drop for StreamSealerConfig {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl SealerConfig for StreamSealerConfig {}
 impl UnsealerConfig for StreamUnsealerConfig {}
 impl crate::client::sealed::SealerConfig for StreamSealerConfig {}
@@ -47,20 +64,20 @@ impl<'r, Rng: RngCore + CryptoRng> Sealer<'r, Rng, StreamSealerConfig> {
         rng: &'r mut Rng,
     ) -> Result<Self, Error> {
         let (header, ss) = Header::new(pk, policies, rng)?;
+        let header = header.with_bind_header(true);
 
-        let (segment_size, _) = stream_mode_checked(&header)?;
+        let StreamMode { segment_size, .. } = stream_mode_checked(&header)?;
         let Algorithm::Aes128Gcm(iv) = header.algo;
 
-        let mut key = [0u8; KEY_SIZE];
+        let key = *dem_key(ss)?;
         let mut nonce = [0u8; STREAM_NONCE_SIZE];
 
-        key.copy_from_slice(&ss.0[..KEY_SIZE]);
         nonce.copy_from_slice(&iv.0[..STREAM_NONCE_SIZE]);
 
         Ok(Sealer {
             rng,
             header,
-            pub_sign_key: pub_sign_key.clone(),
+            pub_sign_key: Some(pub_sign_key.clone()),
             priv_sign_key: None,
             config: StreamSealerConfig {
                 segment_size,
@@ -90,118 +107,108 @@ impl<'r, Rng: RngCore + CryptoRng> Sealer<'r, Rng, StreamSealerConfig> {
         });
 
         w.feed(Uint8Array::from(&PRELUDE[..]).into()).await?;
-        w.feed(Uint8Array::from(&VERSION_V3.to_be_bytes()[..]).into())
+        w.feed(Uint8Array::from(&VERSION_V4.to_be_bytes()[..]).into())
             .await?;
 
         let header_vec = bincode::serialize(&self.header)?;
 
-        w.feed(Uint8Array::from(&(header_vec.len() as u32).to_be_bytes()[..]).into())
+        w.feed(Uint8Array::from(&u32::try_from(header_vec.len())?.to_be_bytes()[..]).into())
             .await?;
 
         w.feed(Uint8Array::from(&header_vec[..]).into()).await?;
 
+        let pub_sign_key = self
+            .pub_sign_key
+            .ok_or(Error::SignatureModeNotSupported(SignatureMode::Unsigned))?;
         let mut signer = Signer::default().chain(&header_vec);
-        let header_sig = signer.clone().sign(&self.pub_sign_key.key.0, self.rng);
+        let header_sig = signer.clone().sign(&pub_sign_key.key.0, self.rng);
         let header_sig_ext = SignatureExt {
             sig: header_sig,
-            pol: self.pub_sign_key.policy.clone(),
+            pol: pub_sign_key.policy.clone(),
         };
         let header_sig_bytes = bincode::serialize(&header_sig_ext)?;
 
-        w.feed(Uint8Array::from(&(header_sig_bytes.len() as u32).to_be_bytes()[..]).into())
+        w.feed(Uint8Array::from(&u32::try_from(header_sig_bytes.len())?.to_be_bytes()[..]).into())
             .await?;
         w.feed(Uint8Array::from(&header_sig_bytes[..]).into())
             .await?;
 
-        let key = get_key(&self.config.key).await?;
+        let mut enc = Deck::new(&self.config.key, &self.config.nonce)
+            .map_err(|_e| Error::Symmetric)?
+            .with_ad(&header_ad(&header_vec));
 
         // Check for a private signing key, otherwise fall back to the public one.
-        let signing_key = self.priv_sign_key.unwrap_or(self.pub_sign_key);
-
-        let pol_bytes = bincode::serialize(&signing_key.policy)?;
-        let pol_len: u32 = pol_bytes.len() as u32;
-
-        if pol_len + POL_SIZE_SIZE as u32 > self.config.segment_size {
-            return Err(Error::ConstraintViolation.into());
-        }
-
-        let buf = Uint8Array::new_with_length(self.config.segment_size + SIG_BYTES as u32);
-
-        buf.set(
-            &Uint8Array::from(&(pol_len as u32).to_be_bytes()[..]).into(),
-            0,
-        );
-        buf.set(
-            &Uint8Array::from(&pol_bytes[..]).into(),
-            POL_SIZE_SIZE as u32,
-        );
-
+        let signing_key = self.priv_sign_key.unwrap_or(pub_sign_key);
+        let segment_size = self.config.segment_size as usize;
+
+        // Leading segments: DEM.K (pol_len | POL_CONTINUED || pol_i || sig_i)
+        // First segment: DEM.K (pol_len || pol || m_0 || sig_0 )
+        // Other segments: DEM.K (m_i || sig_i)
+        let (leading, mut buf) = policy_segments::<Signer>(&signing_key, segment_size)?;
+        let mut start = buf.len();
         let mut counter = 0u32;
-        let mut buf_tail: u32 = POL_SIZE_SIZE as u32 + pol_len;
-        let mut start: u32 = buf_tail;
-
-        while let Some(Ok(data)) = r.next().await {
-            let mut array: Uint8Array = data.dyn_into()?;
-
-            while array.byte_length() != 0 {
-                let len = array.byte_length();
-                let rem = self.config.segment_size - buf_tail;
-
-                if len < rem {
-                    buf.set(&array, buf_tail);
-                    array = Uint8Array::new_with_length(0);
-                    buf_tail += len;
-                } else {
-                    buf.set(&array.slice(0, rem), buf_tail);
-                    array = array.slice(rem, len);
-                    buf_tail += rem;
 
-                    signer.update(&buf.slice(start, buf_tail).to_vec());
-                    let sig = signer
-                        .clone()
-                        .chain(&counter.to_be_bytes())
-                        .chain(&[0x00])
-                        .sign(&signing_key.key.0, self.rng);
-                    let sig_bytes = bincode::serialize(&sig)?;
-
-                    buf.set(&Uint8Array::from(&sig_bytes[..]).into(), buf_tail);
-
-                    let ct = encrypt(
-                        &key,
-                        &aead_nonce(&self.config.nonce, counter, false),
-                        &Uint8Array::new_with_length(0),
-                        &buf,
-                    )
-                    .await?;
-
-                    w.feed(ct.into()).await?;
+        for mut seg in leading {
+            let start = seg.len();
+            sign_segment(
+                &mut seg,
+                start,
+                &mut signer,
+                &signing_key,
+                counter,
+                false,
+                self.rng,
+            )?;
+            enc.wrap(&mut seg).map_err(|_e| Error::Symmetric)?;
+
+            w.feed(Uint8Array::from(&seg[..]).into()).await?;
+            counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
+        }
 
-                    counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
-                    buf_tail = 0;
+        while let Some(data) = r.next().await {
+            let array: Uint8Array = data?.dyn_into()?;
+            let chunk = array.to_vec();
+            let mut rest = &chunk[..];
+
+            while !rest.is_empty() {
+                let n = rest.len().min(segment_size - buf.len());
+                buf.extend_from_slice(&rest[..n]);
+                rest = &rest[n..];
+
+                if buf.len() == segment_size {
+                    sign_segment(
+                        &mut buf,
+                        start,
+                        &mut signer,
+                        &signing_key,
+                        counter,
+                        false,
+                        self.rng,
+                    )?;
+                    enc.wrap(&mut buf).map_err(|_e| Error::Symmetric)?;
+
+                    w.feed(Uint8Array::from(&buf[..]).into()).await?;
+
+                    buf.clear();
                     start = 0;
+                    counter = counter.checked_add(1).ok_or(Error::Symmetric)?;
                 }
             }
         }
 
-        signer.update(&buf.slice(start, buf_tail).to_vec());
-        let sig = signer
-            .chain(&counter.to_be_bytes())
-            .chain(&[0x01])
-            .sign(&signing_key.key.0, self.rng);
-        let sig_bytes = bincode::serialize(&sig)?;
-
-        buf.set(&Uint8Array::from(&sig_bytes[..]).into(), buf_tail);
-        buf_tail += SIG_BYTES as u32;
-
-        let final_ct = encrypt(
-            &key,
-            &aead_nonce(&self.config.nonce, counter, true),
-            &Uint8Array::new_with_length(0),
-            &buf.slice(0, buf_tail),
-        )
-        .await?;
+        // The final segment is never full, it may be empty.
+        sign_segment(
+            &mut buf,
+            start,
+            &mut signer,
+            &signing_key,
+            counter,
+            true,
+            self.rng,
+        )?;
+        enc.wrap_last(&mut buf).map_err(|_e| Error::Symmetric)?;
 
-        w.feed(final_ct.into()).await?;
+        w.feed(Uint8Array::from(&buf[..]).into()).await?;
 
         w.flush().await?;
         w.close().await?;
@@ -256,7 +263,7 @@ where
         if rem == 0 {
             Ok(())
         } else {
-            Err(Error::FormatViolation("unexpected EOF".to_string()).into())
+            Err(Error::HeaderTooShort.into())
         }
     }
 }
@@ -285,26 +292,31 @@ where
 
         let mut h_sig_len_bytes = [0u8; SIG_SIZE_SIZE];
         read_atleast(&mut r, &mut h_sig_len_bytes, &mut spill).await?;
-        let header_sig_len = u32::from_be_bytes(h_sig_len_bytes);
+        let header_sig_len = header_sig_len_checked(h_sig_len_bytes)?;
 
-        let mut header_sig_raw = vec![0u8; header_sig_len as usize];
+        let mut header_sig_raw = vec![0u8; header_sig_len];
         read_atleast(&mut r, &mut header_sig_raw, &mut spill).await?;
-        let h_sig_ext: SignatureExt = bincode::deserialize(&header_sig_raw)?;
+        let h_sig_ext: SignatureExt =
+            bincode::deserialize(&header_sig_raw).map_err(Error::HeaderDeserialize)?;
 
         let verifier = Verifier::default().chain(&header_raw);
-        let pub_id = h_sig_ext.pol.derive_ibs()?;
+        let header: Header =
+            header_verified(version, &header_raw, &h_sig_ext, vk)?.scheme_checked()?;
+        let StreamMode { segment_size, .. } = stream_mode_checked(&header)?;
 
-        if !verifier.clone().verify(&vk.0, &h_sig_ext.sig, &pub_id) {
-            return Err(Error::IncorrectSignature.into());
+        if header.compression != Compression::None {
+            return Err(Error::CompressionNotSupported(header.compression));
         }
 
-        let header: Header = bincode::deserialize(&header_raw)?;
-        let (segment_size, _) = stream_mode_checked(&header)?;
+        if header.signature_mode != SignatureMode::PerSegment {
+            return Err(Error::SignatureModeNotSupported(header.signature_mode));
+        }
 
         Ok(Unsealer {
             version,
             header,
             pub_id: h_sig_ext.pol,
+            header_raw,
             verifier,
             vk: vk.clone(),
             r,
@@ -325,14 +337,98 @@ where
     where
         W: Sink<JsValue, Error = JsValue> + Unpin,
     {
+        if self.version < VERSION_V4 {
+            return self.unseal_legacy(ident, usk, w).await;
+        }
+
         let rec_info = self
             .header
             .recipients
             .get(ident)
             .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
 
-        let ss = rec_info.decaps(usk)?;
-        let key = get_key(&ss.0[..KEY_SIZE]).await?;
+        let key = dem_key(rec_info.decaps(usk)?)?;
+
+        let Algorithm::Aes128Gcm(iv) = self.header.algo;
+        let nonce = &iv.0[..STREAM_NONCE_SIZE];
+
+        let dec = Deck::new(&key[..], nonce).map_err(|_e| Error::Symmetric)?;
+        let dec = if self.header.bind_header {
+            dec.with_ad(&header_ad(&self.header_raw))
+        } else {
+            dec
+        };
+
+        // The bytes that were read beyond the header precede the rest of the input.
+        let spill = core::mem::take(&mut self.config.spill);
+        let r = stream::iter([Ok(spill)])
+            .chain((&mut self.r).map(|data| {
+                data.and_then(|data| data.dyn_into::<Uint8Array>())
+                    .map(|array| array.to_vec())
+                    .map_err(|e| IoError::new(ErrorKind::Other, alloc::format!("{e:?}")))
+            }))
+            .into_async_read();
+
+        // Only per-segment signatures are supported, so no lookahead is needed to know whether a
+        // segment is the final one, apart from a single byte of the next segment.
+        let bufsize = self.config.segment_size as usize + SIG_BYTES + STREAM_TAG_SIZE;
+        let capacity = bufsize + 1;
+
+        let mut segments = Segments {
+            r,
+            dec,
+            vk: self.vk.clone(),
+            signature_mode: self.header.signature_mode,
+            verifier: self.verifier.clone(),
+            pol_id: None,
+            pol_bytes: Vec::new(),
+            deferred: Vec::new(),
+            buf: vec![0u8; capacity],
+            buf_tail: 0,
+            seg: Vec::with_capacity(capacity),
+            bufsize,
+            capacity,
+            counter: 0,
+            state: ReadState::Reading,
+            abort: None,
+        };
+
+        while let Some(m) = segments.next_segment().await? {
+            w.feed(Uint8Array::from(m).into()).await?;
+        }
+
+        w.flush().await?;
+        w.close().await?;
+
+        let (pol, _) = segments
+            .pol_id
+            .ok_or_else(|| Error::FormatViolation("missing policy".to_string()))?;
+
+        Ok(VerificationResult {
+            // The counter is that of the final segment.
+            segments: segments.counter + 1,
+            ..VerificationResult::new(self.pub_id.clone(), pol, ident, rec_info.policy.clone())
+        })
+    }
+
+    /// Unseals a payload of which each segment was sealed using Web Crypto AES-GCM, as the
+    /// browser did before [`VERSION_V4`].
+    async fn unseal_legacy<W>(
+        &mut self,
+        ident: &str,
+        usk: &UserSecretKey<CGWKV>,
+        mut w: W,
+    ) -> Result<VerificationResult, Error>
+    where
+        W: Sink<JsValue, Error = JsValue> + Unpin,
+    {
+        let rec_info = self
+            .header
+            .recipients
+            .get(ident)
+            .ok_or_else(|| Error::UnknownIdentifier(ident.to_string()))?;
+
+        let key = get_key(&dem_key(rec_info.decaps(usk)?)?[..]).await?;
 
         let Algorithm::Aes128Gcm(iv) = self.header.algo;
         let nonce = &iv.0[..STREAM_NONCE_SIZE];
@@ -344,13 +440,31 @@ where
         let mut buf_tail = 0;
         let mut pol_id: Option<(Policy, Identity)> = None;
 
+        // Attributes an authentication failure to the segment at which it occurred.
+        let at_segment = |counter: u32, failure: SegmentFailure| Error::Segment {
+            index: counter,
+            offset: u64::from(counter) * u64::from(segment_size),
+            failure,
+        };
+
         fn extract_policy(
             plain: Uint8Array,
         ) -> Result<(Option<(Policy, Identity)>, Uint8Array), Error> {
+            if plain.byte_length() < POL_SIZE_SIZE as u32 {
+                return Err(Error::FormatViolation("segment too short".to_string()));
+            }
+
             let pol_len =
                 u32::from_be_bytes(plain.slice(0, POL_SIZE_SIZE as u32).to_vec()[..].try_into()?);
+            if plain.byte_length() - (POL_SIZE_SIZE as u32) < pol_len {
+                return Err(Error::FormatViolation(
+                    "policy length too large".to_string(),
+                ));
+            }
+
             let pol_bytes = plain.slice(POL_SIZE_SIZE as u32, POL_SIZE_SIZE as u32 + pol_len);
             let pol: Policy = bincode::deserialize(&pol_bytes.to_vec())?;
+            pol.validate()?;
             let id = pol.derive_ibs()?;
             let new_plain = plain.slice(POL_SIZE_SIZE as u32 + pol_len, plain.byte_length());
 
@@ -387,13 +501,16 @@ where
                         &Uint8Array::new_with_length(0),
                         &buf,
                     )
-                    .await?;
+                    .await
+                    .map_err(|_e| at_segment(counter, SegmentFailure::WrongTag))?;
 
                     if counter == 0 {
                         (pol_id, plain) = extract_policy(plain)?;
                     }
 
-                    debug_assert!(plain.byte_length() > SIG_BYTES as u32);
+                    if plain.byte_length() < SIG_BYTES as u32 {
+                        return Err(Error::FormatViolation("segment too short".to_string()).into());
+                    }
 
                     let m = plain.slice(0, plain.byte_length() - SIG_BYTES as u32);
                     let sig =
@@ -409,7 +526,7 @@ where
                         .chain(&[0x00])
                         .verify(&self.vk.0, &sig, &pol_id.as_ref().unwrap().1)
                     {
-                        return Err(Error::IncorrectSignature.into());
+                        return Err(at_segment(counter, SegmentFailure::IncorrectSignature));
                     }
 
                     w.feed(m.into()).await?;
@@ -426,13 +543,17 @@ where
             &Uint8Array::new_with_length(0),
             &buf.slice(0, buf_tail),
         )
-        .await?;
+        .await
+        .map_err(|_e| at_segment(counter, SegmentFailure::WrongTag))?;
 
         if counter == 0 {
             (pol_id, final_plain) = extract_policy(final_plain)?;
         }
 
-        debug_assert!(final_plain.byte_length() > SIG_BYTES as u32);
+        if final_plain.byte_length() < SIG_BYTES as u32 {
+            return Err(Error::FormatViolation("segment too short".to_string()).into());
+        }
+
         let m = final_plain.slice(0, final_plain.byte_length() - SIG_BYTES as u32);
         let sig = final_plain.slice(
             final_plain.byte_length() - SIG_BYTES as u32,
@@ -448,7 +569,7 @@ where
             .chain(&[0x01])
             .verify(&self.vk.0, &sig, &pol_id.as_ref().unwrap().1)
         {
-            return Err(Error::IncorrectSignature.into());
+            return Err(at_segment(counter, SegmentFailure::IncorrectSignature));
         }
 
         w.feed(m.into()).await?;
@@ -456,16 +577,15 @@ where
         w.flush().await?;
         w.close().await?;
 
-        let private_id = pol_id.unwrap().0;
-        let private = if self.pub_id == private_id {
-            None
-        } else {
-            Some(private_id)
-        };
-
         Ok(VerificationResult {
-            public: self.pub_id.clone(),
-            private,
+            // The counter is that of the final segment.
+            segments: counter + 1,
+            ..VerificationResult::new(
+                self.pub_id.clone(),
+                pol_id.unwrap().0,
+                ident,
+                rec_info.policy.clone(),
+            )
         })
     }
 }
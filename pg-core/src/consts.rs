@@ -14,13 +14,20 @@ pub const VERSION_V1: u16 = 0;
 /// The header format is defined by MessagePack.
 pub const VERSION_V2: u16 = 1;
 
-/// Version 2.
+/// Version 2 (legacy).
 ///
 /// This version uses the CGW anonymous IBE scheme to construct a KEM variant.
 /// The scheme supports a Sign-then-Encrypt composition using the GG-IBS scheme.
-/// The binary header format is defined by Bincode.
+/// The binary header format is defined by Bincode. Headers of this version are still unsealed.
 pub const VERSION_V3: u16 = 2;
 
+/// Version 3.
+///
+/// Extends the header of [`VERSION_V3`] with the compression, the KEM, the signature mode, the
+/// binding of segments to the header, the signature scheme and public metadata. Bincode is not
+/// self-describing, so the header cannot be extended without a new version.
+pub const VERSION_V4: u16 = 3;
+
 /// The size of the tag with which all PostGuard bytestreams begin.
 pub const PRELUDE_SIZE: usize = 4;
 
@@ -39,6 +46,15 @@ pub const SIG_SIZE_SIZE: usize = core::mem::size_of::<u32>();
 /// The size of the policy size.
 pub const POL_SIZE_SIZE: usize = core::mem::size_of::<u32>();
 
+/// Flag in the policy size, set if the signing policy continues in the next segment.
+pub const POL_CONTINUED: u32 = 1 << 31;
+
+/// The maximum size of the signing policy in a stream (1 MiB).
+pub const MAX_POL_SIZE: usize = 1024 * 1024;
+
+/// The maximum size of the header signature (64 KiB).
+pub const MAX_HEADER_SIG_SIZE: usize = 1024 * 64;
+
 /// The maximum size of the header (1 MiB).
 pub const MAX_HEADER_SIZE: usize = 1024 * 1024;
 
@@ -76,3 +92,7 @@ pub const STREAM_NONCE_SIZE: usize = 7;
 /// Size of the authentication tag.
 /// The authentication tag is appended to each segment.
 pub const TAG_SIZE: usize = 16;
+
+/// Size of the counter and authentication tag that the native stream DEM (DECK)
+/// appends to each segment.
+pub const STREAM_TAG_SIZE: usize = 4 + reck::TAG_LEN;
@@ -2,7 +2,7 @@
 
 use core::{array::TryFromSliceError, num::TryFromIntError};
 
-use crate::client::{Algorithm, Mode};
+use crate::client::{Algorithm, Compression, Mode, SignatureMode};
 
 #[allow(unused)]
 use alloc::string::{String, ToString};
@@ -18,13 +18,10 @@ use wasm_bindgen::JsValue;
 pub enum Error {
     /// The packet/bytestream does not start with the expected prelude.
     NotPostGuard,
-    /// The wrong version specifier was found in the header.
-    IncorrectVersion {
-        /// The expected version specifier.
-        expected: u16,
-        /// The found version specifier,
-        found: u16,
-    },
+    /// The bytestream starts with the expected prelude, but its version is not supported.
+    ///
+    /// This is likely a bytestream produced by a newer version of PostGuard.
+    UnsupportedVersion(u16),
     /// Serde JSON error.
     Json(serde_json::Error),
     /// Bincode serialization/deserialization error.
@@ -37,16 +34,53 @@ pub enum Error {
     ConstraintViolation,
     /// Format violation.
     FormatViolation(String),
+    /// The bytestream ended before the header was read completely.
+    HeaderTooShort,
+    /// A length prefix (of the header or header signature) is out of bounds.
+    BadLengthPrefix,
+    /// The header or header signature could not be deserialized.
+    HeaderDeserialize(bincode::Error),
+    /// The header found in the output of an interrupted seal differs from that of the sealer
+    /// that resumes it.
+    HeaderMismatch,
     /// Opaque symmetric encryption error.
     Symmetric,
     /// The symmetric encryption algorithm is not supported.
     AlgorithmNotSupported(Algorithm),
     /// The encryption mode is not supported.
     ModeNotSupported(Mode),
+    /// The compression codec is not supported.
+    CompressionNotSupported(Compression),
+    /// The signature mode is not supported.
+    SignatureModeNotSupported(SignatureMode),
     /// Opaque key encapsulation error.
     KEM,
+    /// The user secret key does not decapsulate the key of the recipient.
+    ///
+    /// The key is well-formed and of the right scheme, since malformed keys are rejected when
+    /// they are deserialized and keys of another scheme by their type, but it was issued for a
+    /// different identity or policy than that of the recipient.
+    WrongUserSecretKey,
     /// The identity-based signature did not verify.
     IncorrectSignature,
+    /// The disclosed attributes do not match a hidden policy, see [`HiddenPolicy::reveal`].
+    ///
+    /// Contains the attribute type that is missing, superfluous or whose value does not match
+    /// the hint.
+    ///
+    /// [`HiddenPolicy::reveal`]: crate::identity::HiddenPolicy::reveal
+    DisclosureMismatch(String),
+    /// A segment of a streamed payload could not be authenticated.
+    Segment {
+        /// The index of the segment, i.e., its counter.
+        index: u32,
+        /// The offset (in bytes) of the segment in the encrypted payload.
+        offset: u64,
+        /// The reason the segment could not be authenticated.
+        failure: SegmentFailure,
+    },
+    /// Unsealing was aborted on request, before the end of the payload.
+    Aborted,
     /// Opaque asynchronous IO error from the futures crate.
     #[cfg(feature = "stream")]
     FuturesIO(FuturesIOError),
@@ -61,11 +95,13 @@ impl core::fmt::Display for Error {
             Self::NotPostGuard => {
                 write!(f, "the bytestream does not start with the expected prelude")
             }
-            Self::IncorrectVersion { expected, found } => {
-                write!(f, "wrong version, expected: {expected}, found: {found}")
-            }
+            Self::UnsupportedVersion(v) => write!(f, "unsupported version: {v}"),
             Self::UnknownIdentifier(ident) => write!(f, "recipient unknown: {ident}"),
             Self::FormatViolation(s) => write!(f, "{s} not (correctly) found in format"),
+            Self::HeaderTooShort => write!(f, "the bytestream ended before the end of the header"),
+            Self::BadLengthPrefix => write!(f, "length prefix out of bounds"),
+            Self::HeaderDeserialize(e) => write!(f, "could not deserialize header: {e}"),
+            Self::HeaderMismatch => write!(f, "the header does not match that of the output"),
             Self::Bincode(e) => {
                 write!(f, "Bincode error: {e}")
             }
@@ -75,8 +111,29 @@ impl core::fmt::Display for Error {
             Self::Symmetric => write!(f, "symmetric encryption operation error"),
             Self::AlgorithmNotSupported(a) => write!(f, "algorithm is not supported: {a:?}"),
             Self::ModeNotSupported(m) => write!(f, "mode is not supported: {m:?}"),
+            Self::CompressionNotSupported(c) => write!(f, "compression is not supported: {c:?}"),
+            Self::SignatureModeNotSupported(m) => {
+                write!(f, "signature mode is not supported: {m:?}")
+            }
             Self::KEM => write!(f, "KEM error"),
+            Self::WrongUserSecretKey => write!(
+                f,
+                "the user secret key was issued for a different identity or policy"
+            ),
             Self::IncorrectSignature => write!(f, "incorrect signature"),
+            Self::DisclosureMismatch(atype) => write!(
+                f,
+                "disclosed attribute does not match the hidden policy: {atype}"
+            ),
+            Self::Segment {
+                index,
+                offset,
+                failure,
+            } => write!(
+                f,
+                "authentication failed at segment {index} (byte offset {offset}): {failure}"
+            ),
+            Self::Aborted => write!(f, "unsealing was aborted"),
             #[cfg(feature = "stream")]
             Self::FuturesIO(e) => write!(f, "futures IO error: {e}"),
             #[cfg(feature = "web")]
@@ -89,6 +146,31 @@ impl core::fmt::Display for Error {
     }
 }
 
+/// The reason a segment could not be authenticated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentFailure {
+    /// The authentication tag of the symmetric encryption did not verify.
+    WrongTag,
+    /// The identity-based signature did not verify.
+    IncorrectSignature,
+    /// The segment carried the counter of another position in the stream, e.g., because segments
+    /// were reordered, duplicated or dropped.
+    OutOfOrder,
+    /// The stream ended with a segment that cannot be the final one, i.e., it was truncated.
+    Truncated,
+}
+
+impl core::fmt::Display for SegmentFailure {
+    fn fmt(&self, f: &mut alloc::fmt::Formatter<'_>) -> alloc::fmt::Result {
+        match self {
+            Self::WrongTag => write!(f, "wrong tag"),
+            Self::IncorrectSignature => write!(f, "incorrect signature"),
+            Self::OutOfOrder => write!(f, "segment out of order"),
+            Self::Truncated => write!(f, "stream truncated"),
+        }
+    }
+}
+
 impl From<bincode::Error> for Error {
     fn from(e: bincode::Error) -> Self {
         Self::Bincode(e)
@@ -4,16 +4,45 @@ use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec::Vec;
+use core::time::Duration;
 use ibs::gg::Identity;
 
 use crate::error::Error;
+use ibe::kem::cgw_kv::CGWKV;
 use ibe::kem::IBKEM;
 use ibe::Derive;
 use serde::{Deserialize, Serialize};
+use subtle::{Choice, ConstantTimeEq};
 use tiny_keccak::{Hasher, Sha3};
 
 const IDENTITY_UNSET: u64 = u64::MAX;
+const IDENTITY_WILDCARD: u64 = u64::MAX - 1;
 const MAX_CON: usize = (IDENTITY_UNSET as usize - 1) >> 1;
+/// The version of the identity derivation, used as the domain separator of [`Policy::derive`].
+///
+/// Bumping this version invalidates all previously derived identities: keys issued for, and
+/// ciphertexts sealed under, identities of another version no longer match.
+pub const DERIVE_VERSION: u8 = 0x00;
+
+/// The authentication method of the identity derivation, which precedes the attributes.
+const DERIVE_METHOD_IRMA: u8 = 0x00;
+
+/// The value that marks an attribute as a wildcard, see [`AttributeValue::Wildcard`].
+///
+/// IRMA discloses attribute values as text that never contains a NUL character, so this is not
+/// the value of any disclosed attribute. Since the wildcard is part of the value, the encoding of
+/// an [`Attribute`] in existing headers is unchanged.
+pub const WILDCARD_VALUE: &str = "\0*";
+
+/// The maximum length (in bytes) of an attribute type, see [`Attribute::validate`].
+pub const MAX_ATTRIBUTE_TYPE_LEN: usize = 256;
+
+/// The maximum length (in bytes) of an attribute value, see [`Attribute::validate`].
+pub const MAX_ATTRIBUTE_VALUE_LEN: usize = 1024;
+
+/// The maximum number of attributes in a conjunction, see [`validate_con`].
+pub const MAX_CON_LEN: usize = 64;
+
 const AMOUNT_CHARS_TO_HIDE: usize = 4;
 const HINT_TYPES: &[&str] = &[
     "pbdf.sidn-pbdf.mobilenumber.mobilenumber",
@@ -38,6 +67,59 @@ pub struct Attribute {
     pub value: Option<String>,
 }
 
+/// A typed attribute value.
+///
+/// IRMA discloses all attribute values as text, so values are stored in an [`Attribute`] using
+/// their canonical text encoding. This ensures that semantically equal values, such as `5` and
+/// `05`, always derive the same identity when they are constructed from this type.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AttributeValue {
+    /// A text value, which is used as-is.
+    Text(String),
+
+    /// An integer value, encoded in decimal without leading zeros or a plus sign.
+    Integer(i64),
+
+    /// No value, i.e., the attribute explicitly has no value.
+    Null,
+
+    /// Any value, i.e., the attribute must be present but its value is not restricted.
+    ///
+    /// Derives an identity distinct from both [`AttributeValue::Null`] and any concrete value.
+    /// Stored as [`WILDCARD_VALUE`].
+    Wildcard,
+}
+
+impl AttributeValue {
+    /// The canonical encoding of this value, as used by [`Policy::derive`].
+    ///
+    /// [`AttributeValue::Null`] has no encoding, and [`AttributeValue::Wildcard`] is encoded as
+    /// [`WILDCARD_VALUE`].
+    pub fn canonical(&self) -> Option<String> {
+        match self {
+            AttributeValue::Text(s) => Some(s.clone()),
+            AttributeValue::Integer(i) => Some(i.to_string()),
+            AttributeValue::Null => None,
+            AttributeValue::Wildcard => Some(WILDCARD_VALUE.to_string()),
+        }
+    }
+}
+
+/// A KEM whose identities are derived from the 64-byte state computed by [`Policy::derive`].
+pub trait DeriveFromState: IBKEM {
+    /// Derives a KEM identity from the state.
+    ///
+    /// By default, the state is hashed once more by [`Derive::derive`]. KEMs whose identities can
+    /// be constructed from a uniformly random 64-byte string directly can override this, which
+    /// saves a Sha3-512 per derivation.
+    fn derive_from_state(state: &[u8; 64]) -> Self::Id {
+        Self::Id::derive(state)
+    }
+}
+
+// Overriding this would change all identities, which invalidates existing keys and ciphertexts.
+impl DeriveFromState for CGWKV {}
+
 /// An PostGuard policy used to encapsulate a shared secret for one recipient.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
 pub struct Policy {
@@ -65,6 +147,11 @@ pub struct HiddenPolicy {
 
 impl Attribute {
     fn hintify_value(&self) -> Attribute {
+        // A wildcard reveals nothing, but must remain recognizable.
+        if self.is_wildcard() {
+            return self.clone();
+        }
+
         let hidden_value = self.value.as_ref().map(|v| {
             if HINT_TYPES.contains(&&self.atype[..]) {
                 let (begin, end) = v.split_at(v.len().saturating_sub(AMOUNT_CHARS_TO_HIDE));
@@ -81,7 +168,49 @@ impl Attribute {
     }
 }
 
+/// Checks that a conjunction and its attributes are within the size limits.
+///
+/// Errors with [`Error::ConstraintViolation`] if the conjunction has more than [`MAX_CON_LEN`]
+/// attributes, or if any of them is invalid (see [`Attribute::validate`]).
+pub fn validate_con(con: &[Attribute]) -> Result<(), Error> {
+    if con.len() > MAX_CON_LEN {
+        return Err(Error::ConstraintViolation);
+    }
+
+    con.iter().try_for_each(Attribute::validate)
+}
+
 impl Policy {
+    /// Checks that the policy is within the size limits, see [`validate_con`].
+    ///
+    /// Policies that are deserialized from untrusted input must be validated before use, e.g.,
+    /// before deriving an identity.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_con(&self.con)
+    }
+
+    /// The time that has passed since the timestamp of the policy, at UNIX time `now`.
+    ///
+    /// A timestamp in the future yields an age of zero.
+    pub fn age(&self, now: u64) -> Duration {
+        Duration::from_secs(now.saturating_sub(self.timestamp))
+    }
+
+    /// Whether the policy is older than `max_age` at UNIX time `now`, see [`Policy::age`].
+    pub fn is_expired(&self, now: u64, max_age: Duration) -> bool {
+        self.age(now) > max_age
+    }
+
+    /// Checks that the timestamp of the policy lies at most `window` before or after UNIX time
+    /// `now`.
+    ///
+    /// A timestamp far in the future usually stems from a skewed clock, and no key can be
+    /// retrieved for the identity until that time. Errors with [`Error::ConstraintViolation`]
+    /// otherwise.
+    pub fn timestamp_sanity(&self, now: u64, window: Duration) -> Result<(), Error> {
+        timestamp_within(self.timestamp, now, window)
+    }
+
     /// Completely hides the attribute value, or provides a hint for certain attribute types
     pub fn to_hidden(&self) -> HiddenPolicy {
         HiddenPolicy {
@@ -90,15 +219,29 @@ impl Policy {
         }
     }
 
-    /// Derives an 64-byte identity from a [`Policy`].
+    /// Derives an 64-byte identity from a [`Policy`], using the current [`DERIVE_VERSION`].
     pub fn derive(&self) -> Result<[u8; 64], Error> {
+        self.derive_versioned(DERIVE_VERSION)
+    }
+
+    /// Derives an 64-byte identity from a [`Policy`] for a given version of the derivation.
+    ///
+    /// Identities of different versions never coincide. During a migration, this can be used to
+    /// compute both the old and the new identity of a policy. The version is separate from the
+    /// authentication method, version 0 is the original derivation, which has no version byte.
+    pub fn derive_versioned(&self, version: u8) -> Result<[u8; 64], Error> {
         // This method implements domain separation as follows:
         // Suppose we have the following policy:
         //  - con[0..n - 1] consisting of n conjunctions.
         //  - timestamp
-        // = H(0 || f_0 || f'_0 ||  .. || f_{n-1} || f'_{n-1} || timestamp),
+        // = H([version] || method || f_0 || f'_0 ||  .. || f_{n-1} || f'_{n-1} || timestamp),
         // where f_i  = H(2i + 1 || a.typ.len() || a.typ),
         // and   f'_i = H(2i + 2 || a.val.len() || a.val).
+        // The method is the authentication method, of which IRMA (0) is the only one. The version
+        // byte is omitted for version 0, so the inputs of version 0 and of the other versions
+        // differ in length modulo 128 and never coincide.
+        // A missing value is encoded as H(2i + 2 || u64::MAX), and a wildcard as
+        // H(2i + 2 || u64::MAX - 1), neither of which can be a valid length.
         //
         // Conjunction is sorted. This requires that Attribute implements a stable Ord.
         // Since lengths encoded as usize are not platform-agnostic, we convert all
@@ -111,8 +254,11 @@ impl Policy {
         let mut tmp = [0u8; 64];
         let mut pre_h = Sha3::v512();
 
-        // 0 indicates the IRMA authentication method.
-        pre_h.update(&[0x00]);
+        if version != 0 {
+            pre_h.update(&[version]);
+        }
+
+        pre_h.update(&[DERIVE_METHOD_IRMA]);
 
         let mut copy = self.con.clone();
         copy.sort();
@@ -133,6 +279,7 @@ impl Policy {
             f.update(&((2 * i + 2) as u64).to_be_bytes());
 
             match &ar.value {
+                _ if ar.is_wildcard() => f.update(&IDENTITY_WILDCARD.to_be_bytes()),
                 None => f.update(&IDENTITY_UNSET.to_be_bytes()),
                 Some(val) => {
                     let val_bytes = val.as_bytes();
@@ -153,8 +300,8 @@ impl Policy {
     }
 
     /// Derive a KEM identity from a [`Policy`].
-    pub fn derive_kem<K: IBKEM>(&self) -> Result<<K as IBKEM>::Id, Error> {
-        Ok(<K as IBKEM>::Id::derive(&self.derive()?))
+    pub fn derive_kem<K: DeriveFromState>(&self) -> Result<<K as IBKEM>::Id, Error> {
+        Ok(K::derive_from_state(&self.derive()?))
     }
 
     /// Derive an IBS identity from a [`Policy`].
@@ -163,7 +310,117 @@ impl Policy {
     }
 }
 
+impl HiddenPolicy {
+    /// Checks that the hidden policy is within the size limits, see [`validate_con`].
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_con(&self.con)
+    }
+
+    /// Checks the timestamp of the hidden policy, see [`Policy::timestamp_sanity`].
+    pub fn timestamp_sanity(&self, now: u64, window: Duration) -> Result<(), Error> {
+        timestamp_within(self.timestamp, now, window)
+    }
+
+    /// Reconstructs the [`Policy`] from the attributes disclosed by the user, in any order.
+    ///
+    /// Every attribute of the hidden policy must be matched by exactly one disclosed attribute of
+    /// the same type, whose hidden value equals the hint. Errors with
+    /// [`Error::DisclosureMismatch`] otherwise, or if attributes are left over.
+    pub fn reveal(&self, disclosed: &[Attribute]) -> Result<Policy, Error> {
+        let mut left: Vec<&Attribute> = disclosed.iter().collect();
+
+        let con = self
+            .con
+            .iter()
+            .map(|hidden| {
+                let pos = left
+                    .iter()
+                    .position(|attr| attr.hintify_value() == *hidden)
+                    .ok_or_else(|| Error::DisclosureMismatch(hidden.atype.clone()))?;
+
+                Ok(left.swap_remove(pos).clone())
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if let Some(attr) = left.first() {
+            return Err(Error::DisclosureMismatch(attr.atype.clone()));
+        }
+
+        Ok(Policy {
+            timestamp: self.timestamp,
+            con,
+        })
+    }
+
+    /// Derives the identity of the policy revealed by `disclosed`, see [`HiddenPolicy::reveal`].
+    ///
+    /// Can be used to confirm that the disclosed attributes yield the identity of a recipient.
+    pub fn derive_disclosed(&self, disclosed: &[Attribute]) -> Result<[u8; 64], Error> {
+        self.reveal(disclosed)?.derive()
+    }
+}
+
+/// Checks that `timestamp` lies at most `window` before or after `now`.
+fn timestamp_within(timestamp: u64, now: u64, window: Duration) -> Result<(), Error> {
+    if timestamp.abs_diff(now) > window.as_secs() {
+        return Err(Error::ConstraintViolation);
+    }
+
+    Ok(())
+}
+
+/// Compares two conjunctions in constant time, up to their lengths, see [`Policy::ct_eq`].
+fn con_ct_eq(con1: &[Attribute], con2: &[Attribute]) -> Choice {
+    con1.iter().zip(con2).fold(
+        (con1.len() as u64).ct_eq(&(con2.len() as u64)),
+        |eq, (a1, a2)| eq & a1.ct_eq(a2),
+    )
+}
+
+impl ConstantTimeEq for Attribute {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let value1 = self.value.as_deref().unwrap_or_default();
+        let value2 = other.value.as_deref().unwrap_or_default();
+
+        self.atype.as_bytes().ct_eq(other.atype.as_bytes())
+            & (self.value.is_some() as u8).ct_eq(&(other.value.is_some() as u8))
+            & value1.as_bytes().ct_eq(value2.as_bytes())
+    }
+}
+
+/// Compares two policies without short-circuiting.
+///
+/// The comparison takes time independent of where the policies differ, but not of the lengths
+/// of the conjunction and the attributes. Prefer this over `==` if the outcome influences the
+/// handling of secret data.
+impl ConstantTimeEq for Policy {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.timestamp.ct_eq(&other.timestamp) & con_ct_eq(&self.con, &other.con)
+    }
+}
+
+/// Compares two hidden policies without short-circuiting, see [`Policy::ct_eq`].
+impl ConstantTimeEq for HiddenPolicy {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.timestamp.ct_eq(&other.timestamp) & con_ct_eq(&self.con, &other.con)
+    }
+}
+
 impl Attribute {
+    /// Checks that the attribute type and value are within the size limits.
+    ///
+    /// Errors with [`Error::ConstraintViolation`] if the type is longer than
+    /// [`MAX_ATTRIBUTE_TYPE_LEN`] or the value is longer than [`MAX_ATTRIBUTE_VALUE_LEN`] bytes.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.atype.len() > MAX_ATTRIBUTE_TYPE_LEN
+            || self.value.as_ref().map_or(0, String::len) > MAX_ATTRIBUTE_VALUE_LEN
+        {
+            return Err(Error::ConstraintViolation);
+        }
+
+        Ok(())
+    }
+
     /// Construct a new attribute request.
     pub fn new(atype: &str, value: Option<&str>) -> Self {
         let atype = atype.to_string();
@@ -171,16 +428,173 @@ impl Attribute {
 
         Attribute { atype, value }
     }
+
+    /// Construct a new attribute request from a typed value.
+    pub fn new_typed(atype: &str, value: AttributeValue) -> Self {
+        Attribute {
+            atype: atype.to_string(),
+            value: value.canonical(),
+        }
+    }
+
+    /// Whether any value of the attribute type is accepted, see [`AttributeValue::Wildcard`].
+    pub fn is_wildcard(&self) -> bool {
+        self.value.as_deref() == Some(WILDCARD_VALUE)
+    }
+
+    /// The typed value of this attribute.
+    ///
+    /// Values are returned as [`AttributeValue::Text`], since integers cannot be told apart from
+    /// their canonical text encoding.
+    pub fn typed_value(&self) -> AttributeValue {
+        match &self.value {
+            _ if self.is_wildcard() => AttributeValue::Wildcard,
+            None => AttributeValue::Null,
+            Some(v) => AttributeValue::Text(v.clone()),
+        }
+    }
+
+    /// Checks whether a disclosed attribute is consistent with this hidden attribute, e.g., taken
+    /// from a [`HiddenPolicy`].
+    ///
+    /// This is the inverse of the hinting done by [`Policy::to_hidden`]. A fully masked value
+    /// matches any value of the same attribute type, whereas a hint only matches if its prefix
+    /// and length do.
+    pub fn matches(&self, full: &Attribute) -> bool {
+        if self.atype != full.atype || self.is_wildcard() != full.is_wildcard() {
+            return false;
+        }
+
+        match (&self.value, &full.value) {
+            (None, None) => true,
+            (Some(hidden), Some(_)) => {
+                hidden.chars().all(|c| c == '*')
+                    || full.hintify_value().value.as_deref() == Some(hidden.as_str())
+            }
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::identity::{Attribute, Policy};
+    use crate::error::Error;
+    use crate::identity::{
+        Attribute, AttributeValue, Policy, DERIVE_VERSION, MAX_ATTRIBUTE_TYPE_LEN,
+        MAX_ATTRIBUTE_VALUE_LEN, MAX_CON_LEN,
+    };
     use crate::test::TestSetup;
     use alloc::string::ToString;
     use alloc::vec::Vec;
+    use core::time::Duration;
     use ibe::kem::cgw_kv::CGWKV;
 
+    #[test]
+    fn test_reveal() {
+        let setup = TestSetup::new(&mut rand::thread_rng());
+
+        for p in &setup.policies {
+            let mut disclosed = p.con.clone();
+            disclosed.reverse();
+
+            let hidden = p.to_hidden();
+            assert_eq!(&hidden.reveal(&disclosed).unwrap(), p);
+            assert_eq!(
+                hidden.derive_disclosed(&disclosed).unwrap(),
+                p.derive().unwrap()
+            );
+        }
+
+        let phone = "pbdf.sidn-pbdf.mobilenumber.mobilenumber";
+        let hidden = Policy {
+            timestamp: 1566722350,
+            con: vec![Attribute::new(phone, Some("0612345678"))],
+        }
+        .to_hidden();
+
+        // The value does not match the hint.
+        let other = [Attribute::new(phone, Some("0687654321"))];
+        assert!(matches!(
+            hidden.reveal(&other),
+            Err(Error::DisclosureMismatch(t)) if t == phone
+        ));
+
+        // An attribute is missing or superfluous.
+        assert!(matches!(
+            hidden.reveal(&[]),
+            Err(Error::DisclosureMismatch(t)) if t == phone
+        ));
+        let email = "pbdf.sidn-pbdf.email.email";
+        let extra = [
+            Attribute::new(phone, Some("0612345678")),
+            Attribute::new(email, Some("bob@example.com")),
+        ];
+        assert!(matches!(
+            hidden.reveal(&extra),
+            Err(Error::DisclosureMismatch(t)) if t == email
+        ));
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        use subtle::ConstantTimeEq;
+
+        let setup = TestSetup::new(&mut rand::thread_rng());
+
+        for p1 in &setup.policies {
+            for p2 in &setup.policies {
+                assert_eq!(bool::from(p1.ct_eq(p2)), p1 == p2);
+                assert_eq!(
+                    bool::from(p1.to_hidden().ct_eq(&p2.to_hidden())),
+                    p1.to_hidden() == p2.to_hidden()
+                );
+            }
+        }
+
+        // A missing value differs from an empty one.
+        let empty = Attribute::new("pbdf.sidn-pbdf.email.email", Some(""));
+        let missing = Attribute::new("pbdf.sidn-pbdf.email.email", None);
+        assert!(!bool::from(empty.ct_eq(&missing)));
+    }
+
+    #[test]
+    fn test_age() {
+        let policy = Policy {
+            timestamp: 1_000,
+            con: Vec::new(),
+        };
+
+        assert_eq!(policy.age(1_060), Duration::from_secs(60));
+        assert_eq!(policy.age(900), Duration::ZERO);
+
+        assert!(!policy.is_expired(1_060, Duration::from_secs(60)));
+        assert!(policy.is_expired(1_061, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_timestamp_sanity() {
+        let policy = Policy {
+            timestamp: 1_000,
+            con: Vec::new(),
+        };
+        let window = Duration::from_secs(60);
+
+        assert!(policy.timestamp_sanity(1_000, window).is_ok());
+        assert!(policy.timestamp_sanity(940, window).is_ok());
+        assert!(policy.timestamp_sanity(1_060, window).is_ok());
+        assert!(policy.to_hidden().timestamp_sanity(1_060, window).is_ok());
+
+        // Too far in the future, or in the past.
+        assert!(matches!(
+            policy.timestamp_sanity(939, window),
+            Err(Error::ConstraintViolation)
+        ));
+        assert!(matches!(
+            policy.to_hidden().timestamp_sanity(1_061, window),
+            Err(Error::ConstraintViolation)
+        ));
+    }
+
     #[test]
     fn test_ordering() {
         let mut rng = rand::thread_rng();
@@ -204,6 +618,7 @@ mod tests {
         let attr = Attribute {
             atype: "pbdf.sidn-pbdf.mobilenumber.mobilenumber".to_string(),
             value: Some("123456789".to_string()),
+            ..Default::default()
         };
         let hinted = attr.hintify_value();
         assert_eq!(hinted.value, Some("12345****".to_string()));
@@ -211,6 +626,7 @@ mod tests {
         let attr_short = Attribute {
             atype: "pbdf.sidn-pbdf.mobilenumber.mobilenumber".to_string(),
             value: Some("123".to_string()),
+            ..Default::default()
         };
         let hinted_short = attr_short.hintify_value();
         assert_eq!(hinted_short.value, Some("***".to_string()));
@@ -218,11 +634,176 @@ mod tests {
         let attr_not_whitelisted = Attribute {
             atype: "pbdf.sidn-pbdf.mobilenumber.test".to_string(),
             value: Some("123456789".to_string()),
+            ..Default::default()
         };
         let hinted_empty = attr_not_whitelisted.hintify_value();
         assert_eq!(hinted_empty.value, Some("".to_string()));
     }
 
+    #[test]
+    fn test_hint_matches() {
+        let mobile = |v: &str| Attribute::new("pbdf.sidn-pbdf.mobilenumber.mobilenumber", Some(v));
+        let full = mobile("0612345678");
+        let hidden = full.hintify_value();
+
+        assert!(hidden.matches(&full));
+        assert!(hidden.matches(&mobile("0612349999")));
+        assert!(!hidden.matches(&mobile("0698765678")));
+        assert!(!hidden.matches(&mobile("061234567")));
+        assert!(!hidden.matches(&Attribute::new(
+            "irma-demo.sidn-pbdf.mobilenumber.mobilenumber",
+            Some("0612345678")
+        )));
+
+        // Fully masked values always match.
+        let email = Attribute::new("pbdf.sidn-pbdf.email.email", Some("alice@example.com"));
+        assert!(email.hintify_value().matches(&email));
+        assert!(email.hintify_value().matches(&Attribute::new(
+            "pbdf.sidn-pbdf.email.email",
+            Some("bob@example.com")
+        )));
+        assert!(mobile("123").hintify_value().matches(&mobile("456")));
+
+        // Attributes without a value only match each other.
+        let any = Attribute::new("pbdf.sidn-pbdf.email.email", None);
+        assert!(any.hintify_value().matches(&any));
+        assert!(!any.hintify_value().matches(&email));
+        assert!(!email.hintify_value().matches(&any));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let derive = |value| {
+            Policy {
+                timestamp: 1566722350,
+                con: vec![Attribute::new_typed(
+                    "pbdf.gemeente.personalData.age",
+                    value,
+                )],
+            }
+            .derive()
+            .unwrap()
+        };
+
+        let int = derive(AttributeValue::Integer(5));
+        assert_eq!(int, derive(AttributeValue::Integer(5)));
+        assert_eq!(int, derive(AttributeValue::Text("5".to_string())));
+        assert_ne!(int, derive(AttributeValue::Text("05".to_string())));
+        assert_ne!(int, derive(AttributeValue::Integer(-5)));
+        assert_ne!(int, derive(AttributeValue::Null));
+
+        // A wildcard, a null and a concrete value all derive different identities.
+        let wildcard = derive(AttributeValue::Wildcard);
+        assert_ne!(wildcard, derive(AttributeValue::Null));
+        assert_ne!(wildcard, int);
+        assert_ne!(wildcard, derive(AttributeValue::Text("".to_string())));
+        assert_ne!(derive(AttributeValue::Null), int);
+
+        assert_eq!(
+            AttributeValue::Integer(-42).canonical().as_deref(),
+            Some("-42")
+        );
+        assert_eq!(
+            Attribute::new_typed("a.b.c.d", AttributeValue::Null),
+            Attribute::new("a.b.c.d", None)
+        );
+
+        for value in [
+            AttributeValue::Text("5".to_string()),
+            AttributeValue::Null,
+            AttributeValue::Wildcard,
+        ] {
+            let attr = Attribute::new_typed("a.b.c.d", value.clone());
+            assert_eq!(attr.typed_value(), value);
+            attr.validate().unwrap();
+        }
+
+        // The wildcard is encoded in the value, which keeps the encoding of attributes intact.
+        let null = Attribute::new("a.b.c.d", None);
+        assert_eq!(
+            serde_json::to_string(&null).unwrap(),
+            r#"{"t":"a.b.c.d","v":null}"#
+        );
+        let wildcard = Attribute::new_typed("a.b.c.d", AttributeValue::Wildcard);
+        assert!(wildcard.is_wildcard() && !null.is_wildcard());
+        let json = serde_json::to_string(&wildcard).unwrap();
+        assert_eq!(json, r#"{"t":"a.b.c.d","v":"\u0000*"}"#);
+        assert_eq!(serde_json::from_str::<Attribute>(&json).unwrap(), wildcard);
+        let bytes = bincode::serialize(&wildcard).unwrap();
+        assert_eq!(bincode::deserialize::<Attribute>(&bytes).unwrap(), wildcard);
+
+        // Hiding the value keeps the wildcard.
+        assert!(wildcard.hintify_value().is_wildcard());
+        assert!(wildcard.hintify_value().matches(&wildcard));
+        assert!(!wildcard.hintify_value().matches(&null));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        for p in setup.policies.iter() {
+            p.validate().unwrap();
+            p.to_hidden().validate().unwrap();
+        }
+
+        let policy = |atype: &str, value: &str| Policy {
+            timestamp: 0,
+            con: vec![Attribute::new(atype, Some(value))],
+        };
+
+        let max_type = "t".repeat(MAX_ATTRIBUTE_TYPE_LEN);
+        let max_value = "v".repeat(MAX_ATTRIBUTE_VALUE_LEN);
+        policy(&max_type, &max_value).validate().unwrap();
+
+        // A multi-megabyte value is rejected.
+        assert!(matches!(
+            policy("pbdf.sidn-pbdf.email.email", &"v".repeat(4 * 1024 * 1024)).validate(),
+            Err(Error::ConstraintViolation)
+        ));
+        assert!(matches!(
+            policy(&(max_type + "t"), "v").validate(),
+            Err(Error::ConstraintViolation)
+        ));
+        assert!(matches!(
+            policy("t", &(max_value + "v")).validate(),
+            Err(Error::ConstraintViolation)
+        ));
+
+        let too_many = Policy {
+            timestamp: 0,
+            con: vec![Attribute::new("t", None); MAX_CON_LEN + 1],
+        };
+        assert!(matches!(
+            too_many.validate(),
+            Err(Error::ConstraintViolation)
+        ));
+        assert!(matches!(
+            too_many.to_hidden().validate(),
+            Err(Error::ConstraintViolation)
+        ));
+    }
+
+    #[test]
+    fn test_derive_version() {
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        // Version 0 is the derivation pinned by the regression test below.
+        assert_eq!(DERIVE_VERSION, 0);
+
+        for p in setup.policies.iter() {
+            let current = p.derive().unwrap();
+            assert_eq!(current, p.derive_versioned(0).unwrap());
+            assert_ne!(current, p.derive_versioned(1).unwrap());
+            assert_ne!(
+                p.derive_versioned(1).unwrap(),
+                p.derive_versioned(2).unwrap()
+            );
+        }
+    }
+
     #[test]
     fn test_regression() {
         let mut rng = rand::thread_rng();
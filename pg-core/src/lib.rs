@@ -180,6 +180,9 @@ pub mod identity;
 #[cfg(any(feature = "rust", feature = "web"))]
 pub mod client;
 
+#[cfg(feature = "stream")]
+pub mod armor;
+
 #[doc(hidden)]
 pub use ibe::{kem, Compress};
 
@@ -189,6 +192,9 @@ pub use ibs;
 #[doc(hidden)]
 pub use consts::*;
 
+#[cfg(all(feature = "rust", feature = "stream"))]
+pub use client::rust::stream::{overhead_for, segment_of_offset, total_segments, SegmentLayout};
+
 #[cfg(feature = "test")]
 pub mod test;
 
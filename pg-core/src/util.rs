@@ -1,6 +1,8 @@
 use crate::consts::*;
 use crate::error::Error;
 use alloc::string::String;
+use ibe::kem::SharedSecret;
+use zeroize::{Zeroize, Zeroizing};
 
 pub(crate) fn open_ct<T>(x: subtle::CtOption<T>) -> Option<T> {
     if bool::from(x.is_some()) {
@@ -21,11 +23,8 @@ pub(crate) fn preamble_checked(preamble: &[u8]) -> Result<(u16, usize), Error> {
             .map_err(|_e| Error::FormatViolation(String::from("version")))?,
     );
 
-    if version != VERSION_V3 {
-        return Err(Error::IncorrectVersion {
-            expected: VERSION_V3,
-            found: version,
-        });
+    if version != VERSION_V3 && version != VERSION_V4 {
+        return Err(Error::UnsupportedVersion(version));
     }
 
     let header_len = u32::from_be_bytes(
@@ -35,8 +34,39 @@ pub(crate) fn preamble_checked(preamble: &[u8]) -> Result<(u16, usize), Error> {
     ) as usize;
 
     if header_len > MAX_HEADER_SIZE {
-        return Err(Error::ConstraintViolation);
+        return Err(Error::BadLengthPrefix);
     }
 
     Ok((version, header_len))
 }
+
+/// Returns the DEM key, i.e., the first [`KEY_SIZE`] bytes of a KEM shared secret.
+///
+/// Errors if the shared secret is too short, which depends on the KEM.
+pub(crate) fn dem_key_checked(ss: &[u8]) -> Result<&[u8], Error> {
+    ss.get(..KEY_SIZE).ok_or(Error::ConstraintViolation)
+}
+
+/// Copies the DEM key out of a KEM shared secret, which is wiped afterwards.
+///
+/// The returned key is wiped when dropped.
+pub(crate) fn dem_key(mut ss: SharedSecret) -> Result<Zeroizing<[u8; KEY_SIZE]>, Error> {
+    let key = dem_key_checked(&ss.0).map(|k| {
+        let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+        key.copy_from_slice(k);
+        key
+    });
+    ss.0.zeroize();
+
+    key
+}
+
+pub(crate) fn header_sig_len_checked(b: [u8; SIG_SIZE_SIZE]) -> Result<usize, Error> {
+    let header_sig_len = u32::from_be_bytes(b) as usize;
+
+    if header_sig_len > MAX_HEADER_SIG_SIZE {
+        return Err(Error::BadLengthPrefix);
+    }
+
+    Ok(header_sig_len)
+}
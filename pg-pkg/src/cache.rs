@@ -0,0 +1,214 @@
+//! Cache of issued user secret keys.
+//!
+//! Deriving a USK is pure computation, but it is repeated for every client that requests a key
+//! for the same policy and timestamp. The cache stores the USKs by the derived identity, which
+//! covers both. Since USKs are secret, the cache is bounded and the stored keys are zeroized when
+//! they are evicted or when the cache is dropped.
+
+use pg_core::kem::IBKEM;
+use pg_core::Compress;
+
+use crate::util::open_ct;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroize;
+
+/// A compressed USK, zeroized on drop.
+struct CachedUsk<B: AsMut<[u8]>>(B);
+
+impl<B: AsMut<[u8]>> Drop for CachedUsk<B> {
+    fn drop(&mut self) {
+        self.0.as_mut().zeroize();
+    }
+}
+
+struct Entry<B: AsMut<[u8]>> {
+    usk: CachedUsk<B>,
+    /// The tick at which this entry was last used.
+    used: u64,
+}
+
+struct Inner<K: IBKEM>
+where
+    <K::Usk as Compress>::Output: AsMut<[u8]>,
+{
+    /// The master secret key that the cached USKs were extracted with.
+    sk: Option<Arc<K::Sk>>,
+    entries: HashMap<[u8; 64], Entry<<K::Usk as Compress>::Output>>,
+    tick: u64,
+    hits: u64,
+}
+
+/// A least recently used (LRU) cache of USKs, shared between all workers.
+///
+/// The cache is emptied when the master secret key changes, e.g., when it is reloaded.
+pub struct UskCache<K: IBKEM>
+where
+    <K::Usk as Compress>::Output: AsMut<[u8]>,
+{
+    capacity: usize,
+    inner: Mutex<Inner<K>>,
+}
+
+impl<K: IBKEM> UskCache<K>
+where
+    <K::Usk as Compress>::Output: AsMut<[u8]>,
+{
+    /// Creates a cache that holds at most `capacity` USKs.
+    pub fn new(capacity: usize) -> Self {
+        UskCache {
+            capacity,
+            inner: Mutex::new(Inner {
+                sk: None,
+                entries: HashMap::new(),
+                tick: 0,
+                hits: 0,
+            }),
+        }
+    }
+
+    /// Returns the cached USK for an identity, or extracts and caches it otherwise.
+    ///
+    /// The USK is extracted without holding the lock, so concurrent requests for the same
+    /// identity might both extract it.
+    pub fn get_or_extract(
+        &self,
+        sk: &Arc<K::Sk>,
+        id: [u8; 64],
+        extract: impl FnOnce() -> K::Usk,
+    ) -> K::Usk {
+        if let Some(usk) = self.get(sk, &id) {
+            return usk;
+        }
+
+        let usk = extract();
+        self.insert(sk, id, &usk);
+
+        usk
+    }
+
+    /// The number of requests that were served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.lock().hits
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<K>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, sk: &Arc<K::Sk>, id: &[u8; 64]) -> Option<K::Usk> {
+        let mut inner = self.lock();
+
+        // Keys extracted with another master secret key are no longer valid.
+        if !inner
+            .sk
+            .as_ref()
+            .map_or(false, |cached| Arc::ptr_eq(cached, sk))
+        {
+            inner.entries.clear();
+            inner.sk = Some(sk.clone());
+            return None;
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(id)?;
+        entry.used = tick;
+
+        let usk = open_ct(K::Usk::from_bytes(&entry.usk.0));
+        if usk.is_some() {
+            inner.hits += 1;
+        }
+
+        usk
+    }
+
+    fn insert(&self, sk: &Arc<K::Sk>, id: [u8; 64], usk: &K::Usk) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.lock();
+
+        // The master secret key was changed while this USK was extracted.
+        if !inner
+            .sk
+            .as_ref()
+            .map_or(false, |cached| Arc::ptr_eq(cached, sk))
+        {
+            return;
+        }
+
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&id) {
+            let lru = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(id, _)| *id);
+
+            if let Some(lru) = lru {
+                inner.entries.remove(&lru);
+            }
+        }
+
+        inner.tick += 1;
+        let used = inner.tick;
+        inner.entries.insert(
+            id,
+            Entry {
+                usk: CachedUsk(usk.to_bytes()),
+                used,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pg_core::identity::DeriveFromState;
+    use pg_core::kem::cgw_kv::CGWKV;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_usk_cache() {
+        let mut rng = thread_rng();
+        let (_, sk) = CGWKV::setup(&mut rng);
+        let sk = Arc::new(sk);
+
+        let cache = UskCache::<CGWKV>::new(2);
+        let id = |i: u8| [i; 64];
+        let extract = |i: u8| {
+            CGWKV::extract_usk(
+                None,
+                &sk,
+                &CGWKV::derive_from_state(&id(i)),
+                &mut thread_rng(),
+            )
+        };
+
+        // USK extraction is randomized, so equal keys must come from the cache.
+        let usk1 = cache.get_or_extract(&sk, id(1), || extract(1)).to_bytes();
+        let usk = cache.get_or_extract(&sk, id(1), || extract(1));
+        assert_eq!(usk.to_bytes(), usk1);
+        assert_eq!(cache.hits(), 1);
+
+        // The least recently used key is evicted.
+        cache.get_or_extract(&sk, id(2), || extract(2));
+        cache.get_or_extract(&sk, id(1), || extract(1));
+        cache.get_or_extract(&sk, id(3), || extract(3));
+        assert_eq!(cache.hits(), 2);
+        let usk = cache.get_or_extract(&sk, id(1), || extract(1));
+        assert_eq!(usk.to_bytes(), usk1);
+        assert_eq!(cache.hits(), 3);
+        cache.get_or_extract(&sk, id(2), || extract(2));
+        assert_eq!(cache.hits(), 3);
+
+        // A new master secret key empties the cache.
+        let (_, sk2) = CGWKV::setup(&mut rng);
+        let sk2 = Arc::new(sk2);
+        cache.get_or_extract(&sk2, id(1), || extract(1));
+        assert_eq!(cache.hits(), 3);
+    }
+}
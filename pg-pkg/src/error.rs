@@ -1,4 +1,7 @@
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
 use serde_json::json;
 use std::fmt::{Display, Formatter};
 
@@ -12,11 +15,16 @@ pub enum Error {
     ChronologyError,
     SessionNotFound,
     UpstreamError,
+    IdentityProviderUnavailable,
     VersionError,
     DecodingError,
     NoAttributesError,
     NoTimestampError,
     ValidityError,
+    TimestampSkew { max_skew: u64 },
+    PolicyError,
+    MissingAttribute(String),
+    RateLimited { retry_after: u64 },
     Unexpected,
 }
 
@@ -56,7 +64,12 @@ impl ResponseError for Error {
             "message": format!("{}", self),
         });
 
-        HttpResponse::build(self.status_code()).json(body)
+        let mut res = HttpResponse::build(self.status_code());
+        if let Error::RateLimited { retry_after } = self {
+            res.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+
+        res.json(body)
     }
 
     fn status_code(&self) -> StatusCode {
@@ -65,11 +78,16 @@ impl ResponseError for Error {
             Error::ChronologyError | Error::VersionError => StatusCode::BAD_REQUEST,
             Error::SessionNotFound => StatusCode::NOT_FOUND,
             Error::UpstreamError => StatusCode::SERVICE_UNAVAILABLE,
+            Error::IdentityProviderUnavailable => StatusCode::SERVICE_UNAVAILABLE,
             Error::DecodingError => StatusCode::UNAUTHORIZED,
             Error::NoAttributesError => StatusCode::FORBIDDEN,
             Error::ValidityError => StatusCode::BAD_REQUEST,
+            Error::TimestampSkew { .. } => StatusCode::BAD_REQUEST,
+            Error::PolicyError => StatusCode::BAD_REQUEST,
+            Error::MissingAttribute(_) => StatusCode::BAD_REQUEST,
             Error::Unexpected => StatusCode::INTERNAL_SERVER_ERROR,
             Error::NoTimestampError => StatusCode::BAD_REQUEST,
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 }
@@ -81,12 +99,24 @@ impl Display for Error {
             Error::ChronologyError => write!(f, "chronology error"),
             Error::SessionNotFound => write!(f, "session not found"),
             Error::UpstreamError => write!(f, "upstream error"),
+            Error::IdentityProviderUnavailable => write!(f, "identity provider unavailable"),
             Error::VersionError => write!(f, "no such protocol version"),
             Error::DecodingError => write!(f, "JWT decoding error"),
             Error::ValidityError => write!(f, "validity exceeds maximum validity"),
             Error::NoTimestampError => write!(f, "no (valid) timestamp given"),
+            Error::TimestampSkew { max_skew } => write!(
+                f,
+                "timestamp lies more than {max_skew} seconds in the future, check the clock"
+            ),
             Error::NoAttributesError => write!(f, "no valid attributes were disclosed"),
+            Error::PolicyError => write!(f, "policy exceeds the size limits"),
+            Error::MissingAttribute(atype) => {
+                write!(f, "policy lacks the required attribute type {atype}")
+            }
             Error::Prometheus(e) => write!(f, "prometheus error: {e}"),
+            Error::RateLimited { retry_after } => {
+                write!(f, "too many requests, retry after {retry_after} seconds")
+            }
             Error::Unexpected => write!(f, "unexpected"),
         }
     }
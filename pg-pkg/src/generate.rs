@@ -1,3 +1,4 @@
+use pg_core::api::parameters_identity;
 use pg_core::ibs::gg;
 use pg_core::kem::cgw_kv::CGWKV;
 use pg_core::{kem::IBKEM, Compress};
@@ -27,6 +28,10 @@ pub fn exec(gen_opts: &GenOpts) -> Result<(), PKGError> {
         ibe_public_path,
         ibs_secret_path,
         ibs_public_path,
+        trust_anchor,
+        anchor_secret_path,
+        anchor_public_path,
+        parameters_key_path,
     } = gen_opts;
 
     match scheme.as_ref() {
@@ -44,6 +49,20 @@ pub fn exec(gen_opts: &GenOpts) -> Result<(), PKGError> {
             write_owned(ibs_secret_path, ibs_sk_bytes)?;
 
             println!("The following keys were written:\n{ibe_public_path}\n{ibe_secret_path}\n{ibs_public_path}\n{ibs_secret_path}");
+
+            if *trust_anchor {
+                let (anchor_pk, anchor_sk) = gg::setup(&mut rng);
+                let params_key = gg::keygen(&anchor_sk, &parameters_identity(), &mut rng);
+
+                write_owned(anchor_public_path, bincode::serialize(&anchor_pk).unwrap())?;
+                write_owned(anchor_secret_path, bincode::serialize(&anchor_sk).unwrap())?;
+                write_owned(
+                    parameters_key_path,
+                    bincode::serialize(&params_key).unwrap(),
+                )?;
+
+                println!("Trust anchor key pair and parameters key generated, store the anchor secret key offline:\n{anchor_public_path}\n{anchor_secret_path}\n{parameters_key_path}");
+            }
         }
         x => {
             return Err(PKGError::InvalidVersion(x.into()));
@@ -1,3 +1,5 @@
+use super::{irma_request_error, IRMA_TIMEOUT};
+use actix_rt::time::timeout;
 use actix_web::http::header::ContentType;
 use actix_web::HttpRequest;
 use actix_web::{web::Data, HttpResponse};
@@ -6,14 +8,18 @@ pub async fn jwt(irma: Data<String>, req: HttpRequest) -> Result<HttpResponse, c
     let token = req.match_info().query("token");
     let irma_url = irma.get_ref().clone();
 
-    let jwt = reqwest::get(&format!("{irma_url}/session/{token}/result-jwt"))
-        .await
-        .or(Err(crate::Error::Unexpected))?
-        .error_for_status()
-        .or(Err(crate::Error::UpstreamError))?
-        .text()
-        .await
-        .or(Err(crate::Error::Unexpected))?;
+    let jwt = timeout(
+        IRMA_TIMEOUT,
+        reqwest::get(&format!("{irma_url}/session/{token}/result-jwt")),
+    )
+    .await
+    .or(Err(crate::Error::IdentityProviderUnavailable))?
+    .map_err(irma_request_error)?
+    .error_for_status()
+    .or(Err(crate::Error::UpstreamError))?
+    .text()
+    .await
+    .or(Err(crate::Error::Unexpected))?;
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::plaintext())
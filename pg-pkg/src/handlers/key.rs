@@ -3,20 +3,30 @@ use actix_web::{HttpMessage, HttpRequest};
 
 use pg_core::api::KeyResponse;
 use pg_core::artifacts::UserSecretKey;
-use pg_core::identity::Policy;
+use pg_core::identity::{DeriveFromState, Policy};
 use pg_core::kem::IBKEM;
+use pg_core::Compress;
 
+use crate::cache::UskCache;
 use crate::middleware::irma::IrmaAuthResult;
-use crate::util::current_time_u64;
+use crate::server::KeyConfig;
+use crate::util::{Clock, Snapshot};
 
 use serde::Serialize;
 
-pub async fn key<K>(req: HttpRequest, msk: Data<K::Sk>) -> Result<HttpResponse, crate::Error>
+pub async fn key<K>(
+    req: HttpRequest,
+    msk: Data<dyn Snapshot<K::Sk>>,
+    config: Data<KeyConfig>,
+    cache: Option<Data<UskCache<K>>>,
+    clock: Data<dyn Clock>,
+) -> Result<HttpResponse, crate::Error>
 where
-    K: IBKEM + 'static,
+    K: IBKEM + DeriveFromState + 'static,
     UserSecretKey<K>: Serialize,
+    <K::Usk as Compress>::Output: AsMut<[u8]>,
 {
-    let sk = msk.get_ref();
+    let sk = msk.load();
     let mut rng = rand::thread_rng();
 
     let timestamp = req
@@ -36,10 +46,12 @@ where
         .cloned()
         .ok_or(crate::Error::Unexpected)?;
 
-    // It is not allowed to ask for USKs with a timestamp in the future.
-    let now = current_time_u64()?;
-    if timestamp > now {
-        return Err(crate::Error::ChronologyError);
+    // It is not allowed to ask for USKs with a timestamp in the future, apart from clock skew.
+    let now = clock.now()?;
+    if timestamp > now.saturating_add(config.max_skew) {
+        return Err(crate::Error::TimestampSkew {
+            max_skew: config.max_skew,
+        });
     }
 
     // It is not allowed to ask for USKs with a timestamp beyond the expiry date.
@@ -52,16 +64,23 @@ where
     req.extensions_mut().clear();
 
     let policy = Policy { timestamp, con };
+    policy.validate().map_err(|_e| crate::Error::PolicyError)?;
+    config.check_size(&policy.con)?;
+    config.check_required(&policy.con)?;
 
-    let id = policy
-        .derive_kem::<K>()
-        .map_err(|_e| crate::Error::Unexpected)?;
+    let state = policy.derive().map_err(|_e| crate::Error::Unexpected)?;
+    let extract = || K::extract_usk(None, &sk, &K::derive_from_state(&state), &mut rng);
 
-    let usk = K::extract_usk(None, sk, &id, &mut rng);
+    let usk = match cache {
+        Some(cache) => cache.get_or_extract(&sk, state, extract),
+        None => extract(),
+    };
 
     Ok(HttpResponse::Ok().json(KeyResponse {
         status,
         proof_status,
         key: Some(UserSecretKey::<K>(usk)),
+        issued_at: Some(now),
+        valid_until: Some(timestamp.saturating_add(config.usk_ttl)),
     }))
 }
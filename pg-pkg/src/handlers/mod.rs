@@ -4,6 +4,7 @@ mod metrics;
 mod parameters;
 mod signing_key;
 mod start;
+mod validate;
 
 pub use jwt::*;
 pub use key::*;
@@ -11,3 +12,4 @@ pub use metrics::*;
 pub use parameters::*;
 pub use signing_key::*;
 pub use start::*;
+pub use validate::*;
@@ -8,21 +8,34 @@ use actix_web::{
 };
 
 use crate::server::ParametersData;
+use crate::util::Snapshot;
 
-pub async fn parameters(req: HttpRequest, pd: Data<ParametersData>) -> impl Responder
+pub async fn parameters(
+    req: HttpRequest,
+    pd: Data<dyn Snapshot<ParametersData>>,
+) -> impl Responder
 where
 {
+    let pd = pd.load();
     let if_none_match = IfNoneMatch::parse(&req);
     let if_modified_since = IfModifiedSince::parse(&req);
 
-    match (if_none_match, if_modified_since) {
-        (Ok(IfNoneMatch::Items(ref tags)), ..) if tags.iter().any(|t| t.strong_eq(&pd.etag)) => {
-            HttpResponse::NotModified().finish()
+    // If-Modified-Since must be ignored when If-None-Match is present (RFC 9110, 13.1.3).
+    let not_modified = match (if_none_match, if_modified_since) {
+        (Ok(IfNoneMatch::Any), _) => true,
+        (Ok(IfNoneMatch::Items(ref tags)), _) if !tags.is_empty() => {
+            tags.iter().any(|t| t.weak_eq(&pd.etag))
         }
-        (.., Ok(IfModifiedSince(ref since))) if &pd.last_modified <= since => {
-            HttpResponse::NotModified().finish()
-        }
-        _ => HttpResponse::Ok()
+        (_, Ok(IfModifiedSince(ref since))) => &pd.last_modified <= since,
+        _ => false,
+    };
+
+    if not_modified {
+        HttpResponse::NotModified()
+            .insert_header(ETag(pd.etag.clone()))
+            .finish()
+    } else {
+        HttpResponse::Ok()
             .insert_header(CacheControl(vec![
                 CacheDirective::Public,
                 CacheDirective::NoCache,
@@ -30,6 +43,6 @@ where
             .insert_header(ETag(pd.etag.clone()))
             .insert_header(LastModified(pd.last_modified))
             .content_type(ContentType::json())
-            .body(pd.pp.clone()),
+            .body(pd.pp.clone())
     }
 }
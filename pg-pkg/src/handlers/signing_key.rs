@@ -5,17 +5,20 @@ use irma::SessionStatus;
 use pg_core::api::{SigningKeyRequest, SigningKeyResponse};
 use pg_core::artifacts::{SigningKey, SigningKeyExt};
 use pg_core::ibs::gg::{keygen, SecretKey};
-use pg_core::identity::Policy;
+use pg_core::identity::{validate_con, Attribute, Policy};
 
 use crate::middleware::irma::IrmaAuthResult;
-use crate::util::current_time_u64;
+use crate::server::KeyConfig;
+use crate::util::{Clock, Snapshot};
 
 pub async fn signing_key(
     req: HttpRequest,
-    msk: Data<SecretKey>,
+    msk: Data<dyn Snapshot<SecretKey>>,
     body: Json<SigningKeyRequest>,
+    config: Data<KeyConfig>,
+    clock: Data<dyn Clock>,
 ) -> Result<HttpResponse, crate::Error> {
-    let sk = msk.get_ref();
+    let sk = msk.load();
     let mut rng = rand::thread_rng();
 
     let IrmaAuthResult {
@@ -32,9 +35,25 @@ pub async fn signing_key(
     req.extensions_mut().clear();
 
     // The PKG gets to decide the timestamp in the policy.
-    let iat = current_time_u64()?;
+    let iat = clock.now()?;
     let body = body.into_inner();
 
+    std::iter::once(&body.pub_sign_id)
+        .chain(body.priv_sign_id.iter())
+        .chain(body.extra_sign_ids.iter().flatten())
+        .try_for_each(|con| validate_con(con))
+        .map_err(|_e| crate::Error::PolicyError)?;
+
+    // Every issued signing policy must be within the configured size and contain the required
+    // attribute types.
+    std::iter::once(&body.pub_sign_id)
+        .chain(body.priv_sign_id.iter())
+        .chain(body.extra_sign_ids.iter().flatten())
+        .try_for_each(|con| {
+            config.check_size(con)?;
+            config.check_required(con)
+        })?;
+
     match status {
         SessionStatus::Done => (),
         _ => {
@@ -43,50 +62,45 @@ pub async fn signing_key(
                 proof_status,
                 pub_sign_key: None,
                 priv_sign_key: None,
+                extra_sign_keys: None,
             }))
         }
     }
 
-    if !body.pub_sign_id.iter().all(|attr| con.contains(attr)) {
-        return Err(crate::Error::Unexpected);
-    }
-
-    let policy = Policy {
-        timestamp: iat,
-        con: body.pub_sign_id.clone(),
-    };
-    let id = policy.derive_ibs().map_err(|_e| crate::Error::Unexpected)?;
-    let key = keygen(sk, &id, &mut rng);
-
-    let pub_sign_key = SigningKeyExt {
-        key: SigningKey(key),
-        policy,
-    };
-
-    let priv_sign_key = body.priv_sign_id.map(|priv_sign_id| {
-        if !priv_sign_id.iter().all(|attr| con.contains(attr)) {
+    // Issue a signing key for a conjunction, provided it was disclosed.
+    let mut issue = |sign_id: Vec<Attribute>| {
+        if !sign_id.iter().all(|attr| con.contains(attr)) {
             return Err(crate::Error::Unexpected);
         }
+
         let policy = Policy {
             timestamp: iat,
-            con: priv_sign_id,
+            con: sign_id,
         };
 
         let id = policy.derive_ibs().map_err(|_e| crate::Error::Unexpected)?;
-        let key = keygen(sk, &id, &mut rng);
+        let key = keygen(&sk, &id, &mut rng);
 
         Ok(SigningKeyExt {
             key: SigningKey(key),
             policy,
         })
-    });
+    };
+
+    let pub_sign_key = issue(body.pub_sign_id)?;
+
+    let priv_sign_key = body.priv_sign_id.map(&mut issue).transpose()?;
 
-    let priv_sign_key = priv_sign_key.map_or(Ok(None), |r| r.map(Some))?;
+    let extra_sign_keys = body
+        .extra_sign_ids
+        .map(|ids| ids.into_iter().map(&mut issue).collect::<Result<Vec<_>, _>>())
+        .transpose()?;
 
     Ok(HttpResponse::Ok().json(SigningKeyResponse {
         status,
         proof_status,
         pub_sign_key: Some(pub_sign_key),
         priv_sign_key,
+        extra_sign_keys,
     }))
 }
@@ -1,7 +1,10 @@
 use crate::Error;
+use actix_rt::time::timeout;
 use actix_web::{web::Data, web::Json, HttpResponse};
 use irma::*;
 use pg_core::api::IrmaAuthRequest;
+use pg_core::identity::validate_con;
+use std::time::Duration;
 
 /// Maximum allowed valitidy (in seconds) of a JWT (1 day).
 const MAX_VALIDITY: u64 = 60 * 60 * 24;
@@ -9,12 +12,36 @@ const MAX_VALIDITY: u64 = 60 * 60 * 24;
 /// Default validity if no validity is specified (5 min).
 const DEFAULT_VALIDITY: u64 = 60 * 5;
 
+/// How long to wait for the IRMA server, such that a hung server does not hold connections open.
+pub(crate) const IRMA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maps a failed request to the IRMA server.
+///
+/// If the server cannot be reached (in time), the identity provider is reported unavailable.
+pub(crate) fn irma_request_error(e: reqwest::Error) -> Error {
+    if e.is_connect() || e.is_timeout() {
+        Error::IdentityProviderUnavailable
+    } else {
+        Error::Unexpected
+    }
+}
+
+/// Returns the requested validity, or the default validity if none was requested.
+pub(crate) fn validity_checked(validity: Option<u64>) -> Result<u64, Error> {
+    match validity {
+        Some(validity) if validity > MAX_VALIDITY => Err(Error::ValidityError),
+        Some(validity) => Ok(validity),
+        None => Ok(DEFAULT_VALIDITY),
+    }
+}
+
 pub async fn start(
     url: Data<String>,
     value: Json<IrmaAuthRequest>,
 ) -> Result<HttpResponse, crate::Error> {
     let irma_url = url.get_ref().clone();
     let kr = value.into_inner();
+    validate_con(&kr.con).map_err(|_e| Error::PolicyError)?;
 
     let dr = DisclosureRequestBuilder::new()
         .add_discons(
@@ -31,11 +58,7 @@ pub async fn start(
         )
         .build();
 
-    let validity = match kr.validity {
-        Some(validity) if validity > MAX_VALIDITY => Err(Error::ValidityError),
-        Some(validity) => Ok(validity),
-        None => Ok(DEFAULT_VALIDITY),
-    }?;
+    let validity = validity_checked(kr.validity)?;
 
     let er = ExtendedIrmaRequest {
         timeout: None,
@@ -48,10 +71,13 @@ pub async fn start(
         .map_err(|_e| Error::Unexpected)?
         .build();
 
-    let session = client
-        .request_extended(&er)
+    let session = timeout(IRMA_TIMEOUT, client.request_extended(&er))
         .await
-        .or(Err(crate::Error::Unexpected))?;
+        .or(Err(Error::IdentityProviderUnavailable))?
+        .map_err(|e| match e {
+            irma::Error::NetworkError(e) => irma_request_error(e),
+            _ => Error::Unexpected,
+        })?;
 
     Ok(HttpResponse::Ok().json(session))
 }
@@ -0,0 +1,69 @@
+use actix_web::{web::Data, web::Json, HttpResponse};
+
+use pg_core::api::{IrmaAuthRequest, PolicyValidationResponse};
+use pg_core::identity::{validate_con, Attribute, Policy};
+
+use super::start::validity_checked;
+use crate::server::KeyConfig;
+
+/// Whether an attribute type is a well-formed IRMA attribute type identifier, i.e., of the form
+/// `scheme.issuer.credential.attribute`.
+fn is_recognized(atype: &str) -> bool {
+    let parts: Vec<&str> = atype.split('.').collect();
+
+    parts.len() == 4
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+/// Whether a key would be issued for the conjunction, once it has been disclosed.
+///
+/// An attribute type can only be disclosed once, and the identity must be derivable, as in the
+/// `key` and `signing_key` handlers, including the required attribute types.
+fn acceptable(con: &[Attribute], config: &KeyConfig) -> bool {
+    let mut types: Vec<&str> = con.iter().map(|attr| attr.atype.as_str()).collect();
+    types.sort_unstable();
+    types.dedup();
+
+    !con.is_empty()
+        && types.len() == con.len()
+        && types.iter().all(|atype| is_recognized(atype))
+        && config.check_required(con).is_ok()
+        && Policy {
+            timestamp: 0,
+            con: con.to_vec(),
+        }
+        .derive()
+        .is_ok()
+}
+
+/// Checks whether the PKG would accept a conjunction, without starting a session or issuing a
+/// key.
+pub async fn validate(
+    value: Json<IrmaAuthRequest>,
+    config: Data<KeyConfig>,
+) -> Result<HttpResponse, crate::Error> {
+    let kr = value.into_inner();
+
+    // Also reject a validity or policy that starting the session would reject, or that exceeds
+    // the configured size.
+    validity_checked(kr.validity)?;
+    validate_con(&kr.con).map_err(|_e| crate::Error::PolicyError)?;
+    config.check_size(&kr.con)?;
+
+    let (recognized, unrecognized): (Vec<String>, Vec<String>) = kr
+        .con
+        .iter()
+        .map(|attr| attr.atype.clone())
+        .partition(|atype| is_recognized(atype));
+
+    Ok(HttpResponse::Ok().json(PolicyValidationResponse {
+        recognized,
+        unrecognized,
+        acceptable: acceptable(&kr.con, &config),
+    }))
+}
@@ -0,0 +1,143 @@
+//! Structured access logging.
+//!
+//! An alternative to the text [`Logger`][actix_web::middleware::Logger], enabled using
+//! `--json-logs`. Every request is logged as a single JSON object with the fields `timestamp`
+//! (milliseconds since the Unix epoch), `path`, `status`, `client`, `response_time_ms` and
+//! `request_id`. Other log records are formatted as JSON objects as well, see
+//! [`json_log_format`], such that the output consists of JSON lines only.
+
+use crate::middleware::request_id::request_id_of;
+use crate::util::client_version;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+};
+use futures::Future;
+use futures_util::future::FutureExt;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The log target of the access log records.
+pub(crate) const ACCESS_LOG_TARGET: &str = "pg_pkg::access";
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Builds the access log record of a single request.
+fn access_record(
+    path: &str,
+    status: u16,
+    client: &str,
+    request_id: &str,
+    elapsed_ms: f64,
+) -> Value {
+    json!({
+        "timestamp": unix_millis(),
+        "path": path,
+        "status": status,
+        "client": client,
+        "response_time_ms": elapsed_ms,
+        "request_id": request_id,
+    })
+}
+
+/// Logs every request as a JSON object, if enabled.
+pub(crate) fn access_log<
+    B: MessageBody,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+>(
+    enabled: bool,
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>> {
+    let start = Instant::now();
+    let fields = enabled.then(|| (client_version(&req), request_id_of(&req)));
+
+    srv.call(req).map(move |res| {
+        if let Some((client, request_id)) = fields {
+            let (path, status) = match &res {
+                Ok(resp) => (resp.request().match_pattern(), resp.status()),
+                Err(e) => (None, e.as_response_error().status_code()),
+            };
+
+            log::info!(
+                target: ACCESS_LOG_TARGET,
+                "{}",
+                access_record(
+                    path.as_deref().unwrap_or("-"),
+                    status.as_u16(),
+                    &client,
+                    &request_id,
+                    start.elapsed().as_secs_f64() * 1000.0,
+                )
+            );
+        }
+
+        res
+    })
+}
+
+/// Formats log records as JSON lines.
+///
+/// Access log records are already JSON objects and are written as is.
+pub(crate) fn json_log_format(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record<'_>,
+) -> std::io::Result<()> {
+    if record.target() == ACCESS_LOG_TARGET {
+        return writeln!(buf, "{}", record.args());
+    }
+
+    let line = json!({
+        "timestamp": unix_millis(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+
+    writeln!(buf, "{line}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[test]
+    fn test_access_record() {
+        let record = access_record(
+            "/v2/irma/key/{timestamp}",
+            200,
+            "unknown",
+            "session-1234",
+            1.5,
+        );
+
+        assert_eq!(record["path"], "/v2/irma/key/{timestamp}");
+        assert_eq!(record["status"], 200);
+        assert_eq!(record["client"], "unknown");
+        assert_eq!(record["request_id"], "session-1234");
+        assert_eq!(record["response_time_ms"], 1.5);
+        assert!(record["timestamp"].as_u64().unwrap() > 0);
+    }
+
+    #[actix_web::test]
+    async fn test_access_log_passthrough() {
+        for enabled in [false, true] {
+            let app = test::init_service(
+                App::new()
+                    .wrap_fn(move |req, srv| access_log(enabled, req, srv))
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+
+            let req = test::TestRequest::get().uri("/").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+    }
+}
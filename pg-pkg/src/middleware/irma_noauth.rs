@@ -62,6 +62,9 @@ where
                     if let Some(priv_id) = skr.priv_sign_id {
                         con.extend(priv_id);
                     }
+                    if let Some(extra_ids) = skr.extra_sign_ids {
+                        con.extend(extra_ids.into_iter().flatten());
+                    }
 
                     Policy { timestamp: 0, con }
                 }
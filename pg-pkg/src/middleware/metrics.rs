@@ -1,6 +1,7 @@
 use crate::server::POSTGUARD_CLIENTS;
 use crate::util::*;
 use actix_http::header::HeaderValue;
+use actix_http::StatusCode;
 use actix_web::{
     body::MessageBody,
     dev::{Service, ServiceRequest, ServiceResponse},
@@ -8,6 +9,17 @@ use actix_web::{
 use futures::Future;
 use futures_util::future::FutureExt;
 
+/// The class of a status code, which keeps the cardinality of the label bounded.
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
 pub(crate) fn collect_metrics<
     B: MessageBody,
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
@@ -41,7 +53,7 @@ pub(crate) fn collect_metrics<
 
         if let Some([a, b, c, d, e]) = values {
             POSTGUARD_CLIENTS
-                .with_label_values(&[&a, &b, &c, &d, &e, status.as_str()])
+                .with_label_values(&[&a, &b, &c, &d, &e, status.as_str(), status_class(status)])
                 .inc();
         }
 
@@ -56,7 +68,6 @@ mod tests {
     use super::*;
     use crate::server::tests::default_setup;
     use actix_http::header::HeaderName;
-    use actix_http::StatusCode;
     use actix_web::test;
     use irma::SessionStatus;
     use pg_core::api::{KeyResponse, Parameters};
@@ -133,10 +144,18 @@ mod tests {
         let expected = "\
         # HELP postguard_clients Contains information about PostGuard clients connecting with the PKG.\n\
         # TYPE postguard_clients counter\n\
-        postguard_clients{client=\"pg4ol\",client_version=\"0.0.1\",host=\"Outlook\",host_version=\"1234.5678.90\",path=\"/v2/key/{timestamp}\",status=\"200\"} 1\n\
-        postguard_clients{client=\"pg4ol\",client_version=\"0.0.1\",host=\"Outlook\",host_version=\"1234.5678.90\",path=\"/v2/parameters\",status=\"200\"} 2\n\
-        postguard_clients{client=\"pg4tb\",client_version=\"0.0.2\",host=\"Thunderbird\",host_version=\"1234.5678.90\",path=\"/v2/parameters\",status=\"200\"} 1\n";
+        postguard_clients{client=\"pg4ol\",client_version=\"0.0.1\",host=\"Outlook\",host_version=\"1234.5678.90\",path=\"/v2/key/{timestamp}\",status=\"200\",status_class=\"2xx\"} 1\n\
+        postguard_clients{client=\"pg4ol\",client_version=\"0.0.1\",host=\"Outlook\",host_version=\"1234.5678.90\",path=\"/v2/parameters\",status=\"200\",status_class=\"2xx\"} 2\n\
+        postguard_clients{client=\"pg4tb\",client_version=\"0.0.2\",host=\"Thunderbird\",host_version=\"1234.5678.90\",path=\"/v2/parameters\",status=\"200\",status_class=\"2xx\"} 1\n";
 
         assert_eq!(actix_web::web::Bytes::from(expected), body);
     }
+
+    #[test]
+    fn test_status_class() {
+        assert_eq!(status_class(StatusCode::OK), "2xx");
+        assert_eq!(status_class(StatusCode::NOT_MODIFIED), "3xx");
+        assert_eq!(status_class(StatusCode::UNAUTHORIZED), "4xx");
+        assert_eq!(status_class(StatusCode::INTERNAL_SERVER_ERROR), "5xx");
+    }
 }
@@ -1,5 +1,10 @@
 //! PostGuard PKG middleware module.
 //!
+//! # Access logging
+//!
+//! The access logging middleware logs requests as JSON lines, as an alternative to the text
+//! logger.
+//!
 //! # Authentication
 //!
 //! Authentication middleware can be used to wrap a (key) service. The middleware has only one
@@ -10,9 +15,21 @@
 //! # Metrics
 //!
 //! The metrics middleware collects Prometheus metrics.
+//!
+//! # Rate limiting
+//!
+//! The rate limiting middleware limits the amount of requests per client to the session and key
+//! endpoints.
+//!
+//! # Request identifiers
+//!
+//! The request identifier middleware assigns every request an identifier, to correlate log lines.
 
+pub mod access_log;
 pub mod irma;
 pub mod metrics;
+pub mod ratelimit;
+pub mod request_id;
 
 #[cfg(test)]
 pub mod irma_noauth;
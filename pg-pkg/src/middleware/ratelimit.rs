@@ -0,0 +1,293 @@
+//! Rate limiting middleware.
+//!
+//! Applies a token bucket per client IP address. Each bucket holds at most `limit` tokens and
+//! refills at a rate of `limit` tokens per minute. Every request takes one token. If no token is
+//! available, the request is rejected with `429 Too Many Requests` and a `Retry-After` header.
+//!
+//! The client is identified by the peer address of the connection. Only if the peer is a trusted
+//! proxy, the client address it forwarded (`Forwarded` or `X-Forwarded-For`) is used instead,
+//! since any client can set these headers.
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+
+use futures::future::{ready, Ready};
+use futures::FutureExt;
+use futures_util::future::LocalBoxFuture;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// The maximum amount of buckets, after which the least recently used buckets are evicted.
+const MAX_BUCKETS: usize = 10_000;
+
+/// The amount of buckets that is evicted at once, which amortizes the cost of finding them.
+const EVICT_BUCKETS: usize = MAX_BUCKETS / 10;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Rate limiting middleware.
+///
+/// The buckets are shared between all clones of this middleware, so it should be constructed
+/// once and cloned into every worker.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// The maximum amount of tokens per bucket.
+    capacity: f64,
+    /// The amount of tokens added per second, which is never zero.
+    refill: f64,
+    /// The proxies whose forwarded client addresses are trusted.
+    trusted_proxies: Arc<[IpAddr]>,
+    /// The buckets, per client.
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimit {
+    /// Allow `limit` requests per minute per client, with bursts of at most `limit` requests.
+    pub fn per_minute(limit: NonZeroU32) -> Self {
+        Self {
+            capacity: f64::from(limit.get()),
+            refill: f64::from(limit.get()) / 60.0,
+            trusted_proxies: Arc::from([]),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Identify clients by the address forwarded by any of `proxies`, if they are the peer.
+    ///
+    /// The proxies must overwrite the forwarding headers set by clients, rather than append to
+    /// them.
+    pub fn with_trusted_proxies(mut self, proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = Arc::from(proxies);
+        self
+    }
+
+    /// The address of the client that sent the request.
+    fn client(&self, req: &ServiceRequest) -> IpAddr {
+        // Without a peer address, e.g., in tests, all requests share a bucket.
+        let peer = req
+            .peer_addr()
+            .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+
+        if !self.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+
+        let info = req.connection_info();
+        let forwarded = info.realip_remote_addr().unwrap_or_default();
+
+        forwarded
+            .parse::<SocketAddr>()
+            .map(|addr| addr.ip())
+            .or_else(|_| forwarded.parse::<IpAddr>())
+            .unwrap_or(peer)
+    }
+
+    /// Takes a token from the bucket of this client.
+    ///
+    /// Returns the amount of seconds after which a token is available if the bucket is empty.
+    fn acquire(&self, client: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&client) {
+            evict_least_recently_used(&mut buckets);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.capacity,
+            last: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill).min(self.capacity);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.refill).ceil() as u64)
+        }
+    }
+}
+
+/// Evicts the [`EVICT_BUCKETS`] buckets that were used least recently.
+fn evict_least_recently_used(buckets: &mut HashMap<IpAddr, Bucket>) {
+    let mut last: Vec<Instant> = buckets.values().map(|b| b.last).collect();
+    let (_, cutoff, _) = last.select_nth_unstable(EVICT_BUCKETS.min(last.len()) - 1);
+    let cutoff = *cutoff;
+
+    buckets.retain(|_, b| b.last > cutoff);
+}
+
+#[doc(hidden)]
+pub struct RateLimitService<S> {
+    service: Rc<S>,
+    limit: RateLimit,
+}
+
+impl<S> Service<ServiceRequest> for RateLimitService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let acquired = self.limit.acquire(self.limit.client(&req));
+
+        async move {
+            if let Err(retry_after) = acquired {
+                return Ok(req.error_response(crate::Error::RateLimited { retry_after }));
+            }
+
+            srv.call(req).await
+        }
+        .boxed_local()
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type Transform = RateLimitService<S>;
+    type InitError = ();
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitService {
+            service: Rc::new(service),
+            limit: self.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::StatusCode;
+    use actix_web::http::header::RETRY_AFTER;
+    use actix_web::{test, web, App, HttpResponse};
+
+    fn limit(n: u32) -> RateLimit {
+        RateLimit::per_minute(NonZeroU32::new(n).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit() {
+        const LIMIT: u32 = 3;
+
+        let app = test::init_service(
+            App::new().service(
+                web::resource("/v2/irma/start")
+                    .wrap(limit(LIMIT))
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let request = |ip: &str| {
+            test::TestRequest::post()
+                .uri("/v2/irma/start")
+                .peer_addr(format!("{ip}:12345").parse().unwrap())
+                .to_request()
+        };
+
+        for _ in 0..LIMIT {
+            let res = test::call_service(&app, request("10.0.0.1")).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = test::call_service(&app, request("10.0.0.1")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+
+        // Other clients have their own bucket.
+        let res = test::call_service(&app, request("10.0.0.2")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_forwarded() {
+        let app = test::init_service(
+            App::new()
+                .service(
+                    web::resource("/untrusted")
+                        .wrap(limit(1))
+                        .route(web::post().to(HttpResponse::Ok)),
+                )
+                .service(
+                    web::resource("/trusted")
+                        .wrap(limit(1).with_trusted_proxies(vec!["10.0.0.1".parse().unwrap()]))
+                        .route(web::post().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        let request = |uri: &str, forwarded: &str| {
+            test::TestRequest::post()
+                .uri(uri)
+                .peer_addr("10.0.0.1:12345".parse().unwrap())
+                .insert_header(("X-Forwarded-For", forwarded))
+                .to_request()
+        };
+
+        // Clients cannot escape their bucket by forging the forwarded address.
+        let res = test::call_service(&app, request("/untrusted", "192.0.2.1")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&app, request("/untrusted", "192.0.2.2")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Behind a trusted proxy, every forwarded client has its own bucket.
+        let res = test::call_service(&app, request("/trusted", "192.0.2.1")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&app, request("/trusted", "192.0.2.2")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&app, request("/trusted", "192.0.2.1")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_rate_limit_eviction() {
+        use std::time::Duration;
+
+        let limit = limit(1);
+        let ip = |i: usize| IpAddr::V4(Ipv4Addr::from(i as u32));
+
+        // Client i was last seen i milliseconds after the first one.
+        let start = Instant::now() - Duration::from_millis(MAX_BUCKETS as u64);
+        limit
+            .buckets
+            .lock()
+            .unwrap()
+            .extend((0..MAX_BUCKETS).map(|i| {
+                let last = start + Duration::from_millis(i as u64);
+                (ip(i), Bucket { tokens: 1.0, last })
+            }));
+
+        // A new client evicts the least recently used buckets, the map does not grow.
+        limit.acquire(ip(MAX_BUCKETS)).unwrap();
+        let buckets = limit.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_BUCKETS - EVICT_BUCKETS + 1);
+        assert!(!buckets.contains_key(&ip(EVICT_BUCKETS - 1)));
+        assert!(buckets.contains_key(&ip(EVICT_BUCKETS)));
+        assert!(buckets.contains_key(&ip(MAX_BUCKETS)));
+    }
+}
@@ -0,0 +1,129 @@
+//! Request correlation middleware.
+//!
+//! Every request is assigned a request identifier. Clients can supply their own identifier using
+//! the `X-Request-Id` header, e.g., to correlate the requests of one IRMA session. Otherwise, a
+//! random identifier is generated. The identifier is stored in the request extensions as a
+//! [`RequestId`], included in the logs and echoed back in the `X-Request-Id` response header.
+
+use actix_http::header::{HeaderName, HeaderValue};
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
+};
+use futures::Future;
+use futures_util::future::FutureExt;
+use rand::RngCore;
+
+/// The header containing the request identifier.
+pub(crate) const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// The maximum length of a request identifier supplied by a client.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// The request identifier, available in the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Generates a random request identifier.
+    fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        Self(bytes.iter().map(|b| format!("{b:02x}")).collect())
+    }
+
+    /// Accepts a client-supplied request identifier, only if it is safe to log.
+    fn from_client(id: &str) -> Option<Self> {
+        let valid = !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        valid.then(|| Self(id.to_string()))
+    }
+}
+
+/// Retrieves the request identifier for the logger.
+pub(crate) fn request_id_of(req: &ServiceRequest) -> String {
+    req.extensions()
+        .get::<RequestId>()
+        .map_or_else(|| String::from("-"), |id| id.0.clone())
+}
+
+pub(crate) fn request_id<
+    B: MessageBody,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(RequestId::from_client)
+        .unwrap_or_else(RequestId::generate);
+
+    req.extensions_mut().insert(id.clone());
+
+    srv.call(req).map(move |res| {
+        res.map(|mut resp| {
+            if let Ok(value) = HeaderValue::from_str(&id.0) {
+                resp.headers_mut()
+                    .insert(HeaderName::from_static("x-request-id"), value);
+            }
+
+            resp
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+
+    async fn echo(req: HttpRequest) -> HttpResponse {
+        let id = req.extensions().get::<RequestId>().unwrap().0.clone();
+        HttpResponse::Ok().body(id)
+    }
+
+    #[actix_web::test]
+    async fn test_request_id() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(request_id)
+                .route("/", web::get().to(echo)),
+        )
+        .await;
+
+        // A supplied identifier is kept.
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "session-1234"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "session-1234"
+        );
+        assert_eq!(test::read_body(resp).await, "session-1234");
+
+        // Otherwise, one is generated.
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let id = resp.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+        assert_eq!(id.len(), 32);
+        assert_eq!(test::read_body(resp).await, id.as_bytes());
+
+        // Identifiers that are unsafe to log are replaced.
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "a b\tc"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_ne!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "a b\tc");
+    }
+}
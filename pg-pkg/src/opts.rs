@@ -1,4 +1,6 @@
 use clap::{Parser, ValueHint};
+use std::net::IpAddr;
+use std::num::NonZeroU32;
 
 /// Private Key Generator (PKG) for PostGuard, an Identity Based Encryption standard.
 #[derive(Parser, Debug)]
@@ -41,6 +43,23 @@ pub struct GenOpts {
     /// Path to store the IBS public key.
     #[clap(long, default_value = "./pkg_ibs.pub")]
     pub ibs_public_path: String,
+
+    /// Also generate a trust anchor key pair and a key to sign the public parameters with. The
+    /// trust anchor secret key should be stored offline.
+    #[clap(long)]
+    pub trust_anchor: bool,
+
+    /// Path to store the trust anchor secret key.
+    #[clap(long, default_value = "./pkg_anchor.sec")]
+    pub anchor_secret_path: String,
+
+    /// Path to store the trust anchor public key, which is distributed to clients.
+    #[clap(long, default_value = "./pkg_anchor.pub")]
+    pub anchor_public_path: String,
+
+    /// Path to store the key used to sign the public parameters.
+    #[clap(long, default_value = "./pkg_params.key")]
+    pub parameters_key_path: String,
 }
 
 /// Run the IRMASeal PKG HTTP service.
@@ -74,4 +93,65 @@ pub struct ServerOpts {
     /// Path to store the IBS public key.
     #[clap(long, default_value = "./pkg_ibs.pub", value_hint = ValueHint::FilePath)]
     pub ibs_public_path: String,
+
+    /// Path to the key used to sign the public parameters, such that clients can verify them
+    /// against an offline trust anchor. The parameters are not signed if not set.
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub parameters_key_path: Option<String>,
+
+    /// Validity (in seconds) of issued user secret keys, counted from the policy timestamp.
+    #[clap(long, default_value = "86400")]
+    pub usk_ttl: u64,
+
+    /// Maximum (in seconds) that the timestamp of a requested user secret key may lie in the
+    /// future, to tolerate clients with a skewed clock.
+    #[clap(long, default_value = "0")]
+    pub max_skew: u64,
+
+    /// Attribute type that every policy must contain before a key is issued, e.g.,
+    /// `pbdf.sidn-pbdf.email.email`. Can be given multiple times. No attribute type is required
+    /// if not set.
+    #[clap(long = "required-attribute")]
+    pub required_attributes: Vec<String>,
+
+    /// Attribute type that clients can use in policies, as listed by `GET /v2/attributes`, e.g.,
+    /// `pbdf.gemeente.personalData.fullname`. Can be given multiple times. The required attribute
+    /// types are always listed.
+    #[clap(long = "attribute-type")]
+    pub attribute_types: Vec<String>,
+
+    /// Maximum number of attributes in a policy, to bound the cost of deriving its identity.
+    /// Policies with more attributes are rejected. Larger values than 64 have no effect.
+    #[clap(long, default_value = "64")]
+    pub max_con: usize,
+
+    /// Maximum number of user secret keys to cache, such that repeated requests for the same
+    /// policy and timestamp are served without extracting the key again. Keys are not cached if
+    /// set to 0.
+    #[clap(long, default_value = "0")]
+    pub usk_cache_size: usize,
+
+    /// Maximum number of requests per minute per client IP to the session and key endpoints.
+    /// Must be at least 1. Requests are not limited if not set.
+    #[clap(long)]
+    pub rate_limit: Option<NonZeroU32>,
+
+    /// Address of a reverse proxy whose forwarded client IP is used for rate limiting. Can be
+    /// given multiple times. Clients are identified by the peer address of the connection
+    /// otherwise.
+    #[clap(long = "trusted-proxy")]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Origin allowed to make cross-origin requests, e.g., `https://example.com`. Can be given
+    /// multiple times. Any origin is allowed if not set.
+    #[clap(long = "allowed-origin", value_hint = ValueHint::Url)]
+    pub allowed_origins: Vec<String>,
+
+    /// Allow cross-origin requests to include credentials. Requires allowed origins to be set.
+    #[clap(long)]
+    pub cors_credentials: bool,
+
+    /// Log requests and other messages as JSON lines instead of text, for log aggregators.
+    #[clap(long)]
+    pub json_logs: bool,
 }
@@ -1,26 +1,36 @@
 use actix_cors::Cors;
 use actix_http::header::HttpDate;
+use actix_rt::signal::unix::{signal, SignalKind};
 use actix_web::http::header::EntityTag;
 use actix_web::{
     http::header,
-    middleware::Logger,
+    middleware::{Condition, Logger},
     web,
     web::{resource, scope, Data},
     App, HttpServer,
 };
 
+use crate::cache::UskCache;
+use crate::middleware::access_log::{access_log, json_log_format};
 use crate::middleware::irma::{IrmaAuth, IrmaAuthType};
 use crate::middleware::metrics::collect_metrics;
+use crate::middleware::ratelimit::RateLimit;
+use crate::middleware::request_id::{request_id, request_id_of, REQUEST_ID_HEADER};
 use crate::opts::*;
 use crate::util::*;
 use crate::{handlers, PKGError};
 
-use pg_core::api::Parameters;
+use pg_core::api::{AttributeTypesResponse, Parameters};
 use pg_core::artifacts::*;
-use pg_core::kem::cgw_kv::CGWKV;
+use pg_core::ibs::gg;
+use pg_core::identity::{Attribute, MAX_CON_LEN};
+use pg_core::kem::{cgw_kv::CGWKV, IBKEM};
 
+use arc_swap::ArcSwap;
 use lazy_static::lazy_static;
 use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::num::NonZeroU32;
+use std::sync::Arc;
 
 lazy_static! {
     pub(crate) static ref POSTGUARD_CLIENTS: IntCounterVec = register_int_counter_vec!(
@@ -32,7 +42,8 @@ lazy_static! {
             "host_version",
             "client",
             "client_version",
-            "status"
+            "status",
+            "status_class"
         ]
     )
     .expect("could not initialize metrics");
@@ -51,6 +62,257 @@ pub struct ParametersData {
     pub etag: EntityTag,
 }
 
+/// Default validity (in seconds) of issued user secret keys (1 day).
+pub const DEFAULT_USK_TTL: u64 = 60 * 60 * 24;
+
+/// Default tolerance (in seconds) for key timestamps in the future.
+pub const DEFAULT_MAX_SKEW: u64 = 0;
+
+/// Settings of the key issuing handlers.
+#[derive(Debug, Clone)]
+pub struct KeyConfig {
+    /// Validity (in seconds) of issued user secret keys, counted from the policy timestamp.
+    pub usk_ttl: u64,
+
+    /// Maximum (in seconds) that a requested key timestamp may lie in the future.
+    pub max_skew: u64,
+
+    /// Attribute types that every policy must contain before a key is issued.
+    pub required_attributes: Vec<String>,
+
+    /// Maximum number of attributes in a policy, at most [`MAX_CON_LEN`].
+    pub max_con: usize,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            usk_ttl: DEFAULT_USK_TTL,
+            max_skew: DEFAULT_MAX_SKEW,
+            required_attributes: Vec::new(),
+            max_con: MAX_CON_LEN,
+        }
+    }
+}
+
+impl KeyConfig {
+    /// Checks that a conjunction does not exceed the configured number of attributes.
+    ///
+    /// This is checked before deriving an identity, to bound the cost of the derivation.
+    pub fn check_size(&self, con: &[Attribute]) -> Result<(), crate::Error> {
+        if con.len() > self.max_con {
+            return Err(crate::Error::PolicyError);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a conjunction contains every required attribute type.
+    pub fn check_required(&self, con: &[Attribute]) -> Result<(), crate::Error> {
+        match self
+            .required_attributes
+            .iter()
+            .find(|atype| !con.iter().any(|attr| &attr.atype == *atype))
+        {
+            Some(atype) => Err(crate::Error::MissingAttribute(atype.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Precomputes the response of `GET /v2/attributes`.
+///
+/// Lists the configured attribute types and the required ones, sorted and without duplicates.
+pub(crate) fn attribute_types_data(
+    mut attribute_types: Vec<String>,
+    required_attributes: &[String],
+) -> Result<ParametersData, PKGError> {
+    attribute_types.extend_from_slice(required_attributes);
+    attribute_types.sort_unstable();
+    attribute_types.dedup();
+
+    ParametersData::new(&AttributeTypesResponse { attribute_types }, None)
+}
+
+/// Locations of the master key pairs on disk.
+#[derive(Debug, Clone)]
+pub struct KeyPaths {
+    /// Path to the IBE public key.
+    pub ibe_public_path: String,
+
+    /// Path to the IBE secret key.
+    pub ibe_secret_path: String,
+
+    /// Path to the IBS public key.
+    pub ibs_public_path: String,
+
+    /// Path to the IBS secret key.
+    pub ibs_secret_path: String,
+
+    /// Path to the key used to sign the public parameters, if any.
+    pub parameters_key_path: Option<String>,
+}
+
+/// Master key material, which is always replaced as a whole.
+pub struct MasterKeySet {
+    /// Precomputed IBE public parameters.
+    pub ibe_pd: Arc<ParametersData>,
+
+    /// Precomputed IBS public parameters.
+    pub ibs_pd: Arc<ParametersData>,
+
+    /// The IBE master secret key.
+    pub ibe_sk: Arc<<CGWKV as IBKEM>::Sk>,
+
+    /// The IBS master secret key.
+    pub ibs_sk: Arc<gg::SecretKey>,
+}
+
+/// Master key material shared by all workers.
+///
+/// The [`MasterKeySet`] is kept behind a single [`ArcSwap`], such that the master key pairs can be
+/// replaced while the server is running (see [`MasterKeys::reload`]). Requests never observe a mix
+/// of old and new keys, and requests that are in flight keep using the keys they loaded.
+#[derive(Clone)]
+pub struct MasterKeys(Arc<ArcSwap<MasterKeySet>>);
+
+// Selects one item from the current master key set.
+struct MasterKeyItem<T> {
+    keys: Arc<ArcSwap<MasterKeySet>>,
+    select: fn(&MasterKeySet) -> &Arc<T>,
+}
+
+impl<T> Snapshot<T> for MasterKeyItem<T> {
+    fn load(&self) -> Arc<T> {
+        (self.select)(&ArcSwap::load(&self.keys)).clone()
+    }
+}
+
+// Reads both master key pairs from disk and precomputes the public parameters.
+fn read_master_keys(paths: &KeyPaths) -> Result<MasterKeySet, PKGError> {
+    let (ibe_pk, ibe_sk) = cgwkv_read_key_pair(&paths.ibe_public_path, &paths.ibe_secret_path)?;
+    let (ibs_pk, ibs_sk) = gg_read_key_pair(&paths.ibs_public_path, &paths.ibs_secret_path)?;
+    let parameters_key = paths
+        .parameters_key_path
+        .as_ref()
+        .map(gg_read_signing_key)
+        .transpose()?;
+
+    let mut ibe_params = Parameters::<PublicKey<CGWKV>> {
+        format_version: 0x00,
+        public_key: PublicKey(ibe_pk),
+        signature: None,
+    };
+
+    let mut ibs_params = Parameters::<VerifyingKey> {
+        format_version: 0x00,
+        public_key: VerifyingKey(ibs_pk),
+        signature: None,
+    };
+
+    // Sign the parameters, such that clients can verify them against the trust anchor.
+    if let Some(key) = parameters_key {
+        let mut rng = rand::thread_rng();
+        let key = SigningKey(key);
+        ibe_params
+            .sign(&key, &mut rng)
+            .map_err(|e| PKGError::Setup(format!("could not sign parameters: {e}")))?;
+        ibs_params
+            .sign(&key, &mut rng)
+            .map_err(|e| PKGError::Setup(format!("could not sign parameters: {e}")))?;
+    }
+
+    let ibe_pd = ParametersData::new(&ibe_params, Some(&paths.ibe_public_path))?;
+    let ibs_pd = ParametersData::new(&ibs_params, Some(&paths.ibs_public_path))?;
+
+    Ok(MasterKeySet {
+        ibe_pd: Arc::new(ibe_pd),
+        ibs_pd: Arc::new(ibs_pd),
+        ibe_sk: Arc::new(ibe_sk),
+        ibs_sk: Arc::new(ibs_sk),
+    })
+}
+
+impl MasterKeys {
+    /// Read the master key pairs from disk.
+    pub fn read(paths: &KeyPaths) -> Result<Self, PKGError> {
+        let keys = read_master_keys(paths)?;
+
+        Ok(MasterKeys(Arc::new(ArcSwap::from_pointee(keys))))
+    }
+
+    /// Re-read the master key pairs from disk and swap them in.
+    ///
+    /// All key files are read and validated before the keys are swapped, at once. If any of them
+    /// fails to load, the current keys are kept.
+    pub fn reload(&self, paths: &KeyPaths) -> Result<(), PKGError> {
+        let keys = read_master_keys(paths)?;
+        self.0.store(Arc::new(keys));
+
+        Ok(())
+    }
+
+    /// The IBE public parameters, to be passed to the handlers.
+    pub fn ibe_pd(&self) -> Data<dyn Snapshot<ParametersData>> {
+        self.item(|keys| &keys.ibe_pd)
+    }
+
+    /// The IBS public parameters, to be passed to the handlers.
+    pub fn ibs_pd(&self) -> Data<dyn Snapshot<ParametersData>> {
+        self.item(|keys| &keys.ibs_pd)
+    }
+
+    /// The IBE master secret key, to be passed to the handlers.
+    pub fn ibe_sk(&self) -> Data<dyn Snapshot<<CGWKV as IBKEM>::Sk>> {
+        self.item(|keys| &keys.ibe_sk)
+    }
+
+    /// The IBS master secret key, to be passed to the handlers.
+    pub fn ibs_sk(&self) -> Data<dyn Snapshot<gg::SecretKey>> {
+        self.item(|keys| &keys.ibs_sk)
+    }
+
+    fn item<T: Send + Sync + 'static>(
+        &self,
+        select: fn(&MasterKeySet) -> &Arc<T>,
+    ) -> Data<dyn Snapshot<T>> {
+        snapshot_data(MasterKeyItem {
+            keys: self.0.clone(),
+            select,
+        })
+    }
+}
+
+/// Builds the CORS middleware.
+///
+/// Any origin is allowed if no origins are given. Credentials can only be allowed for specific
+/// origins.
+pub(crate) fn cors(allowed_origins: &[String], credentials: bool) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_header(header::CONTENT_TYPE)
+        .allowed_header(header::AUTHORIZATION)
+        .allowed_header(header::ETAG)
+        .allowed_header(PG_CLIENT_HEADER)
+        .allowed_header(REQUEST_ID_HEADER)
+        .expose_headers(vec![REQUEST_ID_HEADER])
+        .max_age(86400);
+
+    if allowed_origins.is_empty() {
+        return cors.allow_any_origin();
+    }
+
+    let cors = allowed_origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin));
+
+    if credentials {
+        cors.supports_credentials()
+    } else {
+        cors
+    }
+}
+
 #[actix_rt::main]
 pub async fn exec(server_opts: ServerOpts) -> Result<(), PKGError> {
     let ServerOpts {
@@ -61,67 +323,123 @@ pub async fn exec(server_opts: ServerOpts) -> Result<(), PKGError> {
         ibe_public_path,
         ibs_secret_path,
         ibs_public_path,
+        parameters_key_path,
+        usk_ttl,
+        max_skew,
+        required_attributes,
+        attribute_types,
+        max_con,
+        usk_cache_size,
+        rate_limit,
+        trusted_proxies,
+        allowed_origins,
+        cors_credentials,
+        json_logs,
     } = server_opts;
 
-    let (ibe_pk, ibe_sk) = cgwkv_read_key_pair(&ibe_public_path, &ibe_secret_path)?;
-    let (ibs_pk, ibs_sk) = gg_read_key_pair(&ibs_public_path, &ibs_secret_path)?;
+    if cors_credentials && allowed_origins.is_empty() {
+        return Err(PKGError::Setup(
+            "CORS credentials require at least one allowed origin".to_string(),
+        ));
+    }
+
+    let key_config = KeyConfig {
+        usk_ttl,
+        max_skew,
+        required_attributes,
+        max_con,
+    };
 
-    let ibe_pd = ParametersData::new(
-        &Parameters::<PublicKey<CGWKV>> {
-            format_version: 0x00,
-            public_key: PublicKey(ibe_pk),
-        },
-        Some(&ibe_public_path),
-    )?;
+    // The attribute types never change while the server is running.
+    let attributes = snapshot_data(Arc::new(attribute_types_data(
+        attribute_types,
+        &key_config.required_attributes,
+    )?));
 
-    let ibs_pd = ParametersData::new(
-        &Parameters::<VerifyingKey> {
-            format_version: 0x00,
-            public_key: VerifyingKey(ibs_pk),
-        },
-        Some(&ibs_public_path),
-    )?;
+    // The USK cache is shared between all workers.
+    let usk_cache = Data::new(UskCache::<CGWKV>::new(usk_cache_size));
 
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // All handlers read the time from the system clock.
+    let clock = clock_data(SystemClock);
+
+    // The rate limiter is shared between all workers.
+    let rate_limiter = RateLimit::per_minute(rate_limit.unwrap_or(NonZeroU32::MAX))
+        .with_trusted_proxies(trusted_proxies);
+
+    let paths = KeyPaths {
+        ibe_public_path,
+        ibe_secret_path,
+        ibs_public_path,
+        ibs_secret_path,
+        parameters_key_path,
+    };
+
+    let keys = MasterKeys::read(&paths)?;
+
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"));
+    if json_logs {
+        logger.format(json_log_format);
+    }
+    logger.init();
+
+    // Reload the master key pairs on SIGHUP.
+    let mut hangup = signal(SignalKind::hangup())?;
+    let reload_keys = keys.clone();
+    actix_rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match reload_keys.reload(&paths) {
+                Ok(()) => log::info!("master key pairs reloaded"),
+                Err(e) => log::error!("could not reload master key pairs: {e:?}"),
+            }
+        }
+    });
 
     HttpServer::new(move || {
         App::new()
-            .wrap(
+            .wrap_fn(move |req, srv| access_log(json_logs, req, srv))
+            .wrap(Condition::new(
+                !json_logs,
                 Logger::new(
-                    "request=%{PATH}xi, status=%s, client=%{CLIENT_ID}xi, response_time=%D ms",
+                    "request=%{PATH}xi, status=%s, client=%{CLIENT_ID}xi, request_id=%{REQUEST_ID}xi, response_time=%D ms",
                 )
                 .custom_request_replace("CLIENT_ID", client_version)
+                .custom_request_replace("REQUEST_ID", request_id_of)
                 .custom_request_replace("PATH", |req| {
                     req.match_pattern().unwrap_or("-".to_string())
                 }),
-            )
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allowed_methods(vec!["GET", "POST"])
-                    .allowed_header(header::CONTENT_TYPE)
-                    .allowed_header(header::AUTHORIZATION)
-                    .allowed_header(header::ETAG)
-                    .allowed_header(PG_CLIENT_HEADER)
-                    .max_age(86400),
-            )
+            ))
+            .wrap(cors(&allowed_origins, cors_credentials))
+            .wrap_fn(request_id)
             .service(resource("/metrics").route(web::get().to(handlers::metrics)))
             .service(
                 scope("/v2")
                     .wrap_fn(collect_metrics)
                     .app_data(Data::new(web::JsonConfig::default().limit(1024 * 4096)))
+                    .app_data(clock.clone())
                     .service(
                         resource("/parameters")
-                            .app_data(Data::new(ibe_pd.clone()))
+                            .app_data(keys.ibe_pd())
                             .route(web::get().to(handlers::parameters)),
                     )
                     .service(
                         resource("/sign/parameters")
-                            .app_data(Data::new(ibs_pd.clone()))
+                            .app_data(keys.ibs_pd())
+                            .route(web::get().to(handlers::parameters)),
+                    )
+                    .service(
+                        resource("/policy/validate")
+                            .app_data(Data::new(key_config.clone()))
+                            .route(web::get().to(handlers::validate)),
+                    )
+                    .service(
+                        resource("/attributes")
+                            .app_data(attributes.clone())
                             .route(web::get().to(handlers::parameters)),
                     )
                     .service(
                         scope("/{_:(irma|request)}")
+                            .wrap(Condition::new(rate_limit.is_some(), rate_limiter.clone()))
                             .service(
                                 resource("/start")
                                     .app_data(Data::new(irma.clone()))
@@ -134,13 +452,16 @@ pub async fn exec(server_opts: ServerOpts) -> Result<(), PKGError> {
                             )
                             .service(
                                 resource("/key/{timestamp}")
-                                    .app_data(Data::new(ibe_sk))
+                                    .app_data(keys.ibe_sk())
+                                    .app_data(Data::new(key_config.clone()))
+                                    .app_data(usk_cache.clone())
                                     .wrap(IrmaAuth::new(irma.clone(), IrmaAuthType::Jwt))
                                     .route(web::get().to(handlers::key::<CGWKV>)),
                             )
                             .service(
                                 resource("/sign/key")
-                                    .app_data(Data::new(ibs_sk.clone()))
+                                    .app_data(keys.ibs_sk())
+                                    .app_data(Data::new(key_config.clone()))
                                     .wrap(IrmaAuth::new(irma.clone(), IrmaAuthType::Jwt))
                                     .route(web::post().to(handlers::signing_key)),
                             ),
@@ -159,15 +480,18 @@ pub async fn exec(server_opts: ServerOpts) -> Result<(), PKGError> {
 pub(crate) mod tests {
     use super::*;
 
-    use actix_http::Request;
+    use actix_http::{Request, StatusCode};
     use actix_web::dev::{Service, ServiceResponse};
     use actix_web::{test, web, App, Error};
 
     use crate::middleware::irma_noauth::NoAuth;
     use irma::{ProofStatus, SessionStatus};
-    use pg_core::api::{KeyResponse, Parameters, SigningKeyRequest, SigningKeyResponse};
+    use pg_core::api::{
+        IrmaAuthRequest, KeyResponse, Parameters, PolicyValidationResponse, SigningKeyRequest,
+        SigningKeyResponse,
+    };
     use pg_core::ibs::gg;
-    use pg_core::identity::{Attribute, Policy};
+    use pg_core::identity::{Attribute, Policy, MAX_ATTRIBUTE_VALUE_LEN};
     use pg_core::kem::IBKEM;
 
     use rand::thread_rng;
@@ -180,6 +504,15 @@ pub(crate) mod tests {
             .as_secs()
     }
 
+    /// A clock that is stuck at a given time.
+    pub(crate) struct FixedClock(pub(crate) u64);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> Result<u64, crate::Error> {
+            Ok(self.0)
+        }
+    }
+
     pub(crate) async fn default_setup() -> (
         impl Service<Request, Response = ServiceResponse, Error = Error>,
         <CGWKV as IBKEM>::Pk,
@@ -196,6 +529,7 @@ pub(crate) mod tests {
             &Parameters::<PublicKey<CGWKV>> {
                 format_version: 0x00,
                 public_key: PublicKey(ibe_pk),
+                signature: None,
             },
             None,
         )
@@ -205,6 +539,7 @@ pub(crate) mod tests {
             &Parameters::<VerifyingKey> {
                 format_version: 0x00,
                 public_key: VerifyingKey(ibs_pk.clone()),
+                signature: None,
             },
             None,
         )
@@ -217,25 +552,33 @@ pub(crate) mod tests {
                 .service(
                     scope("/v2")
                         .wrap_fn(collect_metrics)
+                        .app_data(clock_data(SystemClock))
                         .service(
                             resource("/parameters")
-                                .app_data(Data::new(pd))
+                                .app_data(snapshot_data(Arc::new(pd)))
                                 .route(web::get().to(handlers::parameters)),
                         )
                         .service(
                             resource("/sign/parameters")
-                                .app_data(Data::new(pds))
+                                .app_data(snapshot_data(Arc::new(pds)))
                                 .route(web::get().to(handlers::parameters)),
                         )
+                        .service(
+                            resource("/policy/validate")
+                                .app_data(Data::new(KeyConfig::default()))
+                                .route(web::get().to(handlers::validate)),
+                        )
                         .service(
                             resource("/key/{timestamp}")
-                                .app_data(Data::new(ibe_sk))
+                                .app_data(snapshot_data(Arc::new(ibe_sk)))
+                                .app_data(Data::new(KeyConfig::default()))
                                 .wrap(NoAuth::Decryption)
                                 .route(web::get().to(handlers::key::<CGWKV>)),
                         )
                         .service(
                             resource("/sign/key")
-                                .app_data(Data::new(ibs_sk.clone()))
+                                .app_data(snapshot_data(Arc::new(ibs_sk.clone())))
+                                .app_data(Data::new(KeyConfig::default()))
                                 .wrap(NoAuth::Signing)
                                 .route(web::post().to(handlers::signing_key)),
                         ),
@@ -282,6 +625,673 @@ pub(crate) mod tests {
         assert_eq!(params.format_version, 0x00);
     }
 
+    #[actix_web::test]
+    async fn test_validate_policy() {
+        let (app, _, _, _, _) = default_setup().await;
+
+        let validate = |body: serde_json::Value| {
+            test::TestRequest::get()
+                .uri("/v2/policy/validate")
+                .set_json(body)
+                .to_request()
+        };
+
+        let req = validate(serde_json::json!({
+            "con": [
+                { "t": "irma-demo.gemeente.personalData.fullname", "v": "Alice" },
+                { "t": "irma-demo.sidn-pbdf.email.email" }
+            ]
+        }));
+        let res: PolicyValidationResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.recognized.len(), 2);
+        assert!(res.unrecognized.is_empty());
+        assert!(res.acceptable);
+
+        // Malformed attribute types are not recognized.
+        let req = validate(serde_json::json!({
+            "con": [
+                { "t": "irma-demo.gemeente.personalData.fullname", "v": "Alice" },
+                { "t": "fullname", "v": "Alice" }
+            ]
+        }));
+        let res: PolicyValidationResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.unrecognized, vec!["fullname".to_string()]);
+        assert!(!res.acceptable);
+
+        // An empty conjunction, or one that asks for the same attribute type twice, is doomed.
+        for con in [
+            serde_json::json!([]),
+            serde_json::json!([
+                { "t": "irma-demo.gemeente.personalData.fullname", "v": "Alice" },
+                { "t": "irma-demo.gemeente.personalData.fullname", "v": "Bob" }
+            ]),
+        ] {
+            let req = validate(serde_json::json!({ "con": con }));
+            let res: PolicyValidationResponse = test::call_and_read_body_json(&app, req).await;
+            assert!(!res.acceptable);
+        }
+
+        // The validity is checked as when starting a session.
+        let req = validate(serde_json::json!({ "con": [], "validity": 60 * 60 * 24 + 1 }));
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // So are the size limits of the policy.
+        let req = validate(serde_json::json!({
+            "con": [{
+                "t": "irma-demo.gemeente.personalData.fullname",
+                "v": "A".repeat(MAX_ATTRIBUTE_VALUE_LEN + 1)
+            }]
+        }));
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_get_usk_signing_oversized() {
+        let (app, _, _, _, _) = default_setup().await;
+
+        let oversized = vec![Attribute::new(
+            "testattribute",
+            Some(&"A".repeat(MAX_ATTRIBUTE_VALUE_LEN + 1)),
+        )];
+
+        for skr in [
+            SigningKeyRequest {
+                pub_sign_id: oversized.clone(),
+                priv_sign_id: None,
+                extra_sign_ids: None,
+            },
+            SigningKeyRequest {
+                pub_sign_id: vec![Attribute::new("testattribute", Some("testvalue"))],
+                priv_sign_id: None,
+                extra_sign_ids: Some(vec![oversized.clone()]),
+            },
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/v2/sign/key")
+                .set_json(skr)
+                .to_request();
+
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_cors_allowed_origins() {
+        let app = test::init_service(
+            App::new()
+                .wrap(cors(&["https://example.com".to_string()], true))
+                .route("/", web::get().to(actix_web::HttpResponse::Ok)),
+        )
+        .await;
+
+        let resp = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://example.com"))
+            .send_request(&app)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://example.com"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+
+        // Requests from other origins are rejected.
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://evil.com"))
+            .to_request();
+        match app.call(req).await {
+            Ok(resp) => assert!(resp.status().is_client_error()),
+            Err(e) => assert!(e.as_response_error().status_code().is_client_error()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_parameters_not_modified() {
+        let (app, _, _, _, _) = default_setup().await;
+
+        for uri in ["/v2/parameters", "/v2/sign/parameters"] {
+            let resp = test::TestRequest::get().uri(uri).send_request(&app).await;
+            let etag = resp.headers().get("etag").unwrap().clone();
+            let last_modified = resp.headers().get("last-modified").unwrap().clone();
+
+            let resp = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("if-none-match", etag.clone()))
+                .send_request(&app)
+                .await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+            let resp = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("if-modified-since", last_modified.clone()))
+                .send_request(&app)
+                .await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+            // A non-matching ETag takes precedence over the modification date.
+            let resp = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("if-none-match", "\"other\""))
+                .insert_header(("if-modified-since", last_modified))
+                .send_request(&app)
+                .await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+    }
+
+    fn write_key_pairs(dir: &std::path::Path) -> (KeyPaths, <CGWKV as IBKEM>::Pk) {
+        use pg_core::Compress;
+
+        let mut rng = thread_rng();
+        let (ibe_pk, ibe_sk) = CGWKV::setup(&mut rng);
+        let (ibs_pk, ibs_sk) = gg::setup(&mut rng);
+
+        let paths = KeyPaths {
+            ibe_public_path: dir.join("pkg_ibe.pub").to_str().unwrap().to_string(),
+            ibe_secret_path: dir.join("pkg_ibe.sec").to_str().unwrap().to_string(),
+            ibs_public_path: dir.join("pkg_ibs.pub").to_str().unwrap().to_string(),
+            ibs_secret_path: dir.join("pkg_ibs.sec").to_str().unwrap().to_string(),
+            parameters_key_path: None,
+        };
+
+        std::fs::write(&paths.ibe_public_path, ibe_pk.to_bytes().as_ref()).unwrap();
+        std::fs::write(&paths.ibe_secret_path, ibe_sk.to_bytes().as_ref()).unwrap();
+        std::fs::write(&paths.ibs_public_path, bincode::serialize(&ibs_pk).unwrap()).unwrap();
+        std::fs::write(&paths.ibs_secret_path, bincode::serialize(&ibs_sk).unwrap()).unwrap();
+
+        (paths, ibe_pk)
+    }
+
+    #[actix_web::test]
+    async fn test_reload_master_keys() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (paths, pk1) = write_key_pairs(dir.path());
+        let keys = MasterKeys::read(&paths).unwrap();
+
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/parameters")
+                    .app_data(keys.ibe_pd())
+                    .route(web::get().to(handlers::parameters)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/v2/parameters").to_request();
+        let params: Parameters<PublicKey<CGWKV>> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(&params.public_key.0, &pk1);
+
+        // Rotate the keys on disk and reload them while the service keeps running.
+        let (paths, pk2) = write_key_pairs(dir.path());
+        keys.reload(&paths).unwrap();
+
+        let req = test::TestRequest::get().uri("/v2/parameters").to_request();
+        let params: Parameters<PublicKey<CGWKV>> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(&params.public_key.0, &pk2);
+        assert_ne!(&pk1, &pk2);
+    }
+
+    #[actix_web::test]
+    async fn test_reload_master_keys_missing() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (paths, pk) = write_key_pairs(dir.path());
+        let keys = MasterKeys::read(&paths).unwrap();
+
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/parameters")
+                    .app_data(keys.ibe_pd())
+                    .route(web::get().to(handlers::parameters)),
+            ),
+        )
+        .await;
+
+        // A key file that is missing during the rotation fails the reload, the old keys remain.
+        let missing = KeyPaths {
+            ibe_secret_path: dir.path().join("missing.sec").to_str().unwrap().to_string(),
+            ..paths
+        };
+        assert!(keys.reload(&missing).is_err());
+
+        let req = test::TestRequest::get().uri("/v2/parameters").to_request();
+        let params: Parameters<PublicKey<CGWKV>> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(&params.public_key.0, &pk);
+    }
+
+    #[actix_web::test]
+    async fn test_sign_parameters_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (paths, _) = write_key_pairs(dir.path());
+        let keys = MasterKeys::read(&paths).unwrap();
+
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/sign/parameters")
+                    .app_data(keys.ibs_pd())
+                    .route(web::get().to(handlers::parameters)),
+            ),
+        )
+        .await;
+
+        let resp = test::TestRequest::get()
+            .uri("/v2/sign/parameters")
+            .send_request(&app)
+            .await;
+        let etag = resp.headers().get("etag").unwrap().clone();
+
+        let resp = test::TestRequest::get()
+            .uri("/v2/sign/parameters")
+            .insert_header(("if-none-match", etag.clone()))
+            .send_request(&app)
+            .await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        // Rotating the IBS master key pair changes the ETag.
+        let (paths, _) = write_key_pairs(dir.path());
+        keys.reload(&paths).unwrap();
+
+        let resp = test::TestRequest::get()
+            .uri("/v2/sign/parameters")
+            .insert_header(("if-none-match", etag.clone()))
+            .send_request(&app)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_ne!(resp.headers().get("etag").unwrap(), &etag);
+    }
+
+    #[actix_web::test]
+    async fn test_signed_parameters() {
+        use pg_core::api::parameters_identity;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut rng = thread_rng();
+        let (anchor_pk, anchor_sk) = gg::setup(&mut rng);
+        let key = gg::keygen(&anchor_sk, &parameters_identity(), &mut rng);
+
+        let (mut paths, _) = write_key_pairs(dir.path());
+        let key_path = dir
+            .path()
+            .join("pkg_params.key")
+            .to_str()
+            .unwrap()
+            .to_string();
+        std::fs::write(&key_path, bincode::serialize(&key).unwrap()).unwrap();
+        paths.parameters_key_path = Some(key_path);
+
+        let keys = MasterKeys::read(&paths).unwrap();
+        let app = test::init_service(
+            App::new()
+                .service(
+                    resource("/v2/parameters")
+                        .app_data(keys.ibe_pd())
+                        .route(web::get().to(handlers::parameters)),
+                )
+                .service(
+                    resource("/v2/sign/parameters")
+                        .app_data(keys.ibs_pd())
+                        .route(web::get().to(handlers::parameters)),
+                ),
+        )
+        .await;
+
+        let anchor = VerifyingKey(anchor_pk);
+
+        let req = test::TestRequest::get().uri("/v2/parameters").to_request();
+        let params: Parameters<PublicKey<CGWKV>> = test::call_and_read_body_json(&app, req).await;
+        params.verify(&anchor).unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/v2/sign/parameters")
+            .to_request();
+        let params: Parameters<VerifyingKey> = test::call_and_read_body_json(&app, req).await;
+        params.verify(&anchor).unwrap();
+
+        // Without a parameters key, the parameters are not signed.
+        paths.parameters_key_path = None;
+        keys.reload(&paths).unwrap();
+
+        let req = test::TestRequest::get().uri("/v2/parameters").to_request();
+        let params: Parameters<PublicKey<CGWKV>> = test::call_and_read_body_json(&app, req).await;
+        assert!(params.signature.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_get_usk_skew() {
+        let (_, _, ibe_sk, _, _) = default_setup().await;
+        let ibe_sk = snapshot_data(Arc::new(ibe_sk));
+
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/key/{timestamp}")
+                    .app_data(ibe_sk.clone())
+                    .app_data(Data::new(KeyConfig {
+                        max_skew: 60,
+                        ..KeyConfig::default()
+                    }))
+                    .app_data(clock_data(SystemClock))
+                    .wrap(NoAuth::Decryption)
+                    .route(web::get().to(handlers::key::<CGWKV>)),
+            ),
+        )
+        .await;
+
+        let request = |ts: u64| {
+            test::TestRequest::get()
+                .uri(&format!("/v2/key/{ts}"))
+                .set_json(Policy {
+                    timestamp: ts,
+                    con: vec![Attribute::new("testattribute", Some("testvalue"))],
+                })
+                .to_request()
+        };
+
+        // Within the tolerance.
+        let resp = test::call_service(&app, request(now() + 30)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Beyond the tolerance.
+        let resp = test::call_service(&app, request(now() + 3600)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Without tolerance, the default, future timestamps are rejected.
+        let (app, _, _, _, _) = default_setup().await;
+        let resp = test::call_service(&app, request(now() + 30)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // With a pinned clock, the tolerance is exact.
+        let t = 1_700_000_000;
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/key/{timestamp}")
+                    .app_data(ibe_sk)
+                    .app_data(Data::new(KeyConfig {
+                        max_skew: 60,
+                        ..KeyConfig::default()
+                    }))
+                    .app_data(clock_data(FixedClock(t)))
+                    .wrap(NoAuth::Decryption)
+                    .route(web::get().to(handlers::key::<CGWKV>)),
+            ),
+        )
+        .await;
+
+        let key_response: KeyResponse<UserSecretKey<CGWKV>> =
+            test::call_and_read_body_json(&app, request(t + 60)).await;
+        assert_eq!(key_response.issued_at, Some(t));
+
+        let resp = test::call_service(&app, request(t + 61)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_get_usk_signing_clock() {
+        let (_, _, _, _, ibs_sk) = default_setup().await;
+
+        let t = 1_700_000_000;
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/sign/key")
+                    .app_data(snapshot_data(Arc::new(ibs_sk)))
+                    .app_data(Data::new(KeyConfig::default()))
+                    .app_data(clock_data(FixedClock(t)))
+                    .wrap(NoAuth::Signing)
+                    .route(web::post().to(handlers::signing_key)),
+            ),
+        )
+        .await;
+
+        let skr = SigningKeyRequest {
+            pub_sign_id: vec![Attribute::new("testattribute", Some("testvalue"))],
+            priv_sign_id: None,
+            extra_sign_ids: None,
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/v2/sign/key")
+            .set_json(skr)
+            .to_request();
+
+        // The PKG decides the timestamp of the signing policy.
+        let key_response: SigningKeyResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(key_response.pub_sign_key.unwrap().policy.timestamp, t);
+    }
+
+    #[actix_web::test]
+    async fn test_attribute_types() {
+        let data = attribute_types_data(
+            vec![
+                "pbdf.gemeente.personalData.fullname".to_string(),
+                "pbdf.sidn-pbdf.email.email".to_string(),
+            ],
+            &["pbdf.sidn-pbdf.email.email".to_string()],
+        )
+        .unwrap();
+
+        let app = test::init_service(
+            App::new().service(
+                resource("/v2/attributes")
+                    .app_data(snapshot_data(Arc::new(data)))
+                    .route(web::get().to(handlers::parameters)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/v2/attributes").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get("etag").unwrap().clone();
+
+        let res: AttributeTypesResponse = test::read_body_json(resp).await;
+        assert_eq!(
+            res.attribute_types,
+            vec![
+                "pbdf.gemeente.personalData.fullname".to_string(),
+                "pbdf.sidn-pbdf.email.email".to_string(),
+            ]
+        );
+
+        // The list can be cached.
+        let req = test::TestRequest::get()
+            .uri("/v2/attributes")
+            .insert_header(("if-none-match", etag))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[actix_web::test]
+    async fn test_required_attributes() {
+        let (_, _, ibe_sk, _, ibs_sk) = default_setup().await;
+
+        let config = Data::new(KeyConfig {
+            required_attributes: vec!["pbdf.sidn-pbdf.email.email".to_string()],
+            ..KeyConfig::default()
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(clock_data(SystemClock))
+                .app_data(config)
+                .service(
+                    resource("/v2/key/{timestamp}")
+                        .app_data(snapshot_data(Arc::new(ibe_sk)))
+                        .wrap(NoAuth::Decryption)
+                        .route(web::get().to(handlers::key::<CGWKV>)),
+                )
+                .service(
+                    resource("/v2/sign/key")
+                        .app_data(snapshot_data(Arc::new(ibs_sk)))
+                        .wrap(NoAuth::Signing)
+                        .route(web::post().to(handlers::signing_key)),
+                ),
+        )
+        .await;
+
+        let name = Attribute::new("pbdf.gemeente.personalData.fullname", Some("Alice"));
+        let email = Attribute::new("pbdf.sidn-pbdf.email.email", Some("alice@example.com"));
+
+        let request = |con: Vec<Attribute>| {
+            let ts = now();
+            test::TestRequest::get()
+                .uri(&format!("/v2/key/{ts}"))
+                .set_json(Policy { timestamp: ts, con })
+                .to_request()
+        };
+
+        // A policy without the required attribute type is rejected.
+        let resp = test::call_service(&app, request(vec![name.clone()])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = test::call_service(&app, request(vec![name.clone(), email.clone()])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // The same holds for signing keys.
+        let req = test::TestRequest::post()
+            .uri("/v2/sign/key")
+            .set_json(SigningKeyRequest {
+                pub_sign_id: vec![email],
+                priv_sign_id: Some(vec![name]),
+                extra_sign_ids: None,
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_max_con() {
+        let (_, _, ibe_sk, _, ibs_sk) = default_setup().await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(clock_data(SystemClock))
+                .app_data(Data::new(KeyConfig {
+                    max_con: 2,
+                    ..KeyConfig::default()
+                }))
+                .service(resource("/v2/policy/validate").route(web::get().to(handlers::validate)))
+                .service(
+                    resource("/v2/key/{timestamp}")
+                        .app_data(snapshot_data(Arc::new(ibe_sk)))
+                        .wrap(NoAuth::Decryption)
+                        .route(web::get().to(handlers::key::<CGWKV>)),
+                )
+                .service(
+                    resource("/v2/sign/key")
+                        .app_data(snapshot_data(Arc::new(ibs_sk)))
+                        .wrap(NoAuth::Signing)
+                        .route(web::post().to(handlers::signing_key)),
+                ),
+        )
+        .await;
+
+        let con = |n: usize| -> Vec<Attribute> {
+            (0..n)
+                .map(|i| Attribute::new(&format!("irma-demo.test.test.attr{i}"), Some("value")))
+                .collect()
+        };
+
+        let request = |con: Vec<Attribute>| {
+            let ts = now();
+            test::TestRequest::get()
+                .uri(&format!("/v2/key/{ts}"))
+                .set_json(Policy { timestamp: ts, con })
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, request(con(2))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Well below the cryptographic limit, but beyond the configured one.
+        let resp = test::call_service(&app, request(con(3))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/v2/sign/key")
+            .set_json(SigningKeyRequest {
+                pub_sign_id: con(3),
+                priv_sign_id: None,
+                extra_sign_ids: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/v2/policy/validate")
+            .set_json(IrmaAuthRequest {
+                con: con(3),
+                validity: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_irma_unreachable() {
+        // An IRMA server that refuses connections: the port is released right after binding.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let irma = Data::new(format!("http://127.0.0.1:{port}"));
+
+        let app = test::init_service(
+            App::new()
+                .service(
+                    resource("/v2/irma/start")
+                        .app_data(irma.clone())
+                        .route(web::post().to(handlers::start)),
+                )
+                .service(
+                    resource("/v2/irma/jwt/{token}")
+                        .app_data(irma)
+                        .route(web::get().to(handlers::jwt)),
+                ),
+        )
+        .await;
+
+        let requests = [
+            test::TestRequest::post()
+                .uri("/v2/irma/start")
+                .set_json(IrmaAuthRequest {
+                    con: vec![Attribute::new("testattribute", Some("testvalue"))],
+                    validity: None,
+                })
+                .to_request(),
+            test::TestRequest::get()
+                .uri("/v2/irma/jwt/token")
+                .to_request(),
+        ];
+
+        for request in requests {
+            let resp = test::call_service(&app, request).await;
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["message"], "identity provider unavailable");
+        }
+    }
+
     #[actix_web::test]
     async fn test_get_usk() {
         let (app, _, _, _, _) = default_setup().await;
@@ -303,6 +1313,8 @@ pub(crate) mod tests {
 
         assert_eq!(key_response.status, SessionStatus::Done);
         assert_eq!(key_response.proof_status, Some(ProofStatus::Valid));
+        assert!(key_response.issued_at.is_some_and(|iat| iat >= ts));
+        assert_eq!(key_response.valid_until, Some(ts + DEFAULT_USK_TTL));
     }
 
     #[actix_web::test]
@@ -312,6 +1324,7 @@ pub(crate) mod tests {
         let skr = SigningKeyRequest {
             pub_sign_id: vec![Attribute::new("testattribute", Some("testvalue"))],
             priv_sign_id: None,
+            extra_sign_ids: None,
         };
 
         let req = test::TestRequest::post()
@@ -338,6 +1351,7 @@ pub(crate) mod tests {
                 "private test attribute",
                 Some("some private information"),
             )]),
+            extra_sign_ids: None,
         };
 
         let req = test::TestRequest::post()
@@ -358,6 +1372,44 @@ pub(crate) mod tests {
             .is_some_and(|k| k.policy.con == skr.priv_sign_id.unwrap()));
     }
 
+    #[actix_web::test]
+    async fn test_get_usk_signing_batch() {
+        let (app, _, _, _, _) = default_setup().await;
+
+        let extra_sign_ids = vec![
+            vec![Attribute::new("email", Some("alice@example.com"))],
+            vec![
+                Attribute::new("email", Some("alice@work.example.com")),
+                Attribute::new("name", Some("Alice")),
+            ],
+        ];
+
+        let skr = SigningKeyRequest {
+            pub_sign_id: vec![Attribute::new("testattribute", Some("testvalue"))],
+            priv_sign_id: None,
+            extra_sign_ids: Some(extra_sign_ids.clone()),
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/v2/sign/key")
+            .set_json(&skr)
+            .to_request();
+
+        let resp = test::try_call_service(&app, req).await.unwrap();
+        let key_response: SigningKeyResponse = test::try_read_body_json(resp).await.unwrap();
+
+        assert_eq!(key_response.status, SessionStatus::Done);
+        assert!(key_response
+            .pub_sign_key
+            .is_some_and(|k| k.policy.con == skr.pub_sign_id));
+
+        let extra_sign_keys = key_response.extra_sign_keys.unwrap();
+        assert_eq!(extra_sign_keys.len(), extra_sign_ids.len());
+        for (key, con) in extra_sign_keys.iter().zip(extra_sign_ids.iter()) {
+            assert_eq!(&key.policy.con, con);
+        }
+    }
+
     #[actix_web::test]
     async fn test_round_signing() {
         let mut rng = thread_rng();
@@ -366,6 +1418,7 @@ pub(crate) mod tests {
         let skr = SigningKeyRequest {
             pub_sign_id: vec![Attribute::new("testattribute", Some("testvalue"))],
             priv_sign_id: None,
+            extra_sign_ids: None,
         };
 
         let req = test::TestRequest::post()
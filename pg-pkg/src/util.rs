@@ -2,6 +2,7 @@ use actix_http::header::HeaderValue;
 use actix_http::header::HttpDate;
 use actix_web::dev::ServiceRequest;
 use actix_web::http::header::EntityTag;
+use actix_web::web::Data;
 
 use pg_core::kem::{cgw_kv::CGWKV, IBKEM};
 use pg_core::Compress;
@@ -15,6 +16,7 @@ use paste::paste;
 use serde::Serialize;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::SystemTime;
 use twox_hash::XxHash64;
 
@@ -55,6 +57,51 @@ pub fn current_time_u64() -> Result<u64, crate::Error> {
     Ok(n)
 }
 
+/// A source of the current time (UNIX time, in seconds).
+///
+/// The key issuing handlers read the time from a `Data<dyn Clock>`, such that it can be pinned in
+/// tests.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Result<u64, crate::Error>;
+}
+
+/// The system clock, used in production.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<u64, crate::Error> {
+        current_time_u64()
+    }
+}
+
+/// Wraps a [`Clock`], such that it can be passed to the handlers.
+pub fn clock_data<C: Clock + 'static>(clock: C) -> Data<dyn Clock> {
+    Data::from(Arc::new(clock) as Arc<dyn Clock>)
+}
+
+/// A source of state that can be replaced while the server is running.
+///
+/// The handlers read the (master key) state from a `Data<dyn Snapshot<T>>`, such that all master
+/// keys can be swapped at once (see [`crate::server::MasterKeys`]), while tests can pass fixed state.
+pub trait Snapshot<T>: Send + Sync {
+    /// Returns the current state.
+    fn load(&self) -> Arc<T>;
+}
+
+/// Fixed state, which is never replaced.
+impl<T: Send + Sync> Snapshot<T> for Arc<T> {
+    fn load(&self) -> Arc<T> {
+        self.clone()
+    }
+}
+
+/// Wraps a [`Snapshot`], such that it can be passed to the handlers.
+pub fn snapshot_data<T: 'static, S: Snapshot<T> + 'static>(snapshot: S) -> Data<dyn Snapshot<T>> {
+    Data::from(Arc::new(snapshot) as Arc<dyn Snapshot<T>>)
+}
+
 impl ParametersData {
     /// Precompute the public parameters, including cache headers.
     pub(crate) fn new<T: Serialize>(t: &T, path: Option<&str>) -> Result<ParametersData, PKGError> {
@@ -91,7 +138,7 @@ macro_rules! read_keypair {
                 const PK_LENGTH: usize = $scheme::PK_BYTES;
                 const SK_LENGTH: usize = $scheme::SK_BYTES;
 
-                let pk_bytes = std::fs::read(pk_path).unwrap();
+                let pk_bytes = std::fs::read(pk_path)?;
                 if pk_bytes.len() != PK_LENGTH {
                     return Err(PKGError::Setup("wrong pk length".to_string()));
                 }
@@ -99,7 +146,7 @@ macro_rules! read_keypair {
                 let pk_bytes = array_ref![&pk_bytes, 0, PK_LENGTH];
                 let pk = open_ct(<$scheme as IBKEM>::Pk::from_bytes(pk_bytes)).ok_or(PKGError::Setup("could not read pk".to_string()))?;
 
-                let sk_bytes = std::fs::read(sk_path).unwrap();
+                let sk_bytes = std::fs::read(sk_path)?;
                 if sk_bytes.len() != SK_LENGTH {
                     return Err(PKGError::Setup("wrong sk length".to_string()));
                 }
@@ -129,3 +176,13 @@ pub(crate) fn gg_read_key_pair(
 
     Ok((pk, sk))
 }
+
+pub(crate) fn gg_read_signing_key(
+    path: impl AsRef<Path>,
+) -> Result<pg_core::ibs::gg::UserSecretKey, PKGError> {
+    let bytes = std::fs::read(path)?;
+    let key: pg_core::ibs::gg::UserSecretKey = bincode::deserialize(&bytes)
+        .map_err(|e| PKGError::Setup(format!("could not deserialize parameters key: {e}")))?;
+
+    Ok(key)
+}
@@ -8,7 +8,7 @@
 
 use pg_core::artifacts::{PublicKey, SigningKeyExt, UserSecretKey, VerifyingKey};
 use pg_core::client::web::stream::{StreamSealerConfig, StreamUnsealerConfig};
-use pg_core::client::web::{SealerMemoryConfig, UnsealerMemoryConfig};
+use pg_core::client::web::{seal_bytes, unseal_bytes, SealerMemoryConfig, UnsealerMemoryConfig};
 use pg_core::client::{Header, Sealer, Unsealer};
 use pg_core::identity::{EncryptionPolicy, HiddenPolicy};
 use pg_core::kem::cgw_kv::CGWKV;
@@ -125,6 +125,75 @@ pub async fn js_seal(
     Ok(res)
 }
 
+/// Seals the contents of a `Uint8Array` into a `Uint8Array` in one go.
+///
+/// Unlike [`js_seal`], this takes no intermediate objects and can be paired with
+/// [`js_unseal_bytes`]. The output can also be unsealed by the native in-memory `Unsealer`.
+///
+/// # Arguments
+///
+/// * `mpk`      - Master public key, can be obtained using, e.g. fetch(`{PKGURL}/v2/parameters`).
+/// * `options`  - The seal options [`ISealOptions`].
+/// * `plain`    - The plaintext `Uint8Array` for data encapsulation.
+#[wasm_bindgen(js_name = sealBytes)]
+pub async fn js_seal_bytes(
+    mpk: JsValue,
+    options: ISealOptions,
+    plain: Uint8Array,
+) -> Result<Uint8Array, JsValue> {
+    let mut rng = rand::thread_rng();
+
+    let mpk: PublicKey<CGWKV> = serde_wasm_bindgen::from_value(mpk)?;
+
+    let SealOptions {
+        policy,
+        pub_sign_key,
+        priv_sign_key,
+    } = serde_wasm_bindgen::from_value(options.into())?;
+
+    let res = seal_bytes(
+        &mpk,
+        &policy,
+        &pub_sign_key,
+        priv_sign_key.as_ref(),
+        &mut rng,
+        &plain,
+    )
+    .await?;
+
+    Ok(res)
+}
+
+/// Unseals a `Uint8Array` sealed in memory in one go.
+///
+/// Returns an array containing the plaintext and the verification result.
+///
+/// # Arguments
+///
+/// * `input`        - The ciphertext `Uint8Array`.
+/// * `vk`           - The verifying key.
+/// * `recipient_id` - The recipient identifier used for unsealing.
+/// * `usk`          - The User Secret Key associated with the policy of this recipient.
+#[wasm_bindgen(js_name = unsealBytes)]
+pub async fn js_unseal_bytes(
+    input: Uint8Array,
+    vk: JsValue,
+    recipient_id: String,
+    usk: JsValue,
+) -> Result<Array, JsValue> {
+    let vk: VerifyingKey = serde_wasm_bindgen::from_value(vk)?;
+    let usk: UserSecretKey<CGWKV> = serde_wasm_bindgen::from_value(usk)?;
+
+    let (output, pol) = unseal_bytes(&input, &vk, &recipient_id, &usk).await?;
+    let pol_serialized = serde_wasm_bindgen::to_value(&pol)?;
+
+    let arr = Array::new_with_length(2);
+    arr.set(0, output.into());
+    arr.set(1, pol_serialized);
+
+    Ok(arr)
+}
+
 /// Seals the contents of a `ReadableStream` into a `WritableStream` using
 /// the given master public key and policies.
 ///
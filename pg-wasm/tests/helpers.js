@@ -23,3 +23,8 @@ export function new_recording_writable_stream() {
     });
     return { stream, written };
 }
+
+export async function aes_gcm_encrypt(key, iv, data) {
+    const k = await crypto.subtle.importKey("raw", key, "AES-GCM", false, ["encrypt"]);
+    return new Uint8Array(await crypto.subtle.encrypt({ name: "AES-GCM", iv }, k, data));
+}
@@ -23,6 +23,11 @@ extern "C" {
     pub fn written(this: &WritableStreamAndEvents) -> Box<[JsValue]>;
 }
 
+#[wasm_bindgen(module = "/tests/helpers.js")]
+extern "C" {
+    pub async fn aes_gcm_encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> JsValue;
+}
+
 pub struct RecordingWritableStream {
     raw: WritableStreamAndEvents,
 }
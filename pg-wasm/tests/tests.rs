@@ -5,7 +5,9 @@ use pg_core::client::{Sealer, Unsealer, VerificationResult};
 use pg_core::consts::SYMMETRIC_CRYPTO_DEFAULT_CHUNK;
 use pg_core::test::TestSetup;
 use pg_wasm::SealOptions;
-use pg_wasm::{js_seal, js_stream_seal, MemoryUnsealer, StreamUnsealer};
+use pg_wasm::{
+    js_seal, js_seal_bytes, js_stream_seal, js_unseal_bytes, MemoryUnsealer, StreamUnsealer,
+};
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -425,6 +427,99 @@ mod stream {
         assert_eq!(&plain, &plain2);
     }
 
+    // Seals as the browser did before VERSION_V4, i.e., each segment using Web Crypto AES-GCM.
+    async fn test_legacy_to_web(len: usize) {
+        use pg_core::client::{Algorithm, Header, Mode};
+        use pg_core::consts::*;
+        use pg_core::ibs::gg::Signer;
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let signing_key = &setup.signing_keys[0];
+        let usk = serde_wasm_bindgen::to_value(&setup.usks[2]).unwrap();
+        let vk = serde_wasm_bindgen::to_value(&setup.ibs_pk).unwrap();
+
+        let (header, ss) = Header::new(&setup.ibe_pk, &setup.policy, &mut rng).unwrap();
+        let Mode::Streaming { segment_size, .. } = header.mode else {
+            panic!("not a streaming header");
+        };
+        let Algorithm::Aes128Gcm(iv) = header.algo;
+
+        // The header as it was serialized in VERSION_V3.
+        let header_raw =
+            bincode::serialize(&(&header.recipients, &header.algo, &header.mode)).unwrap();
+
+        let mut signer = Signer::default().chain(&header_raw);
+        let header_sig = signer.clone().sign(&signing_key.key.0, &mut rng);
+        let header_sig = bincode::serialize(&(&header_sig, &signing_key.policy)).unwrap();
+
+        let mut b = Vec::new();
+        b.extend_from_slice(&PRELUDE);
+        b.extend_from_slice(&VERSION_V3.to_be_bytes());
+        b.extend_from_slice(&(header_raw.len() as u32).to_be_bytes());
+        b.extend_from_slice(&header_raw);
+        b.extend_from_slice(&(header_sig.len() as u32).to_be_bytes());
+        b.extend_from_slice(&header_sig);
+
+        let pol = bincode::serialize(&signing_key.policy).unwrap();
+        let mut payload = (pol.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(&pol);
+        let pol_len = payload.len();
+
+        let plain = rand_vec(len);
+        payload.extend_from_slice(&plain);
+
+        let chunks: Vec<&[u8]> = payload.chunks(segment_size as usize).collect();
+        for (counter, chunk) in chunks.iter().enumerate() {
+            let last = counter == chunks.len() - 1;
+            let counter = counter as u32;
+
+            // The policy prefix is not signed.
+            let start = if counter == 0 { pol_len } else { 0 };
+            signer.update(&chunk[start..]);
+
+            let sig = signer
+                .clone()
+                .chain(&counter.to_be_bytes())
+                .chain(&[last as u8])
+                .sign(&signing_key.key.0, &mut rng);
+
+            let mut segment = chunk.to_vec();
+            segment.extend_from_slice(&bincode::serialize(&sig).unwrap());
+
+            let mut nonce = [0u8; IV_SIZE];
+            nonce[..STREAM_NONCE_SIZE].copy_from_slice(&iv.0[..STREAM_NONCE_SIZE]);
+            nonce[STREAM_NONCE_SIZE..IV_SIZE - 1].copy_from_slice(&counter.to_be_bytes());
+            nonce[IV_SIZE - 1] = last as u8;
+
+            let ct = aes_gcm_encrypt(&ss.0[..KEY_SIZE], &nonce, &segment).await;
+            b.extend_from_slice(&ct.dyn_into::<Uint8Array>().unwrap().to_vec());
+        }
+
+        let unsealer_input = new_readable_stream_from_array(
+            vec![Uint8Array::from(&b[..]).dyn_into().unwrap()].into_boxed_slice(),
+        );
+        let unsealer_output = new_recording_writable_stream();
+
+        let unsealer = StreamUnsealer::new(unsealer_input, vk).await.unwrap();
+
+        let res = unsealer
+            .unseal("Bob".to_string(), usk, unsealer_output.stream())
+            .await
+            .unwrap();
+        let res: VerificationResult = serde_wasm_bindgen::from_value(res).unwrap();
+
+        let plain2: Vec<u8> = unsealer_output
+            .written()
+            .iter()
+            .flat_map(|chunk| chunk.dyn_ref::<Uint8Array>().unwrap().to_vec())
+            .collect();
+
+        assert_eq!(&plain, &plain2);
+        assert_eq!(&res.public, &signing_key.policy);
+    }
+
     #[wasm_bindgen_test]
     async fn test_seal_unseal_rust() {
         for l in LENGTHS {
@@ -452,4 +547,59 @@ mod stream {
             test_rust_to_web(*l as usize).await;
         }
     }
+
+    #[wasm_bindgen_test]
+    async fn test_unseal_legacy_web() {
+        for l in LENGTHS {
+            test_legacy_to_web(*l as usize).await;
+        }
+    }
+}
+
+mod bytes {
+    use super::*;
+
+    #[wasm_bindgen_test]
+    async fn test_seal_unseal_bytes() {
+        use pg_core::client::rust::UnsealerMemoryConfig as UC;
+
+        let mut rng = rand::thread_rng();
+        let setup = TestSetup::new(&mut rng);
+
+        let options = SealOptions {
+            policy: setup.policy.clone(),
+            pub_sign_key: setup.signing_keys[0].clone(),
+            priv_sign_key: Some(setup.signing_keys[1].clone()),
+        };
+
+        let js_options = serde_wasm_bindgen::to_value(&options).unwrap();
+
+        let mpk = serde_wasm_bindgen::to_value(&setup.ibe_pk).unwrap();
+        let usk = serde_wasm_bindgen::to_value(&setup.usks[2]).unwrap();
+        let vk = serde_wasm_bindgen::to_value(&setup.ibs_pk).unwrap();
+
+        let plain = b"Hello, Bob!".to_vec();
+
+        let ct = js_seal_bytes(mpk, js_options.into(), Uint8Array::from(&plain[..]))
+            .await
+            .unwrap();
+
+        let res = js_unseal_bytes(ct.clone(), vk, "Bob".to_string(), usk)
+            .await
+            .unwrap();
+        let verified: VerificationResult = serde_wasm_bindgen::from_value(res.get(1)).unwrap();
+
+        assert_eq!(
+            &plain,
+            &res.get(0).dyn_into::<Uint8Array>().unwrap().to_vec()
+        );
+        assert_eq!(&verified.public, &setup.signing_keys[0].policy);
+        assert_eq!(verified.private, Some(setup.signing_keys[1].policy.clone()));
+
+        // The output is compatible with the Rust unsealer.
+        let unsealer = Unsealer::<_, UC>::new(&ct.to_vec(), &setup.ibs_pk).unwrap();
+        let (plain2, _) = unsealer.unseal("Bob", &setup.usks[2]).unwrap();
+
+        assert_eq!(&plain, &plain2);
+    }
 }
@@ -1,4 +1,6 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
 use rand::{thread_rng, Rng, RngCore};
 
 use reck::Deck;
@@ -22,11 +24,11 @@ fn bench(c: &mut Benchmarker) {
         group.throughput(Throughput::Bytes(size as u64));
 
         group.bench_function(BenchmarkId::new("wrap", size), |b| {
-            let mut deck = Deck::new(&key, &nonce);
+            let mut deck = Deck::new(&key, &nonce).unwrap();
             b.iter(|| deck.wrap(black_box(&mut buf)))
         });
         group.bench_function(BenchmarkId::new("unwrap", size), |b| {
-            let mut deck = Deck::new(&key, &nonce);
+            let mut deck = Deck::new(&key, &nonce).unwrap();
             b.iter(|| deck.unwrap(black_box(&mut buf)))
         });
     }
@@ -34,18 +36,71 @@ fn bench(c: &mut Benchmarker) {
     group.finish();
 }
 
+// Compares the final segment variants to the regular ones on small payloads, where the cost of a
+// call is most noticeable. All variants start from a fresh `Deck` and buffer, such that the
+// setup is excluded from the measurement in the same way.
+fn bench_last(c: &mut Benchmarker) {
+    let mut group = c.benchmark_group("DeckTinkLast");
+    let mut rng = thread_rng();
+    let key = rng.gen::<[u8; 32]>();
+    let nonce = rng.gen::<[u8; 32]>();
+
+    for e in (4..=10).step_by(2) {
+        let size = 1 << e;
+        let mut plain = vec![0u8; size];
+        rng.fill_bytes(&mut plain);
+
+        let deck = Deck::new(&key, &nonce).unwrap();
+        let mut cipher = plain.clone();
+        deck.clone().wrap(&mut cipher).unwrap();
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_function(BenchmarkId::new("wrap", size), |b| {
+            b.iter_batched(
+                || (deck.clone(), plain.clone()),
+                |(mut deck, mut buf)| deck.wrap(black_box(&mut buf)),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("wrap_last", size), |b| {
+            b.iter_batched(
+                || (deck.clone(), plain.clone()),
+                |(deck, mut buf)| deck.wrap_last(black_box(&mut buf)),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("unwrap", size), |b| {
+            b.iter_batched(
+                || (deck.clone(), cipher.clone()),
+                |(mut deck, mut buf)| deck.unwrap(black_box(&mut buf)),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("unwrap_last", size), |b| {
+            b.iter_batched(
+                || (deck.clone(), cipher.clone()),
+                |(mut deck, mut buf)| deck.unwrap_last(black_box(&mut buf)),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
 #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
 criterion_group!(
     name = benches;
     config = Criterion::default();
-    targets = bench
+    targets = bench, bench_last
 );
 
 #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
 criterion_group!(
     name = benches;
     config = Criterion::default().with_measurement(criterion_cycles_per_byte::CyclesPerByte);
-    targets = bench
+    targets = bench, bench_last
 );
 
 criterion_main!(benches);
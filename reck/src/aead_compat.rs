@@ -0,0 +1,88 @@
+//! An adapter for the RustCrypto [`aead`] traits.
+
+use crate::{Deck, COUNTER_LEN};
+use aead::consts::{U0, U32, U4};
+use aead::{AeadCore, AeadInPlace, Key, KeyInit, KeySizeUser, Nonce, Tag};
+
+/// The nonce of the [`Deck`] that underlies a [`DeckAead`] created using [`KeyInit`].
+const AEAD_NONCE: &[u8] = b"reck-aead";
+
+/// A [`Deck`] that implements [`AeadInPlace`], for single-shot use in crates that expect the
+/// RustCrypto traits.
+///
+/// The AEAD nonce is the counter of the message as a big-endian `u32`, and the tag is detached.
+/// Without associated data, a message is wrapped exactly like [`Deck::wrap_detached`] would under
+/// that counter. Associated data is bound per message, like [`Deck::with_ad`] binds a session.
+///
+/// [`Deck`] is a session AEAD rather than a generic one: a counter must only be used for a single
+/// message under the same key (and nonce of the [`Deck`]), since reusing it reuses the keystream.
+/// The adapter does not track the counters that were used, nor does it re-key.
+#[derive(Clone)]
+pub struct DeckAead {
+    deck: Deck,
+}
+
+impl DeckAead {
+    /// The keyed state for a message with associated data `ad`.
+    fn bound(&self, ad: &[u8]) -> Deck {
+        if ad.is_empty() {
+            self.deck.clone()
+        } else {
+            self.deck.clone().with_ad(ad)
+        }
+    }
+}
+
+impl From<Deck> for DeckAead {
+    fn from(deck: Deck) -> Self {
+        DeckAead { deck }
+    }
+}
+
+impl KeySizeUser for DeckAead {
+    type KeySize = U32;
+}
+
+impl KeyInit for DeckAead {
+    fn new(key: &Key<Self>) -> Self {
+        DeckAead { deck: Deck::init(key, AEAD_NONCE) }
+    }
+}
+
+impl AeadCore for DeckAead {
+    type NonceSize = U4;
+    type TagSize = U32;
+    type CiphertextOverhead = U0;
+}
+
+fn counter_of(nonce: &Nonce<DeckAead>) -> u32 {
+    let mut counter = [0u8; COUNTER_LEN];
+    counter.copy_from_slice(nonce);
+
+    u32::from_be_bytes(counter)
+}
+
+impl AeadInPlace for DeckAead {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<Tag<Self>> {
+        let tag = self.bound(associated_data)._wrap_in_place(buffer, counter_of(nonce));
+
+        Ok(Tag::<Self>::clone_from_slice(&tag))
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aead::Result<()> {
+        self.bound(associated_data)
+            ._unwrap_in_place(buffer, counter_of(nonce), tag)
+            .map_err(|_| aead::Error)
+    }
+}
@@ -0,0 +1,186 @@
+//! Authenticated encryption of byte streams using [`std::io`].
+//!
+//! The plaintext is split into segments of a fixed size, which are wrapped one by one. Every
+//! segment is followed by its counter and tag. The final segment is always shorter than a full
+//! segment, possibly empty, so a stream that was cut off at a segment boundary is detected.
+
+use crate::{Deck, COUNTER_LEN, COUNTER_TAG_LEN};
+use std::io::{self, Read, Write};
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Wraps all data written into it in segments of `segment_size` bytes.
+///
+/// The writer must be closed using [`DeckWriter::close`], which writes the final segment.
+/// Flushing does not wrap a partial segment.
+pub struct DeckWriter<W: Write> {
+    inner: W,
+    deck: Deck,
+    segment_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DeckWriter<W> {
+    /// Creates a new [`DeckWriter`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `segment_size` is zero.
+    pub fn new(inner: W, deck: Deck, segment_size: usize) -> Self {
+        assert!(segment_size > 0, "segment size must be positive");
+
+        DeckWriter {
+            inner,
+            deck,
+            segment_size,
+            buf: Vec::with_capacity(segment_size + COUNTER_TAG_LEN),
+        }
+    }
+
+    /// Wraps the buffered data and writes it to the inner writer.
+    fn write_segment(&mut self) -> io::Result<()> {
+        self.deck
+            .wrap(&mut self.buf)
+            .map_err(|_e| invalid_data("too many segments"))?;
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+
+        Ok(())
+    }
+
+    /// Writes the final segment and returns the inner writer.
+    pub fn close(mut self) -> io::Result<W> {
+        self.deck
+            .wrap_last(&mut self.buf)
+            .map_err(|_e| invalid_data("too many segments"))?;
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for DeckWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.segment_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+
+        // Full segments are wrapped right away, such that the final segment is never a full one.
+        if self.buf.len() == self.segment_size {
+            self.write_segment()?;
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Unwraps a stream written by a [`DeckWriter`] using the same segment size.
+///
+/// The [`Deck`] must be at the same counter as the one given to the [`DeckWriter`].
+///
+/// Reading fails with [`io::ErrorKind::InvalidData`] if a segment is not authentic, out of order
+/// or if the stream was cut off. Data that has been read before such an error is authentic.
+pub struct DeckReader<R: Read> {
+    inner: R,
+    deck: Deck,
+    // The current segment, of which the plaintext is `buf[pos..len]` after unwrapping.
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+    counter: u32,
+    done: bool,
+}
+
+impl<R: Read> DeckReader<R> {
+    /// Creates a new [`DeckReader`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `segment_size` is zero.
+    pub fn new(inner: R, deck: Deck, segment_size: usize) -> Self {
+        assert!(segment_size > 0, "segment size must be positive");
+
+        DeckReader {
+            inner,
+            counter: deck.counter(),
+            deck,
+            buf: vec![0u8; segment_size + COUNTER_TAG_LEN],
+            pos: 0,
+            len: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and unwraps the next segment.
+    fn read_segment(&mut self) -> io::Result<()> {
+        let sealed_len = self.buf.len();
+        let mut tail = 0;
+
+        while tail < sealed_len {
+            match self.inner.read(&mut self.buf[tail..]) {
+                Ok(0) => break,
+                Ok(n) => tail += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if tail < COUNTER_TAG_LEN {
+            return Err(invalid_data("stream is truncated"));
+        }
+
+        let ct_len = tail - COUNTER_TAG_LEN;
+        let counter = &self.buf[ct_len..ct_len + COUNTER_LEN];
+        let counter = u32::from_be_bytes(counter.try_into().unwrap()); // cannot panic
+        if counter != self.counter {
+            return Err(invalid_data("segment is out of order"));
+        }
+
+        self.len = self
+            .deck
+            .unwrap_slice(&mut self.buf, tail)
+            .map_err(|_e| invalid_data("segment is not authentic"))?;
+        self.pos = 0;
+
+        // Only the final segment is shorter than a full one.
+        if tail < sealed_len {
+            self.done = true;
+        } else {
+            self.counter = self
+                .deck
+                .counter_after(self.counter)
+                .ok_or_else(|| invalid_data("too many segments"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DeckReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.len {
+            if self.done || out.is_empty() {
+                return Ok(0);
+            }
+
+            self.read_segment()?;
+        }
+
+        let n = out.len().min(self.len - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
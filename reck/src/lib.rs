@@ -1,44 +1,300 @@
+use std::num::NonZeroU32;
+use std::sync::Arc;
 use xoofff::Xoofff;
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+mod io;
+
+#[cfg(feature = "aead")]
+mod aead_compat;
+
 #[cfg(test)]
 mod tests;
 
+pub use io::{DeckReader, DeckWriter};
+
+#[cfg(feature = "aead")]
+pub use aead_compat::DeckAead;
+
 /// The length of the authentication tags (in bytes).
-const TAG_LEN: usize = 32;
+pub const TAG_LEN: usize = 32;
 
 /// Length of the domain seperation (in bits).
 const DS_BIT_LEN: usize = 1;
 
+/// Domain seperation of the tags of authenticate-only segments.
+const AAD_ONLY_DS: u8 = 0b11;
+
+/// Length of the domain seperation of authenticate-only segments (in bits).
+const AAD_ONLY_DS_BIT_LEN: usize = 2;
+
+/// Domain seperation of the key derivation when re-keying at an interval.
+const REKEY_DS: u8 = 0b01;
+
+/// Length of the domain seperation of the key derivation when re-keying (in bits).
+const REKEY_DS_BIT_LEN: usize = 2;
+
+/// The length of the keys derived when re-keying at an interval (in bytes).
+const REKEY_KEY_LEN: usize = 32;
+
 /// The length of the counter (in bytes).
 const COUNTER_LEN: usize = 4;
 
+/// The length of the keystream that is squeezed at once (in bytes), such that wrapping and
+/// unwrapping do not allocate.
+const KEYSTREAM_CHUNK_LEN: usize = 64;
+
 /// The length of the counter plus the authentication tags (in bytes).
-const COUNTER_TAG_LEN: usize = TAG_LEN + COUNTER_LEN;
+pub const COUNTER_TAG_LEN: usize = TAG_LEN + COUNTER_LEN;
 
+/// The last counter that can be used to wrap a segment.
+const LAST_COUNTER: u32 = u32::MAX - 1;
+
+/// The minimum length of the key (in bytes), for 128-bit security.
+pub const MIN_KEY_LEN: usize = 16;
+
+/// The minimum length of the nonce (in bytes).
+///
+/// A nonce must never be repeated under the same key. Shorter nonces make an accidental
+/// repetition likely if they are chosen at random.
+pub const MIN_NONCE_LEN: usize = 7;
+
+/// The known-answer vector of [`Deck::self_test`], taken from `kats/test_vector_bytes.txt`.
+#[cfg(feature = "self-test")]
+const SELF_TEST_KEY: [u8; 16] = [
+    0x9d, 0x7e, 0x5f, 0x40, 0x21, 0x02, 0xe3, 0xc4, 0xa4, 0x85, 0x66, 0x47, 0x28, 0x09,
+    0xea, 0xcb,
+];
+#[cfg(feature = "self-test")]
+const SELF_TEST_NONCE: [u8; 24] = [
+    0xa7, 0x28, 0xa8, 0x29, 0xa9, 0x2a, 0xaa, 0x2b, 0xab, 0x2c, 0xac, 0x2d, 0xad, 0x2e,
+    0xae, 0x2f, 0xaf, 0x30, 0xb0, 0x31, 0xb1, 0x32, 0xb2, 0x33,
+];
+#[cfg(feature = "self-test")]
+const SELF_TEST_MSG: [u8; 12] = [
+    0x21, 0xa2, 0x22, 0xa3, 0x23, 0xa4, 0x24, 0xa5, 0x25, 0xa6, 0x26, 0xa7,
+];
+#[cfg(feature = "self-test")]
+const SELF_TEST_CIPHER: [u8; 12] = [
+    0x06, 0xa8, 0x49, 0xf6, 0xb7, 0xe8, 0x5c, 0x73, 0xd1, 0x58, 0x77, 0xf0,
+];
+#[cfg(feature = "self-test")]
+const SELF_TEST_TAG: [u8; 32] = [
+    0x07, 0x47, 0x95, 0x0f, 0xcb, 0xac, 0x58, 0xb4, 0x67, 0xef, 0x63, 0x1c, 0x8e, 0x64,
+    0xa7, 0xc7, 0x11, 0x52, 0x65, 0x42, 0x60, 0xa3, 0x4b, 0x12, 0x46, 0x67, 0xd2, 0x6d,
+    0xed, 0xa4, 0x2f, 0x4d,
+];
+
+/// A DECK-based session AEAD.
+///
+/// Cloning a [`Deck`] copies both the keyed state and the counter, so a clone continues the
+/// sequence from the same segment boundary.
+#[derive(Clone)]
 pub struct Deck {
     xoofff: Xoofff,
     counter: u32,
+    rekey: Option<Arc<dyn Fn() -> Deck + Send + Sync>>,
+    rekey_interval: Option<NonZeroU32>,
+    epoch: u64,
 }
 
 #[derive(Debug)]
 pub enum Error {
     Overflow,
     WrongTag,
+    BufferTooSmall,
+    InvalidKeyLength,
+    InvalidNonceLength,
+    SelfTestFailed,
 }
 
 impl Deck {
-    pub fn new(key: &[u8], nonce: &[u8]) -> Self {
+    /// Creates a new [`Deck`] from a key and a nonce.
+    ///
+    /// Errors if the key is shorter than [`MIN_KEY_LEN`] or the nonce is shorter than
+    /// [`MIN_NONCE_LEN`].
+    pub fn new(key: &[u8], nonce: &[u8]) -> Result<Self, Error> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(Error::InvalidKeyLength);
+        }
+        if nonce.len() < MIN_NONCE_LEN {
+            return Err(Error::InvalidNonceLength);
+        }
+
+        Ok(Self::init(key, nonce))
+    }
+
+    /// Creates a new [`Deck`] without checking the key and nonce lengths.
+    fn init(key: &[u8], nonce: &[u8]) -> Self {
         let mut xoofff = Xoofff::new(key);
 
         xoofff.absorb(nonce);
         xoofff.finalize(0, 0, 0);
         xoofff.restart();
 
-        Deck { xoofff, counter: 0 }
+        Deck {
+            xoofff,
+            counter: 0,
+            rekey: None,
+            rekey_interval: None,
+            epoch: 0,
+        }
+    }
+
+    /// Rebuilds a [`Deck`] that continues the sequence at `counter`.
+    ///
+    /// This can be used to resume a session across process restarts, given the value of
+    /// [`Deck::counter`] at the time the session was left.
+    ///
+    /// Never resume at a counter that was already used to wrap a segment under the same key and
+    /// nonce: reusing a counter reuses the keystream, which breaks confidentiality.
+    pub fn resume(key: &[u8], nonce: &[u8], counter: u32) -> Result<Self, Error> {
+        let mut deck = Self::new(key, nonce)?;
+        deck.counter = counter;
+
+        Ok(deck)
+    }
+
+    /// Binds the session to associated data, e.g., a hash of the header of a stream.
+    ///
+    /// The associated data is absorbed into the keyed state, so every segment wrapped afterwards
+    /// can only be unwrapped by a [`Deck`] that is bound to the same data. Must be called before
+    /// any segment is wrapped or unwrapped.
+    pub fn with_ad(mut self, ad: &[u8]) -> Self {
+        self.xoofff.absorb(ad);
+        self.xoofff.finalize(0, 0, 0);
+        self.xoofff.restart();
+
+        self
+    }
+
+    /// Re-initializes the session using `rekey` once the counter is exhausted, instead of failing
+    /// with [`Error::Overflow`].
+    ///
+    /// The returned [`Deck`] continues the sequence: the segment following the one with the last
+    /// counter is wrapped by it, and the receiving side switches to it after unwrapping the
+    /// segment with the last counter. Both sides must therefore use the same hook.
+    ///
+    /// The new [`Deck`] must use a fresh nonce (or key), which was never used before under the
+    /// same key. Otherwise, counters are reused under the same keystream, which breaks
+    /// confidentiality. [`Deck::wrap_many`] does not re-key.
+    pub fn with_rekey_on_overflow(
+        mut self,
+        rekey: impl Fn() -> Deck + Send + Sync + 'static,
+    ) -> Self {
+        self.rekey = Some(Arc::new(rekey));
+        self
+    }
+
+    /// Re-keys the session after every `interval` segments, restarting the counter at 0.
+    ///
+    /// After the segment with counter `interval - 1` is wrapped, a new key is derived from the
+    /// keyed state and the tag of that segment, and the old state is discarded. The receiving
+    /// side derives the same key after unwrapping that segment, so both sides must use the same
+    /// interval. [`Deck::epoch`] counts the re-keys so far.
+    ///
+    /// This bounds the amount of data that is processed under a single key. Moreover, since the
+    /// key derivation is one-way, a compromise of the state in one epoch does not reveal the
+    /// keystream or allow forging tags of earlier epochs. Chaining the tag also binds each epoch
+    /// to all segments before it. [`Deck::wrap_many`] does not re-key.
+    pub fn with_rekey_interval(mut self, interval: NonZeroU32) -> Self {
+        self.rekey_interval = Some(interval);
+        self
+    }
+
+    /// The number of times the session was re-keyed, either at an interval or on overflow.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Replaces the session by the one returned by the re-key hook, keeping the hooks.
+    fn rekey(&mut self) -> Result<(), Error> {
+        let rekey = self.rekey.clone().ok_or(Error::Overflow)?;
+        let (rekey_interval, epoch) = (self.rekey_interval, self.epoch);
+
+        *self = rekey();
+        self.rekey = Some(rekey);
+        self.rekey_interval = rekey_interval;
+        self.epoch = epoch + 1;
+
+        Ok(())
+    }
+
+    /// Derives the next session from the keyed state and `tag` if `counter` ends an interval.
+    ///
+    /// Returns whether the session was re-keyed.
+    #[inline(always)]
+    fn _rekey_at_interval(&mut self, counter: u32, tag: &[u8]) -> bool {
+        match self.rekey_interval {
+            Some(interval) if counter == interval.get() - 1 => (),
+            _ => return false,
+        }
+
+        let mut key = [0u8; REKEY_KEY_LEN];
+        let mut cloned = self.xoofff.clone();
+        cloned.absorb(tag);
+        cloned.finalize(REKEY_DS, REKEY_DS_BIT_LEN, 0);
+        cloned.squeeze(&mut key);
+
+        self.xoofff = Self::init(&key, &[]).xoofff;
+        self.counter = 0;
+        self.epoch += 1;
+
+        true
+    }
+
+    /// The counter of the segment that follows the one with `counter`, if any.
+    pub(crate) fn counter_after(&self, counter: u32) -> Option<u32> {
+        match self.rekey_interval {
+            Some(interval) if counter == interval.get() - 1 => Some(0),
+            _ if counter == LAST_COUNTER && self.rekey.is_some() => Some(0),
+            _ => counter.checked_add(1),
+        }
+    }
+
+    /// The counter that the next wrapped segment will use.
+    pub fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    /// Runs a known-answer test through [`Deck::wrap`] and [`Deck::unwrap`].
+    ///
+    /// Intended as a power-on self-test: errors with [`Error::SelfTestFailed`] if the ciphertext
+    /// or tag differs from the embedded vector, or if a modified ciphertext is not rejected, e.g.,
+    /// because the underlying [`Xoofff`] was miscompiled.
+    #[cfg(feature = "self-test")]
+    pub fn self_test() -> Result<(), Error> {
+        let len = SELF_TEST_MSG.len();
+        let mut buf = SELF_TEST_MSG.to_vec();
+        Deck::new(&SELF_TEST_KEY, &SELF_TEST_NONCE)?.wrap(&mut buf)?;
+
+        if buf[..len] != SELF_TEST_CIPHER
+            || buf[len..len + COUNTER_LEN] != 0u32.to_be_bytes()
+            || buf[len + COUNTER_LEN..] != SELF_TEST_TAG
+        {
+            return Err(Error::SelfTestFailed);
+        }
+
+        let mut modified = buf.clone();
+        modified[0] ^= 1;
+        if Deck::new(&SELF_TEST_KEY, &SELF_TEST_NONCE)?.unwrap(&mut modified).is_ok() {
+            return Err(Error::SelfTestFailed);
+        }
+
+        Deck::new(&SELF_TEST_KEY, &SELF_TEST_NONCE)?
+            .unwrap(&mut buf)
+            .map_err(|_| Error::SelfTestFailed)?;
+        if buf != SELF_TEST_MSG {
+            return Err(Error::SelfTestFailed);
+        }
+
+        Ok(())
     }
 
     #[inline(always)]
-    fn _absorb_finalize_squeeze(&mut self, deck: &mut Xoofff, msg: &[u8], domain_seperator: u8, out : &mut [u8] ){
+    fn _absorb_finalize_squeeze(&self, deck: &mut Xoofff, msg: &[u8], domain_seperator: u8, out : &mut [u8] ){
         deck.absorb(msg);
         deck.finalize(domain_seperator, DS_BIT_LEN, 0);
         deck.squeeze(out);
@@ -46,70 +302,73 @@ impl Deck {
     }
 
     #[inline(always)]
-    fn _absorb_finalize(&mut self, deck: &mut Xoofff, msg: &[u8], domain_seperator: u8 ){
+    fn _absorb_finalize(&self, deck: &mut Xoofff, msg: &[u8], domain_seperator: u8 ){
         deck.absorb(msg);
         deck.finalize(domain_seperator, DS_BIT_LEN, 0);
     }
 
+    /// XORs the keystream squeezed from `deck` into `buf`, one chunk at a time.
     #[inline(always)]
-    fn _xor(&self, buf_out: &mut [u8], buf_in: Vec<u8> ){
-        for (plain, s) in buf_out.iter_mut().zip(buf_in.iter()) {
-            *plain ^= s;
+    fn _xor_keystream(&self, deck: &mut Xoofff, buf: &mut [u8]) {
+        let mut squeezed = [0u8; KEYSTREAM_CHUNK_LEN];
+
+        for chunk in buf.chunks_mut(KEYSTREAM_CHUNK_LEN) {
+            let keystream = &mut squeezed[..chunk.len()];
+            deck.squeeze(keystream);
+
+            for (b, s) in chunk.iter_mut().zip(keystream.iter()) {
+                *b ^= s;
+            }
         }
     }
 
+    /// Encrypts `plain` in place under `counter` and returns the tag.
     #[inline(always)]
-    fn _wrap(&mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
+    fn _wrap_in_place(&self, plain: &mut [u8], counter: u32) -> [u8; TAG_LEN] {
         let mut cloned = self.xoofff.clone();
         let mut tag = [0u8; TAG_LEN];
 
         if plain.len() > 0 {
-            let mut squeezed = vec![0u8; plain.len()];
-            self._absorb_finalize_squeeze(&mut cloned, &self.counter.to_be_bytes(), 0b0, &mut squeezed);
-            self._xor(plain, squeezed);
-            self._absorb_finalize_squeeze(&mut cloned, &plain.as_slice(), 0b1, &mut tag);
-
+            self._absorb_finalize(&mut cloned, &counter.to_be_bytes(), 0b0);
+            self._xor_keystream(&mut cloned, plain);
+            cloned.restart();
+            self._absorb_finalize_squeeze(&mut cloned, plain, 0b1, &mut tag);
         } else {
-            self._absorb_finalize_squeeze(&mut cloned, &self.counter.to_be_bytes(), 0b1, &mut tag);
+            self._absorb_finalize_squeeze(&mut cloned, &counter.to_be_bytes(), 0b1, &mut tag);
         };
 
-        plain.extend_from_slice(&self.counter.to_be_bytes());
-        plain.extend_from_slice(&tag);
-
-        self.counter = self.counter.checked_add(1).ok_or(Error::Overflow)?;
-
-        Ok(())
+        tag
     }
 
+    /// Computes the tag that binds `data` under `counter`, without encrypting it.
     #[inline(always)]
-    pub fn wrap(&mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
-        self._wrap(plain)
-    }
+    fn _tag_aad_only(&self, data: &[u8], counter: u32) -> [u8; TAG_LEN] {
+        let mut cloned = self.xoofff.clone();
+        let mut tag = [0u8; TAG_LEN];
 
-    #[inline(never)]
-    pub fn wrap_last(mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
-        self._wrap(plain)
+        self._absorb_finalize(&mut cloned, &counter.to_be_bytes(), 0b0);
+        cloned.restart();
+        cloned.absorb(data);
+        cloned.finalize(AAD_ONLY_DS, AAD_ONLY_DS_BIT_LEN, 0);
+        cloned.squeeze(&mut tag);
+
+        tag
     }
 
+    /// Verifies `tag` over `cipher` under `counter` and decrypts `cipher` in place on success.
     #[inline(always)]
-    fn _unwrap(&mut self, cipher: &mut Vec<u8>) -> Result<(), Error> {
+    fn _unwrap_in_place(&self, cipher: &mut [u8], counter: u32, tag: &[u8]) -> Result<(), Error> {
         let mut cloned = self.xoofff.clone();
         let mut cloned2: Option<Xoofff> = None;
-        let tag;
 
-        if cipher.len() > COUNTER_TAG_LEN {
-            let ct = &cipher[..cipher.len()-COUNTER_TAG_LEN];
-            tag = &cipher[cipher.len()-TAG_LEN..];
-            let counter = &cipher[cipher.len()-COUNTER_TAG_LEN..cipher.len()-TAG_LEN];
-            self._absorb_finalize(&mut cloned, &counter, 0b0);
+        if cipher.len() > 0 {
+            self._absorb_finalize(&mut cloned, &counter.to_be_bytes(), 0b0);
             cloned2 = Some(cloned.clone());
 
             cloned.restart();
-            self._absorb_finalize(&mut cloned, &ct, 0b1);
+            self._absorb_finalize(&mut cloned, cipher, 0b1);
         } else {
-            tag = &cipher[COUNTER_LEN..];
-            let counter = &cipher[..COUNTER_LEN];
-            self._absorb_finalize(&mut cloned, &counter, 0b1);
+            self._absorb_finalize(&mut cloned, &counter.to_be_bytes(), 0b1);
         }
 
         let mut tag_prime = [0u8; TAG_LEN];
@@ -119,13 +378,175 @@ impl Deck {
             return Err(Error::WrongTag);
         }
 
-        if cipher.len() > COUNTER_TAG_LEN {
-            let mut squeezed = vec![0u8; cipher.len()-COUNTER_TAG_LEN];
-            cloned2.unwrap().squeeze(&mut squeezed); // cannot panic
-            self._xor(cipher, squeezed);
+        if let Some(mut cloned2) = cloned2 {
+            self._xor_keystream(&mut cloned2, cipher);
         }
 
-        cipher.truncate(cipher.len()-COUNTER_TAG_LEN);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn _wrap(&mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
+        let (counter, tag) = self.wrap_detached(plain)?;
+
+        plain.extend_from_slice(&counter.to_be_bytes());
+        plain.extend_from_slice(&tag);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn wrap(&mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
+        self._wrap(plain)
+    }
+
+    #[inline(never)]
+    pub fn wrap_last(mut self, plain: &mut Vec<u8>) -> Result<(), Error> {
+        self._wrap(plain)
+    }
+
+    /// Encrypts `plain` in place, without appending the counter and tag.
+    ///
+    /// Returns the counter that was used and the tag, which must be stored out-of-band and
+    /// supplied to [`Deck::unwrap_detached`].
+    pub fn wrap_detached(&mut self, plain: &mut [u8]) -> Result<(u32, [u8; TAG_LEN]), Error> {
+        // Check before wrapping, such that the plaintext is left untouched on overflow.
+        let counter = self._next_counter()?;
+        let tag = self._wrap_in_place(plain, counter);
+        self._rekey_at_interval(counter, &tag);
+
+        Ok((counter, tag))
+    }
+
+    /// Authenticates `data` in sequence with the wrapped segments, without encrypting it.
+    ///
+    /// Uses the next counter, like [`Deck::wrap`]. Returns the counter followed by the tag, which
+    /// must be supplied to [`Deck::unwrap_aad_only`] along with `data`. The tag is domain
+    /// separated from those of encrypted segments, so neither can be unwrapped as the other.
+    pub fn wrap_aad_only(&mut self, data: &[u8]) -> Result<[u8; COUNTER_TAG_LEN], Error> {
+        let counter = self._next_counter()?;
+        let tag = self._tag_aad_only(data, counter);
+        self._rekey_at_interval(counter, &tag);
+
+        let mut counter_tag = [0u8; COUNTER_TAG_LEN];
+        counter_tag[..COUNTER_LEN].copy_from_slice(&counter.to_be_bytes());
+        counter_tag[COUNTER_LEN..].copy_from_slice(&tag);
+
+        Ok(counter_tag)
+    }
+
+    /// Takes the counter for the next segment, re-keying first if the counter is exhausted.
+    #[inline(always)]
+    fn _next_counter(&mut self) -> Result<u32, Error> {
+        if self.counter > LAST_COUNTER {
+            self.rekey()?;
+        }
+
+        let counter = self.counter;
+        self.counter = counter.checked_add(1).ok_or(Error::Overflow)?;
+
+        Ok(counter)
+    }
+
+    /// Wraps multiple segments at once, using counters `start_counter`, `start_counter + 1`, ...
+    ///
+    /// Each segment is wrapped exactly like [`Deck::wrap`] would, so the output is byte-identical
+    /// to wrapping the segments sequentially starting from `start_counter`. With the `rayon`
+    /// feature, segments are wrapped in parallel.
+    ///
+    /// Returns the counter following the last segment. The internal counter is not affected.
+    pub fn wrap_many(&self, segments: &mut [Vec<u8>], start_counter: u32) -> Result<u32, Error> {
+        let n = u32::try_from(segments.len()).map_err(|_| Error::Overflow)?;
+        let end = start_counter.checked_add(n).ok_or(Error::Overflow)?;
+
+        let wrap_one = |(i, segment): (usize, &mut Vec<u8>)| {
+            let counter = start_counter + i as u32; // cannot overflow, checked above
+            let tag = self._wrap_in_place(segment, counter);
+
+            segment.extend_from_slice(&counter.to_be_bytes());
+            segment.extend_from_slice(&tag);
+        };
+
+        #[cfg(feature = "rayon")]
+        segments.par_iter_mut().enumerate().for_each(wrap_one);
+
+        #[cfg(not(feature = "rayon"))]
+        segments.iter_mut().enumerate().for_each(wrap_one);
+
+        Ok(end)
+    }
+
+    /// Wraps the plaintext in `buf[..plain_len]` in place, without allocating.
+    ///
+    /// The ciphertext, counter and tag are written to `buf[..plain_len + COUNTER_TAG_LEN]`, so
+    /// `buf` must be large enough to hold them. Returns the length that was written.
+    pub fn wrap_slice(&mut self, buf: &mut [u8], plain_len: usize) -> Result<usize, Error> {
+        let total = plain_len
+            .checked_add(COUNTER_TAG_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        if buf.len() < total {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let (plain, rest) = buf.split_at_mut(plain_len);
+        let (counter, tag) = self.wrap_detached(plain)?;
+
+        rest[..COUNTER_LEN].copy_from_slice(&counter.to_be_bytes());
+        rest[COUNTER_LEN..COUNTER_TAG_LEN].copy_from_slice(&tag);
+
+        Ok(total)
+    }
+
+    /// Wraps `plain` into `out`, leaving the plaintext untouched, e.g., when it is read-only.
+    ///
+    /// The ciphertext, counter and tag are written to `out[..plain.len() + COUNTER_TAG_LEN]`,
+    /// exactly as [`Deck::wrap`] would produce them, so `out` must be large enough to hold them.
+    pub fn wrap_to(&mut self, plain: &[u8], out: &mut [u8]) -> Result<(), Error> {
+        let total = plain.len()
+            .checked_add(COUNTER_TAG_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        if out.len() < total {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let (cipher, rest) = out[..total].split_at_mut(plain.len());
+        cipher.copy_from_slice(plain);
+        let (counter, tag) = self.wrap_detached(cipher)?;
+
+        rest[..COUNTER_LEN].copy_from_slice(&counter.to_be_bytes());
+        rest[COUNTER_LEN..].copy_from_slice(&tag);
+
+        Ok(())
+    }
+
+    /// Unwraps the ciphertext, counter and tag in `buf[..cipher_len]` in place.
+    ///
+    /// On success, the plaintext occupies the start of `buf`. Returns the length of the
+    /// plaintext. Like [`Deck::wrap_slice`], this does not allocate.
+    pub fn unwrap_slice(&mut self, buf: &mut [u8], cipher_len: usize) -> Result<usize, Error> {
+        if buf.len() < cipher_len {
+            return Err(Error::BufferTooSmall);
+        }
+        if cipher_len < COUNTER_TAG_LEN {
+            return Err(Error::WrongTag);
+        }
+
+        let ct_len = cipher_len - COUNTER_TAG_LEN;
+        let (ct, counter_tag) = buf[..cipher_len].split_at_mut(ct_len);
+        let (counter, tag) = counter_tag.split_at(COUNTER_LEN);
+        let counter = u32::from_be_bytes(counter.try_into().unwrap()); // cannot panic
+
+        self._unwrap_in_place(ct, counter, tag)?;
+        self._rekey_after(counter, tag)?;
+
+        Ok(ct_len)
+    }
+
+    #[inline(always)]
+    fn _unwrap(&mut self, cipher: &mut Vec<u8>) -> Result<(), Error> {
+        let ct_len = self.unwrap_slice(cipher, cipher.len())?;
+
+        cipher.truncate(ct_len);
         Ok(())
     }
 
@@ -138,4 +559,55 @@ impl Deck {
     pub fn unwrap_last(&mut self, ct: &mut Vec<u8>) -> Result<(), Error> {
         self._unwrap(ct)
     }
+
+    /// Verifies a tag that was stored out-of-band and decrypts `cipher` in place on success.
+    ///
+    /// This is the inverse of [`Deck::wrap_detached`]. The `counter` must be the one that
+    /// [`Deck::wrap_detached`] returned for this segment.
+    pub fn unwrap_detached(
+        &mut self,
+        cipher: &mut [u8],
+        counter: u32,
+        tag: &[u8; TAG_LEN],
+    ) -> Result<(), Error> {
+        self._unwrap_in_place(cipher, counter, tag)?;
+        self._rekey_after(counter, tag)
+    }
+
+    /// Verifies the counter and tag of an authenticate-only segment over `data`.
+    ///
+    /// This is the inverse of [`Deck::wrap_aad_only`]. Returns the counter of the segment, which
+    /// the caller must compare to the position it expects, as [`DeckReader`] does for encrypted
+    /// segments.
+    pub fn unwrap_aad_only(
+        &mut self,
+        data: &[u8],
+        counter_tag: &[u8; COUNTER_TAG_LEN],
+    ) -> Result<u32, Error> {
+        let (counter, tag) = counter_tag.split_at(COUNTER_LEN);
+        let counter = u32::from_be_bytes(counter.try_into().unwrap()); // cannot panic
+
+        if tag != self._tag_aad_only(data, counter) {
+            return Err(Error::WrongTag);
+        }
+
+        self._rekey_after(counter, tag)?;
+
+        Ok(counter)
+    }
+
+    /// Follows the sender to the next session after the segment with the last counter, or the
+    /// one that ends an interval.
+    #[inline(always)]
+    fn _rekey_after(&mut self, counter: u32, tag: &[u8]) -> Result<(), Error> {
+        if self._rekey_at_interval(counter, tag) {
+            return Ok(());
+        }
+
+        if counter == LAST_COUNTER && self.rekey.is_some() {
+            self.rekey()?;
+        }
+
+        Ok(())
+    }
 }
@@ -1,73 +1,755 @@
 //extern crate serde;
-use crate::Deck;
+use crate::{Deck, Error, COUNTER_TAG_LEN, MIN_KEY_LEN, MIN_NONCE_LEN, TAG_LEN};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Lines};
 
-#[test]
-fn tests_deck() {
+/// A known answer test vector.
+struct Kat {
+    key: Vec<u8>,
+    nonce: Vec<u8>,
+    msg: Vec<u8>,
+    cipher: Vec<u8>,
+    tag: Vec<u8>,
+}
+
+/// Reads the next field of a test vector, which is the fifth word on its own line.
+fn read_field(reader: &mut Lines<BufReader<File>>) -> Option<Vec<u8>> {
+    let line = reader.next()?.unwrap();
+    Some(hex::decode(line.split(" ").collect::<Vec<_>>()[4]).unwrap())
+}
+
+fn read_kats() -> Vec<Kat> {
     let test_file = "./kats/test_vector_bytes.txt";
     let file = File::open(test_file).unwrap();
     let mut reader = BufReader::new(file).lines();
 
-    while let Some(line) = reader.next() {
-        // key to be used for instantiating deck function
-        let key = line.unwrap();
-        let key = key.split(" ").collect::<Vec<_>>()[4];
-        let key = hex::decode(key).unwrap();
-
-        // nonce to be used for instantiating deck function
-        let nonce = reader.next().unwrap().unwrap();
-        let nonce = nonce.split(" ").collect::<Vec<_>>()[4];
-        let nonce = hex::decode(nonce).unwrap();
-
-        // message to be absorbed into deck function
-        let msg = reader.next().unwrap().unwrap();
-        let msg = msg.split(" ").collect::<Vec<_>>()[4];
-        let mut buf = hex::decode(msg).unwrap();
-        let msg = buf.clone();
-
-        // # expected cipher text to be compared to
-        let exp_cipher = reader.next().unwrap().unwrap();
-        let exp_cipher = exp_cipher.split(" ").collect::<Vec<_>>()[4];
-        let exp_cipher = hex::decode(exp_cipher).unwrap();
-
-        // # expected tag to be compared to
-        let exp_tag = reader.next().unwrap().unwrap();
-        let exp_tag = exp_tag.split(" ").collect::<Vec<_>>()[4];
-        let exp_tag = hex::decode(exp_tag).unwrap();
-
-        let mut deck = Deck::new(&key, &nonce);
-        deck.wrap(&mut buf).unwrap();
-
-        let ct = &buf[..&buf.len()-36];
-        let tag = &buf[buf.len()-32..];
-
-        if ct.len() > 36 {
-            assert_eq!(&exp_cipher, ct);
+    let mut kats = Vec::new();
+    while let Some(key) = read_field(&mut reader) {
+        kats.push(Kat {
+            key,
+            nonce: read_field(&mut reader).unwrap(),
+            msg: read_field(&mut reader).unwrap(),
+            cipher: read_field(&mut reader).unwrap(),
+            tag: read_field(&mut reader).unwrap(),
+        });
+    }
+
+    kats
+}
+
+fn check_kat(kat: &Kat) {
+    let len = kat.msg.len();
+
+    for last in [false, true] {
+        let mut buf = kat.msg.clone();
+        let mut deck = Deck::init(&kat.key, &kat.nonce);
+        if last {
+            deck.wrap_last(&mut buf).unwrap();
+        } else {
+            deck.wrap(&mut buf).unwrap();
+        }
+
+        assert_eq!(buf.len(), len + COUNTER_TAG_LEN);
+        assert_eq!(&kat.cipher, &buf[..len]);
+        assert_eq!(&buf[len..len + 4], &0u32.to_be_bytes());
+        assert_eq!(&kat.tag, &buf[len + 4..]);
+
+        let mut deck = Deck::init(&kat.key, &kat.nonce);
+        if last {
+            deck.unwrap_last(&mut buf).unwrap();
+        } else {
+            deck.unwrap(&mut buf).unwrap();
         }
-        assert_eq!(&tag, &exp_tag);
-        
-        let mut deck = Deck::new(&key, &nonce);
-        deck.unwrap(&mut buf).unwrap();
-        if buf.len() > 0 {
-            assert_eq!(&msg, &buf);
+        assert_eq!(&kat.msg, &buf);
+    }
+}
+
+#[test]
+fn tests_deck() {
+    for kat in read_kats() {
+        check_kat(&kat);
+    }
+}
+
+#[cfg(feature = "self-test")]
+#[test]
+fn tests_deck_self_test() {
+    Deck::self_test().unwrap();
+
+    // The embedded vector is one of the known answer tests.
+    let kat = read_kats()
+        .into_iter()
+        .find(|kat| {
+            kat.key == crate::SELF_TEST_KEY
+                && kat.nonce == crate::SELF_TEST_NONCE
+                && kat.msg == crate::SELF_TEST_MSG
+        })
+        .unwrap();
+    assert_eq!(&kat.cipher, &crate::SELF_TEST_CIPHER);
+    assert_eq!(&kat.tag, &crate::SELF_TEST_TAG);
+}
+
+#[test]
+fn tests_deck_short() {
+    // Both sides of the `COUNTER_TAG_LEN` boundary, and the empty plaintext that only tags the
+    // counter.
+    let kats = read_kats();
+
+    for len in [0, 1, 31, 32, 33, 35, 36, 37] {
+        let kat = kats.iter().find(|kat| kat.msg.len() == len).unwrap();
+        check_kat(kat);
+
+        // Any truncation must be rejected, also below `COUNTER_TAG_LEN`.
+        let mut buf = kat.msg.clone();
+        Deck::init(&kat.key, &kat.nonce).wrap(&mut buf).unwrap();
+        for cut in 1..=buf.len() {
+            let mut truncated = buf[..buf.len() - cut].to_vec();
+            assert!(Deck::init(&kat.key, &kat.nonce).unwrap(&mut truncated).is_err());
         }
+    }
+}
+
+#[test]
+fn tests_deck_slice() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    for len in [0, 1, 35, 36, 37, 1024] {
+        let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+        let mut attached = msg.clone();
+        Deck::new(&key, &nonce).unwrap().wrap(&mut attached).unwrap();
+
+        // A buffer with spare room at the end, as in a ring buffer.
+        let mut buf = vec![0xffu8; len + COUNTER_TAG_LEN + 10];
+        buf[..len].copy_from_slice(&msg);
+
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        let written = deck.wrap_slice(&mut buf, len).unwrap();
+        assert_eq!(written, len + COUNTER_TAG_LEN);
+        assert_eq!(&attached[..], &buf[..written]);
+        assert_eq!(&buf[written..], &[0xffu8; 10]);
+
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        let plain_len = deck.unwrap_slice(&mut buf, written).unwrap();
+        assert_eq!(plain_len, len);
+        assert_eq!(&msg[..], &buf[..plain_len]);
+
+        // The buffer must fit the counter and tag.
+        let mut small = vec![0u8; len + COUNTER_TAG_LEN - 1];
+        assert!(matches!(
+            Deck::new(&key, &nonce).unwrap().wrap_slice(&mut small, len),
+            Err(Error::BufferTooSmall)
+        ));
+    }
+}
+
+#[test]
+fn tests_deck_keystream_chunks() {
+    let deck = Deck::new(&[0x42u8; 32], &[0x24u8; 32]).unwrap();
+
+    // Squeezing the keystream in chunks yields the same keystream as squeezing it at once.
+    for len in [1, 63, 64, 65, 200] {
+        let mut chunked = deck.xoofff.clone();
+        chunked.absorb(&0u32.to_be_bytes());
+        chunked.finalize(0b0, 1, 0);
+
+        let mut expected = vec![0u8; len];
+        chunked.clone().squeeze(&mut expected);
+
+        let mut buf = vec![0u8; len];
+        deck._xor_keystream(&mut chunked, &mut buf);
+        assert_eq!(buf, expected);
+    }
+}
+
+#[test]
+fn tests_deck_wrap_to() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    for len in [0, 1, 35, 36, 37, 1024] {
+        let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+        let mut attached = msg.clone();
+        Deck::new(&key, &nonce).unwrap().wrap(&mut attached).unwrap();
+
+        let plain = msg.clone();
+        let mut out = vec![0xffu8; len + COUNTER_TAG_LEN + 10];
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        deck.wrap_to(&plain, &mut out).unwrap();
+
+        // The plaintext is left untouched, and so is the rest of the output.
+        assert_eq!(&plain, &msg);
+        assert_eq!(&attached[..], &out[..attached.len()]);
+        assert_eq!(&out[attached.len()..], &[0xffu8; 10]);
+
+        // Consecutive segments use consecutive counters.
+        let mut second = vec![0u8; len + COUNTER_TAG_LEN];
+        deck.wrap_to(&plain, &mut second).unwrap();
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        deck.unwrap(&mut attached).unwrap();
+        deck.unwrap(&mut second).unwrap();
+        assert_eq!(&second, &msg);
+
+        // The output must fit the counter and tag.
+        let mut small = vec![0u8; len + COUNTER_TAG_LEN - 1];
+        assert!(matches!(
+            Deck::new(&key, &nonce).unwrap().wrap_to(&plain, &mut small),
+            Err(Error::BufferTooSmall)
+        ));
+    }
+}
+
+#[cfg(feature = "aead")]
+#[test]
+fn tests_deck_aead() {
+    use crate::DeckAead;
+    use aead::{Aead, AeadInPlace, KeyInit, Payload};
+
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+    let msg = b"a single message".to_vec();
+
+    // Without associated data, the adapter wraps exactly like wrap_detached.
+    let mut detached = msg.clone();
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    let (counter, tag) = deck.wrap_detached(&mut detached).unwrap();
+
+    let mut buf = msg.clone();
+    let cipher = DeckAead::from(Deck::new(&key, &nonce).unwrap());
+    let aead_tag = cipher.encrypt_in_place_detached(&counter.to_be_bytes().into(), b"", &mut buf).unwrap();
+    assert_eq!(buf, detached);
+    assert_eq!(&aead_tag[..], &tag[..]);
+
+    cipher.decrypt_in_place_detached(&counter.to_be_bytes().into(), b"", &mut buf, &aead_tag).unwrap();
+    assert_eq!(buf, msg);
+
+    // A round trip through the standard interface, with associated data.
+    let cipher = DeckAead::new(&key.into());
+    let nonce = 7u32.to_be_bytes().into();
+    let ct = cipher.encrypt(&nonce, Payload { msg: &msg, aad: b"header" }).unwrap();
+    assert_eq!(ct.len(), msg.len() + TAG_LEN);
+    assert_eq!(cipher.decrypt(&nonce, Payload { msg: &ct, aad: b"header" }).unwrap(), msg);
+
+    // The associated data and the counter are authenticated.
+    assert!(cipher.decrypt(&nonce, Payload { msg: &ct, aad: b"other" }).is_err());
+    assert!(cipher.decrypt(&8u32.to_be_bytes().into(), &ct[..]).is_err());
+}
+
+#[test]
+fn tests_deck_detached() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    for len in [0, 1, 35, 36, 37, 1024] {
+        let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+        // The detached ciphertext and tag must equal the attached ones.
+        let mut attached = msg.clone();
+        Deck::new(&key, &nonce).unwrap().wrap(&mut attached).unwrap();
+
+        let mut detached = msg.clone();
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        let (counter, tag) = deck.wrap_detached(&mut detached).unwrap();
+
+        assert_eq!(counter, 0);
+        assert_eq!(&attached[..len], &detached[..]);
+        assert_eq!(&attached[attached.len() - TAG_LEN..], &tag[..]);
+
+        let mut deck = Deck::new(&key, &nonce).unwrap();
+        let mut wrong = detached.clone();
+        let mut wrong_tag = tag;
+        wrong_tag[0] ^= 1;
+        assert!(deck.unwrap_detached(&mut wrong, counter, &wrong_tag).is_err());
+        assert!(deck.unwrap_detached(&mut wrong, counter + 1, &tag).is_err());
+
+        deck.unwrap_detached(&mut detached, counter, &tag).unwrap();
+        assert_eq!(&msg, &detached);
+    }
+}
+
+#[test]
+fn tests_deck_detached_counter() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    let mut segments = vec![vec![1u8; 100], vec![2u8; 100], vec![3u8; 100]];
+    let tags: Vec<_> = segments
+        .iter_mut()
+        .map(|s| deck.wrap_detached(s).unwrap())
+        .collect();
+
+    // Segments can be unwrapped in any order, given their counter.
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    for (i, (s, (counter, tag))) in segments.iter_mut().zip(tags.iter()).enumerate().rev() {
+        assert_eq!(*counter, i as u32);
+        deck.unwrap_detached(s, *counter, tag).unwrap();
+        assert_eq!(s, &vec![i as u8 + 1; 100]);
+    }
+}
+
+#[test]
+fn tests_deck_counter_frames() {
+    // Several segments wrapped in sequence, including empty ones that only tag the counter.
+    let lens = [0, 1, 35, 36, 37, 100, 0];
+
+    for kat in read_kats().iter().filter(|kat| !kat.msg.is_empty()).take(4) {
+        let mut deck = Deck::init(&kat.key, &kat.nonce);
+        let mut frames = Vec::new();
+        for (i, len) in lens.iter().enumerate() {
+            let mut buf: Vec<u8> = kat.msg.iter().cycle().take(*len).copied().collect();
+            if i == lens.len() - 1 {
+                deck.wrap_last(&mut buf).unwrap();
+            } else {
+                deck.wrap(&mut buf).unwrap();
+            }
+
+            // Every frame is `ct || counter || tag`, with the counter of that segment.
+            assert_eq!(buf.len(), len + COUNTER_TAG_LEN);
+            assert_eq!(&buf[buf.len() - 36..buf.len() - 32], &(i as u32).to_be_bytes());
+            assert_eq!(deck.counter(), i as u32 + 1);
+            frames.push(buf);
+        }
+
+        // The first frame of a non-empty message is the known answer.
+        let mut first = kat.msg.clone();
+        Deck::init(&kat.key, &kat.nonce).wrap(&mut first).unwrap();
+        assert_eq!(&first[..kat.msg.len()], &kat.cipher);
+        assert_eq!(&first[kat.msg.len() + 4..], &kat.tag);
+
+        let deck = Deck::init(&kat.key, &kat.nonce);
+        for (i, frame) in frames.iter().enumerate() {
+            let len = lens[i];
+            let expected: Vec<u8> = kat.msg.iter().cycle().take(len).copied().collect();
+
+            let mut buf = frame.clone();
+            if i == lens.len() - 1 {
+                deck.clone().unwrap_last(&mut buf).unwrap();
+            } else {
+                deck.clone().unwrap(&mut buf).unwrap();
+            }
+            assert_eq!(buf, expected);
+
+            // The counter is authenticated, also when the segment is empty.
+            let mut modified = frame.clone();
+            let at = modified.len() - 33;
+            modified[at] ^= 1;
+            assert!(matches!(
+                deck.clone().unwrap(&mut modified),
+                Err(Error::WrongTag)
+            ));
+
+            // So is the ciphertext preceding it.
+            if len > 0 {
+                let mut modified = frame.clone();
+                modified[0] ^= 1;
+                assert!(matches!(
+                    deck.clone().unwrap(&mut modified),
+                    Err(Error::WrongTag)
+                ));
+            }
+        }
+
+        // Frames of different segments with equal plaintexts differ in their counter and tag.
+        assert_ne!(frames[0], frames[lens.len() - 1]);
+    }
+}
+
+#[test]
+fn tests_deck_wrap_many() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let segments: Vec<Vec<u8>> = (0..64u8).map(|i| vec![i; 1000 + i as usize]).collect();
+
+    let mut sequential = segments.clone();
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    for segment in sequential.iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
+
+    let mut parallel = segments.clone();
+    let deck = Deck::new(&key, &nonce).unwrap();
+    let end = deck.wrap_many(&mut parallel, 0).unwrap();
+
+    assert_eq!(end, segments.len() as u32);
+    assert_eq!(sequential, parallel);
+
+    // Continuing from a counter equals wrapping the remainder sequentially.
+    let mut tail = segments[32..].to_vec();
+    deck.wrap_many(&mut tail, 32).unwrap();
+    assert_eq!(&sequential[32..], &tail[..]);
+
+    assert!(deck.wrap_many(&mut parallel, u32::MAX).is_err());
+}
+
+#[test]
+fn tests_deck_clone() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    deck.wrap(&mut vec![1u8; 100]).unwrap();
+    deck.wrap(&mut vec![2u8; 100]).unwrap();
+
+    // Snapshot at a segment boundary.
+    let mut snapshot = deck.clone();
+
+    let mut a = vec![3u8; 100];
+    let mut b = a.clone();
+    deck.wrap(&mut a).unwrap();
+    snapshot.wrap(&mut b).unwrap();
+
+    assert_eq!(a, b);
+    assert_eq!(&a[100..104], &2u32.to_be_bytes());
+}
+
+#[test]
+fn tests_deck_resume() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let mut segments: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 100]).collect();
+
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    for segment in segments[..5].iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
 
-        let mut deck = Deck::new(&key, &nonce);
-        deck.wrap_last(&mut buf).unwrap();
+    // "Restart" the process, only keeping the counter.
+    let counter = deck.counter();
+    drop(deck);
+    assert_eq!(counter, 5);
 
-        let ct = &buf[..&buf.len()-36];
-        let tag = &buf[buf.len()-32..];
+    let mut deck = Deck::resume(&key, &nonce, counter).unwrap();
+    for segment in segments[5..].iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
+    assert_eq!(deck.counter(), 10);
+
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    for (i, segment) in segments.iter_mut().enumerate() {
+        deck.unwrap(segment).unwrap();
+        assert_eq!(segment, &vec![i as u8; 100]);
+    }
+}
+
+#[test]
+fn tests_deck_ad() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let plain = vec![7u8; 100];
+    let mut ct = plain.clone();
+    Deck::new(&key, &nonce)
+        .unwrap()
+        .with_ad(b"header 1")
+        .wrap(&mut ct)
+        .unwrap();
+
+    let mut unbound = plain.clone();
+    Deck::new(&key, &nonce).unwrap().wrap(&mut unbound).unwrap();
+    assert_ne!(ct, unbound);
+
+    let open = |mut deck: Deck| {
+        let mut buf = ct.clone();
+        deck.unwrap(&mut buf).map(|_| buf)
+    };
+
+    let deck = Deck::new(&key, &nonce).unwrap();
+    assert_eq!(open(deck.clone().with_ad(b"header 1")).unwrap(), plain);
+    assert!(matches!(
+        open(deck.clone().with_ad(b"header 2")),
+        Err(Error::WrongTag)
+    ));
+    assert!(matches!(open(deck.clone().with_ad(b"")), Err(Error::WrongTag)));
+    assert!(matches!(open(deck), Err(Error::WrongTag)));
+
+    // Binding commutes with resuming.
+    let mut ct = plain.clone();
+    Deck::resume(&key, &nonce, 3)
+        .unwrap()
+        .with_ad(b"header 1")
+        .wrap(&mut ct)
+        .unwrap();
+    assert_eq!(&ct[100..104], &3u32.to_be_bytes());
+    Deck::new(&key, &nonce)
+        .unwrap()
+        .with_ad(b"header 1")
+        .unwrap(&mut ct)
+        .unwrap();
+    assert_eq!(ct, plain);
+}
 
-        if ct.len() > 36 {
-            assert_eq!(&exp_cipher, ct);
+#[test]
+fn tests_deck_overflow() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    let plain = vec![7u8; 100];
+
+    let mut deck = Deck::resume(&key, &nonce, u32::MAX).unwrap();
+    let mut buf = plain.clone();
+    assert!(matches!(deck.wrap(&mut buf), Err(Error::Overflow)));
+    assert!(matches!(
+        deck.clone().wrap_last(&mut buf),
+        Err(Error::Overflow)
+    ));
+    assert!(matches!(deck.wrap_detached(&mut buf), Err(Error::Overflow)));
+
+    // The plaintext is left untouched.
+    assert_eq!(buf, plain);
+
+    // The counter before the last one can still be used.
+    let mut deck = Deck::resume(&key, &nonce, u32::MAX - 1).unwrap();
+    deck.wrap(&mut buf).unwrap();
+    assert_eq!(&buf[100..104], &(u32::MAX - 1).to_be_bytes());
+    assert!(matches!(deck.wrap(&mut plain.clone()), Err(Error::Overflow)));
+}
+
+#[test]
+fn tests_deck_rekey() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    // Both sides derive the same fresh nonce.
+    let rekey = move || Deck::new(&key, &[0x25u8; 32]).unwrap();
+
+    let segments: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 100]).collect();
+    let mut wrapped = segments.clone();
+
+    let mut deck = Deck::resume(&key, &nonce, u32::MAX - 1)
+        .unwrap()
+        .with_rekey_on_overflow(rekey);
+    for segment in wrapped.iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
+    assert_eq!(deck.counter(), 2);
+
+    // The new session starts over at counter 0.
+    assert_eq!(&wrapped[0][100..104], &(u32::MAX - 1).to_be_bytes());
+    assert_eq!(&wrapped[1][100..104], &0u32.to_be_bytes());
+    assert_eq!(&wrapped[2][100..104], &1u32.to_be_bytes());
+
+    let mut deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_on_overflow(rekey);
+    for (segment, plain) in wrapped.clone().iter_mut().zip(segments.iter()) {
+        deck.unwrap(segment).unwrap();
+        assert_eq!(segment, plain);
+    }
+
+    // Without the hook, the receiver cannot follow.
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    deck.unwrap(&mut wrapped[0]).unwrap();
+    assert!(matches!(deck.unwrap(&mut wrapped[1]), Err(Error::WrongTag)));
+}
+
+#[test]
+fn tests_deck_rekey_interval() {
+    use crate::{DeckReader, DeckWriter};
+    use std::io::{Read, Write};
+    use std::num::NonZeroU32;
+
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+    let interval = NonZeroU32::new(3).unwrap();
+
+    let segments: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 100]).collect();
+    let mut wrapped = segments.clone();
+
+    let mut deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_interval(interval);
+    for segment in wrapped.iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
+    assert_eq!(deck.epoch(), 3);
+    assert_eq!(deck.counter(), 1);
+
+    // The counter restarts in every epoch.
+    for (i, segment) in wrapped.iter().enumerate() {
+        assert_eq!(&segment[100..104], &(i as u32 % 3).to_be_bytes());
+    }
+
+    // Every epoch uses a different key, so equal plaintexts under equal counters differ.
+    let mut same = vec![vec![0u8; 100]; 4];
+    let mut deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_interval(interval);
+    for segment in same.iter_mut() {
+        deck.wrap(segment).unwrap();
+    }
+    assert_eq!(&same[0][100..104], &same[3][100..104]);
+    assert_ne!(same[0], same[3]);
+
+    let mut deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_interval(interval);
+    for (segment, plain) in wrapped.clone().iter_mut().zip(segments.iter()) {
+        deck.unwrap(segment).unwrap();
+        assert_eq!(segment, plain);
+    }
+    assert_eq!(deck.epoch(), 3);
+
+    // Without the interval, the receiver cannot follow.
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    for segment in wrapped[..3].iter_mut() {
+        deck.unwrap(&mut segment.clone()).unwrap();
+    }
+    assert!(matches!(deck.unwrap(&mut wrapped[3]), Err(Error::WrongTag)));
+
+    // Streams follow the epochs as well.
+    let msg: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_interval(interval);
+    let mut writer = DeckWriter::new(Vec::new(), deck, 64);
+    writer.write_all(&msg).unwrap();
+    let sealed = writer.close().unwrap();
+
+    let deck = Deck::new(&key, &nonce)
+        .unwrap()
+        .with_rekey_interval(interval);
+    let mut out = Vec::new();
+    DeckReader::new(&sealed[..], deck, 64)
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, msg);
+}
+
+#[test]
+fn tests_deck_io() {
+    use crate::{DeckReader, DeckWriter};
+    use std::io::{Read, Write};
+
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+    let segment_size = 64;
+
+    let seal = |msg: &[u8]| {
+        let deck = Deck::new(&key, &nonce).unwrap();
+        let mut writer = DeckWriter::new(Vec::new(), deck, segment_size);
+        // Write in chunks that do not align with the segments.
+        for chunk in msg.chunks(50) {
+            writer.write_all(chunk).unwrap();
         }
-        assert_eq!(&tag, &exp_tag);
-        
-        let mut deck = Deck::new(&key, &nonce);
-        deck.unwrap_last(&mut buf).unwrap();
-        if buf.len() > 0 {
-            assert_eq!(&msg, &buf);
+        writer.close().unwrap()
+    };
+
+    let open = |sealed: &[u8]| {
+        let deck = Deck::new(&key, &nonce).unwrap();
+        let mut reader = DeckReader::new(sealed, deck, segment_size);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).map(|_| out)
+    };
+
+    for len in [0, 1, 63, 64, 65, 128, 1000] {
+        let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let sealed = seal(&msg);
+
+        // Every full segment is followed by a shorter final one.
+        let full = len / segment_size;
+        assert_eq!(sealed.len(), len + (full + 1) * COUNTER_TAG_LEN);
+        assert_eq!(open(&sealed).unwrap(), msg);
+
+        // Cutting off the stream, also at a segment boundary, is detected.
+        for cut in [1, COUNTER_TAG_LEN, len % segment_size + COUNTER_TAG_LEN] {
+            if cut <= sealed.len() {
+                assert!(open(&sealed[..sealed.len() - cut]).is_err());
+            }
         }
+
+        let mut modified = sealed.clone();
+        modified[0] ^= 1;
+        assert!(open(&modified).is_err());
     }
+
+    // Swapping two segments is detected.
+    let msg = vec![0u8; 3 * segment_size];
+    let sealed = seal(&msg);
+    let seg = segment_size + COUNTER_TAG_LEN;
+    let mut swapped = sealed.clone();
+    swapped[..seg].copy_from_slice(&sealed[seg..2 * seg]);
+    swapped[seg..2 * seg].copy_from_slice(&sealed[..seg]);
+    assert!(open(&swapped).is_err());
+}
+
+#[test]
+fn tests_deck_lengths() {
+    let key = [0x42u8; MIN_KEY_LEN];
+    let nonce = [0x24u8; MIN_NONCE_LEN];
+
+    assert!(Deck::new(&key, &nonce).is_ok());
+    assert!(matches!(Deck::new(&key, &[]), Err(Error::InvalidNonceLength)));
+    assert!(matches!(
+        Deck::new(&key, &nonce[1..]),
+        Err(Error::InvalidNonceLength)
+    ));
+    assert!(matches!(Deck::new(&key[1..], &nonce), Err(Error::InvalidKeyLength)));
+    assert!(matches!(
+        Deck::resume(&key, &[], 1),
+        Err(Error::InvalidNonceLength)
+    ));
+}
+
+#[test]
+fn tests_deck_aad_only() {
+    let key = [0x42u8; 32];
+    let nonce = [0x24u8; 32];
+
+    // Encrypted segments, interleaved with control segments that are only authenticated.
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    let mut first = vec![1u8; 100];
+    deck.wrap(&mut first).unwrap();
+    let control = b"length: 100".to_vec();
+    let control_tag = deck.wrap_aad_only(&control).unwrap();
+    let mut last = vec![2u8; 50];
+    deck.wrap_last(&mut last).unwrap();
+
+    assert_eq!(&control_tag[..4], &1u32.to_be_bytes());
+
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    let mut buf = first.clone();
+    deck.unwrap(&mut buf).unwrap();
+    assert_eq!(buf, vec![1u8; 100]);
+    assert_eq!(deck.unwrap_aad_only(&control, &control_tag).unwrap(), 1);
+    let mut buf = last.clone();
+    deck.unwrap_last(&mut buf).unwrap();
+    assert_eq!(buf, vec![2u8; 50]);
+
+    // The control data is authenticated.
+    assert!(matches!(
+        deck.unwrap_aad_only(b"length: 999", &control_tag),
+        Err(Error::WrongTag)
+    ));
+
+    // So is its position, it cannot be moved to another counter.
+    let mut moved = control_tag;
+    moved[..4].copy_from_slice(&0u32.to_be_bytes());
+    assert!(matches!(
+        deck.unwrap_aad_only(&control, &moved),
+        Err(Error::WrongTag)
+    ));
+
+    // An authenticate-only segment cannot be unwrapped as an encrypted one, nor vice versa.
+    let mut as_encrypted = control.clone();
+    as_encrypted.extend_from_slice(&control_tag);
+    assert!(matches!(deck.unwrap(&mut as_encrypted), Err(Error::WrongTag)));
+
+    let mut empty = Vec::new();
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    deck.wrap(&mut empty).unwrap();
+    let empty_tag: [u8; COUNTER_TAG_LEN] = empty[..].try_into().unwrap();
+    assert!(matches!(
+        Deck::new(&key, &nonce).unwrap().unwrap_aad_only(&[], &empty_tag),
+        Err(Error::WrongTag)
+    ));
+
+    // The counter advances as for an encrypted segment.
+    let mut deck = Deck::new(&key, &nonce).unwrap();
+    deck.wrap_aad_only(&[]).unwrap();
+    assert_eq!(deck.counter(), 1);
 }